@@ -0,0 +1,69 @@
+// Timings of the parse and render paths: FEN and PGN parsing, rasterizing one board and
+// putting a full page of nine diagrams together. Run with `cargo bench`
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use fen2pdf::{BoardCache, ChessPosition};
+use std::hint::black_box;
+
+const MIDDLEGAME: &str = "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 9";
+
+// Nine chapters with some moves each, a page's worth of diagrams
+fn sample_study() -> String {
+    let fens = [
+        "6rk/6pp/8/6N1/8/8/8/1Q4K1 w - - 0 1",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 3 3",
+        MIDDLEGAME,
+        "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1",
+        "1K6/1P1k4/8/8/8/8/r7/2R5 w - - 0 1",
+        "r3k2r/ppp2ppp/2n1bn2/2bpp3/4P3/2NP1N2/PPP1BPPP/R1BQK2R w KQkq - 0 7",
+        "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+        "2r3k1/5ppp/8/8/8/8/5PPP/1R4K1 b - - 0 1",
+    ];
+    let mut pgn = String::new();
+    for (i, fen) in fens.iter().enumerate() {
+        pgn.push_str(&format!(
+            "[Event \"Bench: Chapter {}\"]\n[StudyName \"Bench\"]\n[ChapterName \"Chapter {}\"]\n[FEN \"{}\"]\n[SetUp \"1\"]\n\n*\n\n",
+            i + 1,
+            i + 1,
+            fen
+        ));
+    }
+    pgn
+}
+
+fn parse(c: &mut Criterion) {
+    c.bench_function("parse_fen", |b| b.iter(|| fen2pdf::parse_fen(black_box(MIDDLEGAME.split(' ').next().unwrap()))));
+
+    let options = fen2pdf::parse_args(&["fen2pdf".to_string(), "bench".to_string()]).unwrap();
+    let path = std::env::temp_dir().join(format!("fen2pdf_bench_{}.pgn", std::process::id()));
+    std::fs::write(&path, sample_study()).unwrap();
+    let path = path.to_string_lossy().into_owned();
+    c.bench_function("read_lichess_study", |b| b.iter(|| fen2pdf::read_lichess_study(black_box(&path), &options).unwrap()));
+    let _ = std::fs::remove_file(&path);
+}
+
+fn render(c: &mut Criterion) {
+    let options = fen2pdf::parse_args(&["fen2pdf".to_string(), "bench".to_string()]).unwrap();
+    let position = ChessPosition::new(1, "Middlegame".to_string(), MIDDLEGAME.to_string());
+    c.bench_function("generate_board_rgb_data", |b| b.iter(|| fen2pdf::generate_board_rgb_data(black_box(&position), &options).unwrap()));
+
+    let path = std::env::temp_dir().join(format!("fen2pdf_bench_{}.pgn", std::process::id()));
+    std::fs::write(&path, sample_study()).unwrap();
+    let study = fen2pdf::read_lichess_study(&path.to_string_lossy(), &options).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let pdf = std::env::temp_dir().join(format!("fen2pdf_bench_{}.pdf", std::process::id()));
+    let pdf = pdf.to_string_lossy().into_owned();
+    let mut group = c.benchmark_group("create_pdf");
+    // A page takes long enough that fewer samples do
+    group.sample_size(10);
+    group.bench_function("nine_boards", |b| {
+        // A new cache each time, so that every board is rendered
+        b.iter(|| fen2pdf::create_pdf(&study, &pdf, &options, &BoardCache::new(None).unwrap()).unwrap())
+    });
+    group.finish();
+    let _ = std::fs::remove_file(&pdf);
+}
+
+criterion_group!(benches, parse, render);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+#ifndef FEN2PDF_H
+#define FEN2PDF_H
+
+/* Generated with cbindgen:0.27.0 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define FEN2PDF_OK 0
+
+/**
+ * An argument is NULL or not UTF-8, or the options are invalid
+ */
+#define FEN2PDF_ERROR_ARGUMENT 1
+
+/**
+ * The study could not be downloaded
+ */
+#define FEN2PDF_ERROR_FETCH 2
+
+/**
+ * The PGN holds no positions
+ */
+#define FEN2PDF_ERROR_PARSE 3
+
+/**
+ * The PDF could not be made
+ */
+#define FEN2PDF_ERROR_RENDER 4
+
+/**
+ * The PDF could not be written to the output path
+ */
+#define FEN2PDF_ERROR_OUTPUT 5
+
+/**
+ * fen2pdf panicked
+ */
+#define FEN2PDF_ERROR_INTERNAL 6
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Downloads the Lichess study `study_id` (through the download cache) and writes its PDF to
+ * `output_path`. `options` holds fen2pdf's command line options separated by spaces, e.g.
+ * "--grid 2x3 --solutions appendix", and may be NULL
+ *
+ * # Safety
+ * Each argument is NULL or a NUL-terminated string
+ */
+int32_t fen2pdf_render_study_to_file(const char *study_id, const char *output_path, const char *options);
+
+/**
+ * Writes the PDF of `fens`, one FEN a line, to `output_path`; `options` as for
+ * fen2pdf_render_study_to_file
+ *
+ * # Safety
+ * Each argument is NULL or a NUL-terminated string
+ */
+int32_t fen2pdf_render_fens(const char *fens, const char *output_path, const char *options);
+
+/**
+ * The message of the last failed call on this thread, or NULL after a successful one. The
+ * string belongs to fen2pdf and stays valid until the next call
+ */
+const char *fen2pdf_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FEN2PDF_H */
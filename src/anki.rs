@@ -0,0 +1,217 @@
+// Anki deck (.apkg) export: a zip archive holding an Anki collection (SQLite, schema 11,
+// as written by genanki) and the media files the cards refer to
+
+use anyhow::Result;
+use serde_json::json;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Fixed so that repeated exports of a study update the same note type
+const MODEL_ID: i64 = 1_607_392_319;
+
+const SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null, tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+const CARD_CSS: &str = ".card { font-family: serif; font-size: 20px; text-align: center; }\nimg { max-width: 100%; }";
+
+// One note: a PNG board image above the front's HTML text, HTML text on the back
+pub struct Card {
+    pub image: Vec<u8>,
+    pub front: String,
+    pub back: String,
+}
+
+impl Card {
+    // Media files share one folder in Anki, so they are named after their content
+    fn image_name(&self) -> String {
+        format!("fen2pdf-{}.png", &sha1_smol::Sha1::from(&self.image).digest().to_string()[..16])
+    }
+}
+
+// The .apkg file: the collection, built in a temporary SQLite file, and the images zipped
+pub fn deck_bytes(deck_name: &str, cards: &[Card]) -> Result<Vec<u8>> {
+    let collection = std::env::temp_dir().join(format!("fen2pdf-{}.anki2", std::process::id()));
+    let _ = std::fs::remove_file(&collection);
+    let written = write_collection(&collection, deck_name, cards);
+    let bytes = written.and_then(|_| Ok(std::fs::read(&collection)?));
+    let _ = std::fs::remove_file(&collection);
+    let bytes = bytes?;
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("collection.anki2", options)?;
+    zip.write_all(&bytes)?;
+
+    // Media files are stored under their index; "media" maps them back to their names
+    let media: serde_json::Map<String, serde_json::Value> =
+        cards.iter().enumerate().map(|(i, card)| (i.to_string(), json!(card.image_name()))).collect();
+    zip.start_file("media", options)?;
+    zip.write_all(serde_json::Value::Object(media).to_string().as_bytes())?;
+    for (i, card) in cards.iter().enumerate() {
+        zip.start_file(i.to_string(), options)?;
+        zip.write_all(&card.image)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+fn write_collection(path: &std::path::Path, deck_name: &str, cards: &[Card]) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let (seconds, millis) = (now.as_secs() as i64, now.as_millis() as i64);
+    let deck_id = stable_id(deck_name);
+
+    let db = rusqlite::Connection::open(path)?;
+    db.execute_batch(SCHEMA)?;
+    db.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        rusqlite::params![
+            seconds,
+            millis,
+            collection_config().to_string(),
+            json!({ MODEL_ID.to_string(): model(deck_id, seconds) }).to_string(),
+            json!({ "1": deck(1, "Default", seconds), deck_id.to_string(): deck(deck_id, deck_name, seconds) }).to_string(),
+            json!({ "1": deck_options(seconds) }).to_string(),
+        ],
+    )?;
+
+    for (i, card) in cards.iter().enumerate() {
+        let note_id = millis + i as i64;
+        let fields = format!("<img src=\"{}\"><br>{}\x1f{}", card.image_name(), card.front, card.back);
+        let sort_field = strip_html(&card.front);
+        db.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')",
+            rusqlite::params![
+                note_id,
+                guid(&format!("{}\x1f{}\x1f{}", deck_name, card.image_name(), card.front)),
+                MODEL_ID,
+                seconds,
+                fields,
+                sort_field,
+                checksum(&sort_field),
+            ],
+        )?;
+        // New cards, shown in export order
+        db.execute(
+            "INSERT INTO cards VALUES (?1, ?2, ?3, 0, ?4, -1, 0, 0, ?5, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+            rusqlite::params![note_id, note_id, deck_id, seconds, i as i64 + 1],
+        )?;
+    }
+    Ok(())
+}
+
+fn collection_config() -> serde_json::Value {
+    json!({
+        "activeDecks": [1], "curDeck": 1, "newSpread": 0, "collapseTime": 1200, "timeLim": 0,
+        "estTimes": true, "dueCounts": true, "curModel": null, "nextPos": 1,
+        "sortType": "noteFld", "sortBackwards": false, "addToCur": true,
+    })
+}
+
+fn model(deck_id: i64, modified: i64) -> serde_json::Value {
+    let field = |name: &str, ord: u32| {
+        json!({ "name": name, "ord": ord, "font": "Liberation Sans", "media": [], "rtl": false, "size": 20, "sticky": false })
+    };
+    json!({
+        "id": MODEL_ID.to_string(),
+        "name": "fen2pdf exercise",
+        "type": 0,
+        "mod": modified,
+        "usn": -1,
+        "sortf": 0,
+        "did": deck_id,
+        "flds": [field("Front", 0), field("Back", 1)],
+        "tmpls": [{
+            "name": "Exercise", "ord": 0, "did": null, "bqfmt": "", "bafmt": "",
+            "qfmt": "{{Front}}",
+            "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}",
+        }],
+        "css": CARD_CSS,
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": [],
+    })
+}
+
+fn deck(id: i64, name: &str, modified: i64) -> serde_json::Value {
+    json!({
+        "id": id, "name": name, "desc": "", "mod": modified, "usn": -1, "conf": 1, "dyn": 0,
+        "collapsed": false, "extendNew": 10, "extendRev": 50,
+        "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0],
+    })
+}
+
+// Anki's default review settings
+fn deck_options(modified: i64) -> serde_json::Value {
+    json!({
+        "id": 1, "name": "Default", "mod": modified, "usn": 0, "maxTaken": 60, "autoplay": true,
+        "timer": 0, "replayq": true, "dyn": false,
+        "new": { "delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500, "order": 1, "perDay": 20, "bury": true, "separate": true },
+        "rev": { "perDay": 100, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1, "maxIvl": 36500, "minSpace": 1, "bury": true },
+        "lapse": { "delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0 },
+    })
+}
+
+// Deck IDs derived from the name, so a re-imported deck is merged rather than duplicated
+fn stable_id(name: &str) -> i64 {
+    let digest = sha1_smol::Sha1::from(name).digest().bytes();
+    let id = i64::from_be_bytes(digest[..8].try_into().unwrap_or_default());
+    (id & 0x0000_FFFF_FFFF_FFFF) | 1 << 40
+}
+
+fn guid(text: &str) -> String {
+    sha1_smol::Sha1::from(text).digest().to_string()[..16].to_string()
+}
+
+// Anki's duplicate check: the first 8 hex digits of the SHA-1 of the sort field
+fn checksum(text: &str) -> i64 {
+    i64::from_str_radix(&sha1_smol::Sha1::from(text).digest().to_string()[..8], 16).unwrap_or(0)
+}
+
+// Text of an HTML field, tags (such as line breaks) turned into spaces
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,906 @@
+// Command line: options, flags and config files parsed into Options
+
+use crate::{BoardRenderer, DOWNLOAD_RETRIES, DiskStorage, Export, ExportFormat, GamePhase, HTTP_TIMEOUT, HintMode, IMAGE_DPI, ImageCompression, JPEG_QUALITY, LICHESS_URL, LOGO_HEIGHT, LOGO_MAX_HEIGHT, LOGO_MAX_WIDTH, Logo, LogoPosition, Numbering, Options, PREVIEW_PORT, PageSize, PageTemplate, PdfOptions, PrintMode, REQUESTS_PER_MINUTE, RasterRenderer, STUDY_EXPORT, STUDY_ID_LENGTH, SolutionsMode, SortKey, Source, SplitKey, Storage, Theme, VectorRenderer, WATCH_INTERVAL, cmyk, difficulty, encrypt, engine, platform, schedule, shapes};
+use crate::selection::{creation_time, random_seed};
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub fn print_usage(program: &str) {
+    eprintln!("Usage: {} [options] <study-id | file.pgn>...", program);
+    eprintln!("       {} --games <file.pgn> --engine <path> [options]", program);
+    eprintln!("       {} serve [--port <n>] [options]", program);
+    eprintln!("       {} tui [options] <study-id>", program);
+    eprintln!("       {} book [--title <text>] [options] <study-id>...", program);
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --encoding <name>          Encoding of PGN files, e.g. windows-1252 or latin-1 (default: UTF-8,");
+    eprintln!("                             else Windows-1252)");
+    eprintln!("  --diagram-keyword <word>   Add a diagram wherever a move comment contains <word>");
+    eprintln!("  --variation-diagrams       Also add diagrams from variations: after an annotated or marked first move");
+    eprintln!("  --eco                      Print ECO code and opening name for diagrams from game moves");
+    eprintln!("  --captured                 Show the pieces each side has captured beside the board");
+    eprintln!("  --eval-bar                 Draw an evaluation bar beside each board ([%eval] comments or --engine)");
+    eprintln!("  --shapes                   Draw the study's arrows and highlighted squares ([%cal], [%csl]) on the boards");
+    eprintln!("  --shape-colors <colors>    lichess (default), print (grays) and/or brushes like R=#808080 (G, R, Y, B)");
+    eprintln!("  --arrow-width <length>     Arrow width on a full-size board (default 1.2mm)");
+    eprintln!("  --arrow-style <style>      solid (default), dashed or by-color (dashes per brush; default with --print-mode bw)");
+    eprintln!("  --links                    Make each board a link to its chapter on Lichess");
+    eprintln!("  --qr                       Print a QR code of the chapter's Lichess address beside each board");
+    eprintln!("  --engine <path>            Analyse each position with a UCI engine (e.g. Stockfish)");
+    eprintln!("                             and print evaluation and best move in the solutions");
+    eprintln!("  --depth <n>                Engine search depth (default: 20)");
+    eprintln!("  --engine-timeout <secs>    Time limit per position (default: 60)");
+    eprintln!("  --engine-jobs <n>          Engine processes run in parallel (default: CPU count)");
+    eprintln!("  --mate-labels              Label forced mates \"Mate in N\" (engine score or own search)");
+    eprintln!("  --mate-depth <n>           Longest mate searched without an engine (default: 3)");
+    eprintln!("  --difficulty               Print a difficulty indicator (1-5) under each diagram");
+    eprintln!("  --sort difficulty          Order the diagrams from easiest to hardest");
+    eprintln!("  --puzzle-db <csv>          Take ratings from the Lichess puzzle database (lichess_db_puzzle.csv)");
+    eprintln!("  --tablebase                Print tablebase results for positions with up to 7 pieces");
+    eprintln!("  --games <file.pgn>         Build puzzles from the worst moves of played games (needs --engine)");
+    eprintln!("  --critical-max <n>         Puzzles taken from each game (default: 3)");
+    eprintln!("  --solutions appendix       List each chapter's mainline moves in a \"Solutions\" section");
+    eprintln!("  --solutions upside-down    Print each page's solutions upside down at the bottom of the page");
+    eprintln!("  --solutions annotated      Solutions section in two columns with the chapters' comments and variations");
+    eprintln!("  --solution-diagrams        Miniature diagram after the key move of longer solutions (appendix, annotated)");
+    eprintln!("  --solutions verso          Print the solutions on the back of each page for duplex printing");
+    eprintln!("  --solutions popup          Put each diagram's solution in a note PDF viewers open on click (not printed)");
+    eprintln!("  --worksheet                Draw ruled answer lines under every board (boards shrink slightly)");
+    eprintln!("  --fillable                 Add a form field under every board to type the answer into");
+    eprintln!("  --hide-captions            Number the diagrams only; chapter names go to the solutions");
+    eprintln!("  --export anki <file.apkg>  Also write an Anki deck: board on the front, caption and solution on the back");
+    eprintln!("  --export png <dir>         Also write every diagram as a PNG image into <dir>");
+    eprintln!("  --export svg <dir>         Also write every diagram as a scalable SVG image into <dir>");
+    eprintln!("  --export html <file.html>  Also write a web page with the diagrams and collapsible solutions");
+    eprintln!("  --export markdown <file>   Also write Markdown notes linking to PNG diagrams in <name>-images/");
+    eprintln!("  --export latex <file.tex>  Also write LaTeX source with xskak \\chessboard diagrams");
+    eprintln!("  --export json <file.json>  Also write the parsed positions, solutions and analysis as JSON");
+    eprintln!("  --export csv <file.csv>    Also write number, chapter, FEN, side to move and solution per position");
+    eprintln!("  --png-captions             Render the number and caption below the board in PNG images");
+    eprintln!("  --review-schedule          Add a spaced repetition table: exercises x review dates with checkboxes");
+    eprintln!("  --review-start <date>      First day of the review schedule, YYYY-MM-DD (default: today)");
+    eprintln!("  --info-page                Add a last page about the study: authors, URL, date, chapters, variants");
+    eprintln!("  --stars                    Rate puzzles with 1-5 stars next to the number (needs a rating)");
+    eprintln!("  --star-thresholds <list>   Lowest ratings for 2-5 stars (default 1400,1800,2200,2600)");
+    eprintln!("  --side <white|black>       Keep only positions where this side is to move");
+    eprintln!("  --max-pieces <n>           Keep only positions with at most n pieces (kings and pawns included)");
+    eprintln!("  --only <phase>             Keep only middlegames or endgames (at most 6 queens, rooks and minor pieces)");
+    eprintln!("  --must-contain <pieces>    Keep only positions with these pieces, e.g. Q, Rr or PPp");
+    eprintln!("  --order <file>             Print the chapters in the order listed (names or numbers, one per line)");
+    eprintln!("  --unlisted <mode>          Chapters missing from the order file: append (default) or skip");
+    eprintln!("  --skip <n>                 Leave out the first n positions (numbers are kept)");
+    eprintln!("  --take <n>                 Render at most n positions");
+    eprintln!("  --max-pages <n>            Render at most n pages of diagrams");
+    eprintln!("  --split-by chapter         Write one PDF per chapter (Study_Ch01_Name.pdf, ...)");
+    eprintln!("  --section-breaks           Start every chapter on a new page, headed by the chapter's name");
+    eprintln!("  --author <name>            Author in the PDF's document properties (default: the study's owner)");
+    eprintln!("  --pdfa                     Archival PDF/A-2b output (embeds a system serif font)");
+    eprintln!("  --encrypt                  Password-protect the PDF: readers may view, print and copy but not modify it");
+    eprintln!("  --owner-password <pw>      Password that lifts the restrictions (required with --encrypt)");
+    eprintln!("  --user-password <pw>       Password needed to open the PDF (default: none)");
+    eprintln!("  --no-print                 With --encrypt, also forbid printing");
+    eprintln!("  --linearize                Web-optimized PDF: viewers show the first page while the rest downloads");
+    eprintln!("  --reproducible             Byte-identical PDFs for the same input, dated SOURCE_DATE_EPOCH (default: 1970-01-01)");
+    eprintln!("  --verbose                  Report how many board images were reused from the board cache");
+    eprintln!("  --cache-dir <dir>          Keep the board images in <dir>, so reruns only render new positions");
+    eprintln!("  --retries <n>              Try failed downloads again n times, waiting longer each time (default: 3)");
+    eprintln!("  --timeout <secs>           Give up on a connection that stalls this long (default: 30)");
+    eprintln!("  --proxy <url>              Send requests through this proxy (default: HTTP_PROXY/HTTPS_PROXY)");
+    eprintln!("  --rate <n>                 Start at most n requests per minute (default: 60)");
+    eprintln!("  --api-base <url>           Download studies from this Lichess instance (default: https://lichess.org)");
+    eprintln!("  --mirror <url>             Try this host when the download fails; repeat for several, tried in order");
+    eprintln!("  --contact <text>           Add an email address or username to the User-Agent sent with requests");
+    eprintln!("  --no-comments              Download studies without comments (no diagram markers or annotated solutions)");
+    eprintln!("  --no-variations            Download studies without variations");
+    eprintln!("  --no-clocks                Download studies without clock times");
+    eprintln!("  --orientation              Download studies with each chapter's board orientation tag");
+    eprintln!("  --offline                  Use only studies in the download cache, never the network");
+    eprintln!("  --watch                    Keep running and remake the PDF whenever the study changes");
+    eprintln!("  --interval <secs>          Time between checks for --watch (default: 300)");
+    eprintln!("  --config <file>            Read options from a file, one per line (flag and value); later flags take precedence");
+    eprintln!("  --preview-server           Show the pages at http://localhost:8000/ instead of writing the PDF,");
+    eprintln!("                             rendered again and reloaded whenever the --config file changes");
+    eprintln!("  --preview-port <n>         Port of --preview-server (default: 8000)");
+    eprintln!("  --image-compression <mode> Board images: flate (lossless, default) or jpeg[:quality] (1-100, default 85)");
+    eprintln!("  --draft                    Quick preview for checking the layout: coarse boards with letters for pieces,");
+    eprintln!("                             no solutions, QR codes, captured pieces or analysis");
+    eprintln!("  --page-size <size>         Paper size: a4 (default) or letter");
+    eprintln!("  --grid <cols>x<rows>|auto  Diagrams across and down each page, e.g. 2x3 (default: 3x3, at most 6x6);");
+    eprintln!("                             auto picks 1x1 to 4x4 by the number of positions");
+    eprintln!("  --template <file>          Page layout of one's own: title, grid, footer and sidebar regions in mm");
+    eprintln!("  --theme <name>             Board colors: gray (default), blue, brown or green");
+    eprintln!("  --renderer <name>          Board drawing: raster (piece images, default) or vector (SVG pieces)");
+    eprintln!("  --print-mode <mode>        color (default) or bw: grayscale images, bordered boards and dashed rulings for laser printers");
+    eprintln!("  --cmyk <profile.icc>       Device CMYK colors for print shops, with this CMYK profile as output intent");
+    eprintln!("  --attach-pgn               Embed the source PGN in the PDF as a file attachment");
+    eprintln!("  --logo <image>             Club logo (PNG, JPEG, ...) in the header of every page");
+    eprintln!("  --logo-pos <position>      top-right (default) or top-left");
+    eprintln!("  --logo-height <length>     Printed logo height, e.g. 15mm (default 12mm, at most 18mm)");
+    eprintln!("  --watermark <text>         Print the text diagonally in light gray behind every page, e.g. DRAFT or a student's name");
+    eprintln!("  --bleed <length>           Extend the pages beyond the trim size, e.g. 3mm (or pt, in)");
+    eprintln!("  --crop-marks               Draw trim marks at the page corners, outside the bleed");
+    eprintln!("  --append <file.pdf>        Also add the generated pages to the end of this PDF (created if missing)");
+    eprintln!("  --shuffle [seed]           Random diagram order; the same seed gives the same order, none a new one on every run");
+    eprintln!("  --seed <n>                 Shuffle with this seed, also one --shuffle would take for a study ID");
+    eprintln!("  --checkboxes               Checkbox beside each exercise number (three with --solutions verso)");
+    eprintln!("  --ids                      Print each exercise's stable ID in small type beside its number");
+    eprintln!("  --numbering <mode>         Exercise numbers: sequence (1, 2, 3, ..., default), chapter (3.1, 3.2, ...), id (the stable IDs) or none");
+    eprintln!("  --number-start <n>         Number of the first exercise, to continue earlier sheets (default 1)");
+    eprintln!("  --number-prefix <text>     Printed before each number, e.g. \"Ex. \"");
+    eprintln!("  --hints <mode>             Hint under each diagram: none (default), move-count or theme");
+    eprintln!("  --hide-side-to-move        Always show boards from White's side; the solutions say whose move it is");
+    eprintln!();
+    eprintln!("Note: A colon (:) in position descriptions triggers a line feed in the PDF");
+    eprintln!("Note: A [%diagram] marker in a move comment adds a diagram after that move");
+}
+
+pub fn next_value<S: Into<String>>(iter: &mut impl Iterator<Item = S>, flag: &str) -> Result<String> {
+    iter.next().map(Into::into).ok_or_else(|| anyhow!("{} requires a value", flag))
+}
+
+// An http(s) address that study paths are appended to, without a trailing slash
+fn next_base_url<S: Into<String>>(iter: &mut impl Iterator<Item = S>, flag: &str) -> Result<String> {
+    let url = next_value(iter, flag)?;
+    match url::Url::parse(&url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(url.trim_end_matches('/').to_string()),
+        _ => Err(anyhow!("Invalid {} '{}', expected e.g. https://lichess.org", flag, url)),
+    }
+}
+
+pub fn next_number<T: std::str::FromStr, S: Into<String>>(iter: &mut impl Iterator<Item = S>, flag: &str) -> Result<T> {
+    let value = next_value(iter, flag)?;
+    value.parse().map_err(|_| anyhow!("{} expects a number, got '{}'", flag, value))
+}
+
+// Reads the logo image, scaled down to what is printed at IMAGE_DPI so that the copy on every
+// page stays small
+fn load_logo(path: &str, position: LogoPosition, height: f32) -> Result<Logo> {
+    use printpdf::image_crate::imageops::FilterType;
+    if height <= 0.0 || height > LOGO_MAX_HEIGHT {
+        return Err(anyhow!("The logo height has to be above 0 and at most {}mm", LOGO_MAX_HEIGHT));
+    }
+    let image = printpdf::image_crate::open(path).map_err(|e| anyhow!("Cannot read logo {}: {}", path, e))?;
+    let aspect = image.width() as f32 / image.height() as f32;
+    let print_width = (height * aspect).min(LOGO_MAX_WIDTH);
+    let print_height = print_width / aspect;
+    let pixels = |mm: f32| (mm / 25.4 * IMAGE_DPI).round().max(1.0) as u32;
+    let image = if image.width() > pixels(print_width) {
+        image.resize_exact(pixels(print_width), pixels(print_height), FilterType::Lanczos3)
+    } else {
+        image
+    };
+    let rgba = image.to_rgba8();
+    let rgb_data = rgba
+        .pixels()
+        .flat_map(|pixel| {
+            let alpha = pixel[3] as u32;
+            [0, 1, 2].map(|i| ((pixel[i] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8)
+        })
+        .collect();
+    Ok(Logo { width: rgba.width(), height: rgba.height(), rgb_data, position, print_width, print_height })
+}
+
+// flate, jpeg or jpeg:<quality>
+fn parse_image_compression(value: &str) -> Result<ImageCompression> {
+    match value.split_once(':') {
+        None if value == "flate" => Ok(ImageCompression::Flate),
+        None if value == "jpeg" => Ok(ImageCompression::Jpeg(JPEG_QUALITY)),
+        Some(("jpeg", quality)) => match quality.parse() {
+            Ok(quality @ 1..=100) => Ok(ImageCompression::Jpeg(quality)),
+            _ => Err(anyhow!("Invalid JPEG quality '{}', expected 1 to 100", quality)),
+        },
+        _ => Err(anyhow!("Unknown image compression: {} (expected flate or jpeg[:quality])", value)),
+    }
+}
+
+// A length like "3mm", "8.5pt" or "0.125in" in millimeters; plain numbers are millimeters
+fn parse_length(value: &str) -> Result<f32> {
+    let (number, factor) = if let Some(number) = value.strip_suffix("mm") {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix("pt") {
+        (number, 25.4 / 72.0)
+    } else if let Some(number) = value.strip_suffix("in") {
+        (number, 25.4)
+    } else {
+        (value, 1.0)
+    };
+    match number.trim().parse::<f32>() {
+        Ok(length) if length >= 0.0 && length.is_finite() => Ok(length * factor),
+        _ => Err(anyhow!("Invalid length '{}', expected e.g. 3mm, 8.5pt or 0.125in", value)),
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options> {
+    parse_args_with_storage(args, Arc::new(DiskStorage))
+}
+
+// The options reading their --config files, and later their other files, through `storage`
+pub fn parse_args_with_storage(args: &[String], storage: Arc<dyn Storage>) -> Result<Options> {
+    let mut study_ids = Vec::new();
+    let mut diagram_keyword = None;
+    let mut encoding = None;
+    let mut eco = false;
+    let mut captured = false;
+    let mut eval_bar = false;
+    let mut variation_diagrams = false;
+    let mut shapes = false;
+    let mut shape_colors = None;
+    let mut arrow_width = None;
+    let mut arrow_style = None;
+    let mut links = false;
+    let mut qr = false;
+    let mut engine_path = None;
+    let mut depth = 20;
+    let mut engine_timeout = 60;
+    let mut engine_jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut mate_labels = false;
+    let mut mate_depth = 3;
+    let mut difficulty = false;
+    let mut sort = None;
+    let mut puzzle_db = None;
+    let mut tablebase = false;
+    let mut games = None;
+    let mut critical_max = 3;
+    let mut solutions = None;
+    let mut page_size = PageSize::A4;
+    let mut grid = Some((3, 3));
+    let mut grid_given = false;
+    let mut template = None;
+    let mut theme = Theme::Gray;
+    let mut renderer: Arc<dyn BoardRenderer> = Arc::new(RasterRenderer);
+    let mut worksheet = false;
+    let mut fillable = false;
+    let mut hide_captions = false;
+    let mut hide_side_to_move = false;
+    let mut hints = None;
+    let mut solution_diagrams = false;
+    let mut review_schedule = false;
+    let mut info_page = false;
+    let mut review_start = None;
+    let mut exports = Vec::new();
+    let mut stars = false;
+    let mut star_thresholds = None;
+    let mut checkboxes = false;
+    let mut numbering = Numbering::Sequence;
+    let mut show_ids = false;
+    let mut number_start = None;
+    let mut number_prefix = String::new();
+    let mut png_captions = false;
+    let mut shuffle = None;
+    let mut split_by = None;
+    let mut section_breaks = false;
+    let mut append = None;
+    let mut pdfa = false;
+    let mut image_compression = ImageCompression::Flate;
+    let mut draft = false;
+    let mut print_mode = PrintMode::Color;
+    let mut cmyk = None;
+    let mut bleed = 0.0;
+    let mut watermark = None;
+    let mut attach_pgn = false;
+    let mut logo = None;
+    let mut logo_position = None;
+    let mut logo_height = None;
+    let mut crop_marks = false;
+    let mut encrypt = false;
+    let mut owner_password = None;
+    let mut user_password = None;
+    let mut no_print = false;
+    let mut linearize = false;
+    let mut reproducible = false;
+    let mut verbose = false;
+    let mut cache_dir = None;
+    let mut retries = DOWNLOAD_RETRIES;
+    let mut timeout = HTTP_TIMEOUT;
+    let mut proxy = None;
+    let mut offline = false;
+    let mut watch = false;
+    let mut watch_interval = None;
+    let mut config_files = Vec::new();
+    let mut preview_server = false;
+    let mut preview_port = None;
+    let mut api_base = LICHESS_URL.to_string();
+    let mut mirrors = Vec::new();
+    let mut contact = None;
+    let mut study_export = STUDY_EXPORT;
+    let mut request_interval = Duration::from_secs(60) / REQUESTS_PER_MINUTE;
+    let mut author = None;
+    let mut side = None;
+    let mut max_pieces = None;
+    let mut phase = None;
+    let mut must_contain = Vec::new();
+    let mut order = None;
+    let mut order_unlisted = true;
+    let mut skip = 0;
+    let mut take = None;
+    let mut max_pages = None;
+
+    let mut iter = PendingArgs(args.iter().skip(1).cloned().collect());
+    while let Some(arg) = iter.next() {
+        let arg = &arg;
+        match arg.as_str() {
+            "--diagram-keyword" => diagram_keyword = Some(next_value(&mut iter, arg)?),
+            "--encoding" => {
+                let label = next_value(&mut iter, arg)?;
+                // WHATWG labels, which spell Latin-1 without a hyphen
+                let encoding_for = |label: &str| encoding_rs::Encoding::for_label(label.trim().as_bytes());
+                encoding = Some(
+                    encoding_for(&label)
+                        .or_else(|| encoding_for(&label.to_lowercase().replace("latin-", "latin")))
+                        .ok_or_else(|| anyhow!("Unknown encoding: {} (e.g. utf-8, windows-1252 or latin-1)", label))?,
+                );
+            }
+            "--eco" => eco = true,
+            "--captured" => captured = true,
+            "--eval-bar" => eval_bar = true,
+            "--variation-diagrams" => variation_diagrams = true,
+            "--shapes" => shapes = true,
+            "--shape-colors" => shape_colors = Some(shapes::parse_colors(&next_value(&mut iter, arg)?)?),
+            "--arrow-width" => arrow_width = Some(parse_length(&next_value(&mut iter, arg)?)?),
+            "--arrow-style" => {
+                arrow_style = match next_value(&mut iter, arg)?.as_str() {
+                    "solid" => Some(shapes::ArrowStyle::Solid),
+                    "dashed" => Some(shapes::ArrowStyle::Dashed),
+                    "by-color" => Some(shapes::ArrowStyle::ByColor),
+                    style => return Err(anyhow!("Unknown arrow style: {} (expected solid, dashed or by-color)", style)),
+                }
+            }
+            "--links" => links = true,
+            "--qr" => qr = true,
+            "--engine" => engine_path = Some(next_value(&mut iter, arg)?),
+            "--depth" => depth = next_number(&mut iter, arg)?,
+            "--engine-timeout" => engine_timeout = next_number(&mut iter, arg)?,
+            "--engine-jobs" => engine_jobs = next_number(&mut iter, arg)?,
+            "--mate-labels" => mate_labels = true,
+            "--mate-depth" => mate_depth = next_number(&mut iter, arg)?,
+            "--difficulty" => difficulty = true,
+            "--sort" => {
+                sort = match next_value(&mut iter, arg)?.as_str() {
+                    "difficulty" => Some(SortKey::Difficulty),
+                    key => return Err(anyhow!("Unknown sort key: {}", key)),
+                }
+            }
+            "--split-by" => {
+                split_by = match next_value(&mut iter, arg)?.as_str() {
+                    "chapter" => Some(SplitKey::Chapter),
+                    key => return Err(anyhow!("Unknown split key: {} (expected chapter)", key)),
+                }
+            }
+            "--section-breaks" => section_breaks = true,
+            "--append" => append = Some(next_value(&mut iter, arg)?),
+            "--pdfa" => pdfa = true,
+            "--encrypt" => encrypt = true,
+            "--owner-password" => owner_password = Some(next_value(&mut iter, arg)?),
+            "--user-password" => user_password = Some(next_value(&mut iter, arg)?),
+            "--no-print" => no_print = true,
+            "--linearize" => linearize = true,
+            "--reproducible" => reproducible = true,
+            "--verbose" => verbose = true,
+            "--cache-dir" => cache_dir = Some(next_value(&mut iter, arg)?),
+            "--retries" => retries = next_number(&mut iter, arg)?,
+            "--timeout" => match next_number(&mut iter, arg)? {
+                0 => return Err(anyhow!("--timeout must be at least 1 second")),
+                seconds => timeout = Duration::from_secs(seconds),
+            },
+            "--proxy" => {
+                let url = next_value(&mut iter, arg)?;
+                if !platform::is_valid_proxy(&url) {
+                    return Err(anyhow!("Invalid proxy URL '{}', expected e.g. http://proxy.example.com:3128", url));
+                }
+                proxy = Some(url);
+            }
+            "--offline" => offline = true,
+            "--watch" => watch = true,
+            "--interval" => match next_number(&mut iter, arg)? {
+                0 => return Err(anyhow!("--interval must be at least 1 second")),
+                seconds => watch_interval = Some(Duration::from_secs(seconds)),
+            },
+            // Only where a flag is expected, not as another flag's value
+            "--config" => {
+                let path = next_value(&mut iter, arg)?;
+                iter.insert(config_options(&path, storage.as_ref())?);
+                config_files.push(path);
+            }
+            "--preview-server" => preview_server = true,
+            "--preview-port" => preview_port = Some(next_number(&mut iter, arg)?),
+            "--no-comments" => study_export.comments = false,
+            "--no-variations" => study_export.variations = false,
+            "--no-clocks" => study_export.clocks = false,
+            "--orientation" => study_export.orientation = true,
+            "--api-base" => api_base = next_base_url(&mut iter, arg)?,
+            "--mirror" => mirrors.push(next_base_url(&mut iter, arg)?),
+            "--contact" => {
+                let value = next_value(&mut iter, arg)?;
+                // Header values are limited to visible ASCII
+                if !value.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+                    return Err(anyhow!("--contact takes ASCII text only, e.g. an email address"));
+                }
+                contact = Some(value);
+            }
+            "--rate" => match next_number(&mut iter, arg)? {
+                0 => return Err(anyhow!("--rate must be at least 1 request per minute")),
+                rate => request_interval = Duration::from_secs(60) / rate,
+            },
+            "--image-compression" => image_compression = parse_image_compression(&next_value(&mut iter, arg)?)?,
+            "--draft" => draft = true,
+            "--print-mode" => {
+                print_mode = match next_value(&mut iter, arg)?.as_str() {
+                    "color" => PrintMode::Color,
+                    "bw" => PrintMode::Bw,
+                    mode => return Err(anyhow!("Unknown print mode: {} (expected color or bw)", mode)),
+                }
+            }
+            "--page-size" => {
+                page_size = match next_value(&mut iter, arg)?.to_lowercase().as_str() {
+                    "a4" => PageSize::A4,
+                    "letter" => PageSize::Letter,
+                    size => return Err(anyhow!("Unknown page size: {} (expected a4 or letter)", size)),
+                }
+            }
+            "--template" => template = Some(PageTemplate::load(&next_value(&mut iter, arg)?)?),
+            "--grid" => {
+                let value = next_value(&mut iter, arg)?;
+                grid = match value.split_once('x').map(|(columns, rows)| (columns.parse(), rows.parse())) {
+                    _ if value == "auto" => None,
+                    Some((Ok(columns), Ok(rows))) => Some((columns, rows)),
+                    _ => return Err(anyhow!("Invalid grid '{}', expected columns x rows like 3x3, or auto", value)),
+                };
+                grid_given = true;
+            }
+            "--theme" => {
+                theme = match next_value(&mut iter, arg)?.as_str() {
+                    "gray" => Theme::Gray,
+                    "blue" => Theme::Blue,
+                    "brown" => Theme::Brown,
+                    "green" => Theme::Green,
+                    theme => return Err(anyhow!("Unknown theme: {} (expected gray, blue, brown or green)", theme)),
+                }
+            }
+            "--renderer" => {
+                renderer = match next_value(&mut iter, arg)?.as_str() {
+                    "raster" => Arc::new(RasterRenderer),
+                    "vector" => Arc::new(VectorRenderer),
+                    name => return Err(anyhow!("Unknown renderer: {} (expected raster or vector)", name)),
+                }
+            }
+            "--cmyk" => cmyk = Some(cmyk::read_profile(&next_value(&mut iter, arg)?)?),
+            "--bleed" => bleed = parse_length(&next_value(&mut iter, arg)?)?,
+            "--crop-marks" => crop_marks = true,
+            "--attach-pgn" => attach_pgn = true,
+            "--logo" => logo = Some(next_value(&mut iter, arg)?),
+            "--logo-pos" => {
+                logo_position = match next_value(&mut iter, arg)?.as_str() {
+                    "top-left" => Some(LogoPosition::TopLeft),
+                    "top-right" => Some(LogoPosition::TopRight),
+                    position => return Err(anyhow!("Unknown logo position: {} (expected top-left or top-right)", position)),
+                }
+            }
+            "--logo-height" => logo_height = Some(parse_length(&next_value(&mut iter, arg)?)?),
+            "--watermark" => watermark = Some(next_value(&mut iter, arg)?).filter(|text| !text.trim().is_empty()),
+            "--author" => author = Some(next_value(&mut iter, arg)?),
+            // Without a seed every run gets a new order (see below); a seed implies --shuffle. A
+            // number of a study ID's length after --shuffle is taken for the study ID
+            "--shuffle" => {
+                let seed = iter.next_if(|value| value.len() != STUDY_ID_LENGTH && value.parse::<u64>().is_ok());
+                shuffle = match seed {
+                    Some(seed) => Some(seed.parse().ok()),
+                    None => shuffle.or(Some(None)),
+                };
+            }
+            "--seed" => shuffle = Some(Some(next_number(&mut iter, arg)?)),
+            "--side" => {
+                side = match next_value(&mut iter, arg)?.as_str() {
+                    "white" => Some(shakmaty::Color::White),
+                    "black" => Some(shakmaty::Color::Black),
+                    value => return Err(anyhow!("Unknown side: {} (expected white or black)", value)),
+                }
+            }
+            "--max-pieces" => max_pieces = Some(next_number(&mut iter, arg)?),
+            "--only" => {
+                phase = match next_value(&mut iter, arg)?.as_str() {
+                    "middlegames" => Some(GamePhase::Middlegame),
+                    "endgames" => Some(GamePhase::Endgame),
+                    value => return Err(anyhow!("Unknown game phase: {} (expected middlegames or endgames)", value)),
+                }
+            }
+            "--must-contain" => {
+                let pieces = next_value(&mut iter, arg)?;
+                if let Some(piece) = pieces.chars().find(|piece| !"KQRBNPkqrbnp".contains(*piece)) {
+                    return Err(anyhow!("--must-contain expects FEN piece letters (KQRBNP, kqrbnp), got '{}'", piece));
+                }
+                must_contain.extend(pieces.chars());
+            }
+            "--order" => order = Some(next_value(&mut iter, arg)?),
+            "--unlisted" => {
+                order_unlisted = match next_value(&mut iter, arg)?.as_str() {
+                    "append" => true,
+                    "skip" => false,
+                    value => return Err(anyhow!("Unknown --unlisted mode: {} (expected append or skip)", value)),
+                }
+            }
+            "--skip" => skip = next_number(&mut iter, arg)?,
+            "--take" => take = Some(next_number(&mut iter, arg)?),
+            "--max-pages" => max_pages = Some(next_number(&mut iter, arg)?),
+            "--puzzle-db" => puzzle_db = Some(next_value(&mut iter, arg)?),
+            "--tablebase" => tablebase = true,
+            "--games" => games = Some(next_value(&mut iter, arg)?),
+            "--critical-max" => critical_max = next_number(&mut iter, arg)?,
+            "--worksheet" => worksheet = true,
+            "--fillable" => fillable = true,
+            "--hide-captions" => hide_captions = true,
+            "--hide-side-to-move" => hide_side_to_move = true,
+            "--solution-diagrams" => solution_diagrams = true,
+            "--stars" => stars = true,
+            "--checkboxes" => checkboxes = true,
+            "--ids" => show_ids = true,
+            "--number-start" => number_start = Some(next_number(&mut iter, arg)?),
+            "--number-prefix" => number_prefix = next_value(&mut iter, arg)?,
+            "--numbering" => {
+                numbering = match next_value(&mut iter, arg)?.as_str() {
+                    "sequence" => Numbering::Sequence,
+                    "chapter" => Numbering::Chapter,
+                    "id" => Numbering::Id,
+                    "none" => Numbering::None,
+                    mode => return Err(anyhow!("Unknown numbering: {}", mode)),
+                }
+            }
+            "--png-captions" => png_captions = true,
+            "--star-thresholds" => star_thresholds = Some(parse_thresholds(&next_value(&mut iter, arg)?)?),
+            "--export" => {
+                let format = match next_value(&mut iter, arg)?.as_str() {
+                    "anki" => ExportFormat::Anki,
+                    "png" => ExportFormat::Png,
+                    "svg" => ExportFormat::Svg,
+                    "html" => ExportFormat::Html,
+                    "markdown" | "md" => ExportFormat::Markdown,
+                    "latex" | "tex" => ExportFormat::Latex,
+                    "json" => ExportFormat::Json,
+                    "csv" => ExportFormat::Csv,
+                    format => return Err(anyhow!("Unknown export format: {}", format)),
+                };
+                exports.push(Export { format, path: next_value(&mut iter, arg)? });
+            }
+            "--review-schedule" => review_schedule = true,
+            "--info-page" => info_page = true,
+            "--review-start" => review_start = Some(schedule::Date::parse(&next_value(&mut iter, arg)?)?),
+            "--hints" => {
+                hints = match next_value(&mut iter, arg)?.as_str() {
+                    "none" => None,
+                    "move-count" => Some(HintMode::MoveCount),
+                    "theme" => Some(HintMode::Theme),
+                    mode => return Err(anyhow!("Unknown hints mode: {}", mode)),
+                }
+            }
+            "--solutions" => {
+                solutions = match next_value(&mut iter, arg)?.as_str() {
+                    "appendix" => Some(SolutionsMode::Appendix),
+                    "upside-down" => Some(SolutionsMode::UpsideDown),
+                    "verso" => Some(SolutionsMode::Verso),
+                    "annotated" => Some(SolutionsMode::Annotated),
+                    "popup" => Some(SolutionsMode::Popup),
+                    mode => return Err(anyhow!("Unknown solutions mode: {}", mode)),
+                }
+            }
+            flag if flag.starts_with("--") => return Err(anyhow!("Unknown option: {}", flag)),
+            _ => study_ids.push(arg.clone()),
+        }
+    }
+
+    let source = match (study_ids.is_empty(), games) {
+        (false, Some(_)) => return Err(anyhow!("Give either study IDs or --games, not both")),
+        (false, None) => Source::Study(study_ids),
+        (true, Some(games)) if engine_path.is_some() => Source::Games(games),
+        (true, Some(_)) => return Err(anyhow!("--games requires --engine")),
+        (true, None) => return Err(anyhow!("Missing study ID")),
+    };
+    // Every study would overwrite the same export file
+    if matches!(&source, Source::Study(study_ids) if study_ids.len() > 1) && !exports.is_empty() {
+        return Err(anyhow!("--export writes a single file; give one study ID with it"));
+    }
+    if offline && tablebase {
+        return Err(anyhow!("--tablebase looks positions up online; leave it out with --offline"));
+    }
+    if watch_interval.is_some() && !watch {
+        return Err(anyhow!("--interval sets how often --watch checks the studies; give --watch with it"));
+    }
+    if watch && (offline || !matches!(source, Source::Study(_))) {
+        return Err(anyhow!("--watch downloads studies again; it needs study IDs and no --offline"));
+    }
+    if preview_port.is_some() && !preview_server {
+        return Err(anyhow!("--preview-port sets the port of --preview-server; give --preview-server with it"));
+    }
+    if preview_server && (watch || !matches!(&source, Source::Study(study_ids) if study_ids.len() == 1)) {
+        return Err(anyhow!("--preview-server shows one study; give one study ID and no --watch"));
+    }
+    if solution_diagrams && !matches!(solutions, Some(SolutionsMode::Appendix | SolutionsMode::Annotated)) {
+        return Err(anyhow!("--solution-diagrams requires --solutions appendix or annotated"));
+    }
+    // A draft leaves out everything that takes long or adds pages; --games still needs its
+    // engine to find the positions
+    if draft {
+        solutions = None;
+        solution_diagrams = false;
+        qr = false;
+        captured = false;
+        tablebase = false;
+        mate_labels = false;
+        if matches!(source, Source::Study(_)) {
+            engine_path = None;
+        }
+    }
+    let created = creation_time(reproducible)?;
+    // A reproducible shuffle needs a seed; without one the order follows the creation time
+    let shuffle = shuffle.map(|seed: Option<u64>| seed.unwrap_or_else(|| if reproducible { created % 1_000_000 } else { random_seed() }));
+    let logo = match logo {
+        Some(path) => Some(load_logo(&path, logo_position.unwrap_or(LogoPosition::TopRight), logo_height.unwrap_or(LOGO_HEIGHT))?),
+        None if logo_position.is_some() || logo_height.is_some() => return Err(anyhow!("--logo-pos and --logo-height require --logo")),
+        None => None,
+    };
+    let shapes = match shapes {
+        true => {
+            let style = shapes::ShapeStyle::new(print_mode == PrintMode::Bw);
+            Some(shapes::ShapeStyle {
+                colors: shape_colors.unwrap_or(style.colors),
+                width: arrow_width.unwrap_or(style.width),
+                arrows: arrow_style.unwrap_or(style.arrows),
+            })
+        }
+        false if shape_colors.is_some() || arrow_width.is_some() || arrow_style.is_some() => {
+            return Err(anyhow!("--shape-colors, --arrow-width and --arrow-style require --shapes"));
+        }
+        false => None,
+    };
+    let encryption = match (encrypt, owner_password) {
+        (true, Some(owner_password)) if !owner_password.is_empty() => {
+            let user_password = user_password.unwrap_or_default();
+            encrypt::check_password(&owner_password)?;
+            encrypt::check_password(&user_password)?;
+            Some(encrypt::Encryption { owner_password, user_password, allow_printing: !no_print })
+        }
+        (true, _) => return Err(anyhow!("--encrypt requires --owner-password")),
+        (false, None) if user_password.is_none() && !no_print => None,
+        (false, _) => return Err(anyhow!("--owner-password, --user-password and --no-print require --encrypt")),
+    };
+    // PDF/A-2 only allows PDF/A files as attachments
+    if attach_pgn && pdfa {
+        return Err(anyhow!("PDF/A-2 does not allow attaching the PGN; use either --pdfa or --attach-pgn"));
+    }
+    // PDF/A-2 annotations have to be printed and carry appearance streams
+    if solutions == Some(SolutionsMode::Popup) && pdfa {
+        return Err(anyhow!("PDF/A-2 does not allow hidden notes; use either --pdfa or --solutions popup"));
+    }
+    // PDF/A-2 forbids NeedAppearances, so fields would need appearance streams of their own
+    if fillable && pdfa {
+        return Err(anyhow!("PDF/A-2 requires appearance streams for form fields; use either --pdfa or --fillable"));
+    }
+    // The image crate writes no CMYK JPEGs
+    if cmyk.is_some() && image_compression != ImageCompression::Flate {
+        return Err(anyhow!("--cmyk stores the board images with flate; --image-compression jpeg is not supported with it"));
+    }
+    if encryption.is_some() && pdfa {
+        return Err(anyhow!("PDF/A does not allow encryption; use either --pdfa or --encrypt"));
+    }
+    // Encrypted PDFs cannot be merged afterwards
+    if encryption.is_some() && append.is_some() {
+        return Err(anyhow!("--append cannot add to or from encrypted PDFs; leave out --encrypt"));
+    }
+    let mut pdf = PdfOptions::builder().page_size(page_size).theme(theme);
+    pdf = match grid {
+        Some((columns, rows)) => pdf.grid(columns, rows),
+        None if max_pages.is_some() => return Err(anyhow!("--max-pages counts the pages of a fixed grid; give --grid with columns x rows")),
+        None => pdf.auto_grid(),
+    };
+    if let Some(solutions) = solutions {
+        pdf = pdf.solutions(solutions);
+    }
+    if let Some(template) = template {
+        if grid_given {
+            return Err(anyhow!("The --template sets the grid; leave out --grid"));
+        }
+        pdf = pdf.template(template);
+    }
+    let pdf = pdf.build()?;
+    if number_start.is_some() && numbering != Numbering::Sequence {
+        return Err(anyhow!("--number-start applies to --numbering sequence"));
+    }
+    if numbering == Numbering::None && (pdf.solutions().is_some() || review_schedule || review_start.is_some()) {
+        return Err(anyhow!("--numbering none leaves no numbers to find the exercises by in --solutions or --review-schedule"));
+    }
+
+    Ok(Options {
+        source,
+        encoding,
+        diagram_keyword,
+        eco,
+        captured,
+        eval_bar,
+        variation_diagrams,
+        shapes,
+        links,
+        qr,
+        engine: engine_path.map(|path| engine::EngineConfig {
+            path,
+            depth,
+            timeout: std::time::Duration::from_secs(engine_timeout),
+            jobs: engine_jobs,
+        }),
+        mate_labels,
+        mate_depth,
+        difficulty,
+        sort,
+        shuffle,
+        split_by,
+        section_breaks,
+        append,
+        pdfa,
+        image_compression,
+        draft,
+        print_mode,
+        cmyk,
+        bleed,
+        crop_marks,
+        watermark,
+        logo,
+        attach_pgn,
+        encryption,
+        linearize,
+        reproducible,
+        verbose,
+        cache_dir,
+        retries,
+        timeout,
+        proxy,
+        user_agent: match contact {
+            Some(contact) => format!("fen2pdf/{} (contact: {})", env!("CARGO_PKG_VERSION"), contact),
+            None => format!("fen2pdf/{}", env!("CARGO_PKG_VERSION")),
+        },
+        api_base,
+        mirrors,
+        study_export,
+        request_interval,
+        offline,
+        watch: watch.then(|| watch_interval.unwrap_or(WATCH_INTERVAL)),
+        config_files,
+        preview: preview_server.then(|| preview_port.unwrap_or(PREVIEW_PORT)),
+        created,
+        author,
+        side,
+        max_pieces,
+        phase,
+        must_contain,
+        order,
+        order_unlisted,
+        skip,
+        take,
+        max_pages,
+        puzzle_db,
+        tablebase,
+        critical_max,
+        pdf,
+        renderer,
+        progress: None,
+        storage,
+        network: None,
+        worksheet,
+        fillable,
+        hide_captions,
+        hide_side_to_move,
+        hints,
+        solution_diagrams,
+        review_schedule: (review_schedule || review_start.is_some()).then(|| review_start.unwrap_or(schedule::Date::from_unix_time(created))),
+        info_page,
+        book_pages: None,
+        exports,
+        star_thresholds: (stars || star_thresholds.is_some()).then(|| star_thresholds.unwrap_or(difficulty::LEVEL_THRESHOLDS)),
+        checkboxes,
+        numbering,
+        // The IDs are the numbers already
+        show_ids: show_ids && numbering == Numbering::Sequence,
+        number_start: number_start.unwrap_or(1),
+        number_prefix,
+        png_captions,
+    })
+}
+
+// The arguments parse_args has still to go through
+struct PendingArgs(std::collections::VecDeque<String>);
+
+impl PendingArgs {
+    // Parsed next, before the arguments that follow on the command line, which so take precedence
+    fn insert(&mut self, args: Vec<String>) {
+        for arg in args.into_iter().rev() {
+            self.0.push_front(arg);
+        }
+    }
+
+    // The next argument, if it is one that `accept`s
+    fn next_if(&mut self, accept: impl FnOnce(&str) -> bool) -> Option<String> {
+        self.0.front().is_some_and(|arg| accept(arg)).then(|| self.0.pop_front()).flatten()
+    }
+}
+
+impl Iterator for PendingArgs {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.0.pop_front()
+    }
+}
+
+// The options of a --config file: one per line, the flag and its value (which may contain
+// spaces); # starts a comment line
+fn config_options(path: &str, storage: &dyn Storage) -> Result<Vec<String>> {
+    let text = read_text(storage, path).map_err(|e| anyhow!("Cannot read config file '{}': {}", path, e))?;
+    let mut options = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (flag, value) = line.split_once(char::is_whitespace).map_or((line, ""), |(flag, value)| (flag, value.trim()));
+        if flag == "--config" {
+            return Err(anyhow!("Config file '{}' cannot name another with --config", path));
+        }
+        options.push(flag.to_string());
+        if !value.is_empty() {
+            options.push(value.to_string());
+        }
+    }
+    Ok(options)
+}
+
+// A text file named in the options (--config, --order), from the storage of the run
+pub fn read_text(storage: &dyn Storage, path: &str) -> std::io::Result<String> {
+    String::from_utf8(storage.read(path)?).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+// Four ascending ratings, e.g. "1200,1500,1800,2100"
+fn parse_thresholds(value: &str) -> Result<[u32; 4]> {
+    let invalid = || anyhow!("Invalid star thresholds '{}', expected four ascending ratings like 1200,1500,1800,2100", value);
+    let ratings: Vec<u32> = value.split(',').map(|rating| rating.trim().parse().map_err(|_| invalid())).collect::<Result<_>>()?;
+    let thresholds: [u32; 4] = ratings.try_into().map_err(|_| invalid())?;
+    if thresholds.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(invalid());
+    }
+    Ok(thresholds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChessPosition, MemoryStorage};
+    use crate::selection::order_positions;
+
+    #[test]
+    fn shuffle_takes_an_optional_seed() {
+        let parse = |line: &str| parse_args(&line.split(' ').map(str::to_string).collect::<Vec<_>>()).unwrap();
+        let study_ids = |options: &Options| match &options.source {
+            Source::Study(ids) => ids.join(" "),
+            Source::Games(_) => String::new(),
+        };
+        let options = parse("fen2pdf --shuffle 42 abcd1234");
+        assert_eq!((options.shuffle, study_ids(&options).as_str()), (Some(42), "abcd1234"));
+        // A study ID made of digits is not a seed
+        let options = parse("fen2pdf --shuffle 12345678");
+        assert!(options.shuffle.is_some());
+        assert_eq!(study_ids(&options), "12345678");
+        let options = parse("fen2pdf --seed 12345678 --shuffle abcd1234");
+        assert_eq!((options.shuffle, study_ids(&options).as_str()), (Some(12345678), "abcd1234"));
+    }
+
+    #[test]
+    fn config_and_order_files_come_from_the_storage() {
+        let storage = Arc::new(MemoryStorage::default());
+        storage.insert("layout.conf", b"# Club handouts\n--watermark Club Night\n--order order.txt\n".to_vec());
+        storage.insert("order.txt", b"Second\n1\n".to_vec());
+        let args: Vec<String> = ["fen2pdf", "--config", "layout.conf", "abcd1234"].map(str::to_string).to_vec();
+        let options = parse_args_with_storage(&args, storage).unwrap();
+        assert_eq!(options.watermark.as_deref(), Some("Club Night"));
+
+        let mut positions: Vec<ChessPosition> = ["First", "Second", "Third"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ChessPosition { chapter: i + 1, chapter_name: name.to_string(), ..ChessPosition::new(i as i32 + 1, name.to_string(), String::new()) })
+            .collect();
+        order_positions(&mut positions, options.order.as_deref().unwrap(), false, options.storage.as_ref()).unwrap();
+        let order: Vec<String> = positions.iter().map(|pos| format!("{}:{}", pos.number, pos.description)).collect();
+        assert_eq!(order, ["1:Second", "2:First"]);
+    }
+}
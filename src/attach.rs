@@ -0,0 +1,58 @@
+// File attachments (--attach-pgn): the source PGN embedded in the PDF, listed in the viewer's
+// attachments panel, so the document carries the exact input it was made from
+
+use anyhow::Result;
+use printpdf::lopdf::{Document, Object, Stream, StringFormat, dictionary};
+use crate::metadata::pdf_date;
+use crate::outline::text_string;
+
+pub struct Attachment<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    // MIME type, e.g. "application/x-chess-pgn"
+    pub mime_type: &'a str,
+    pub data: &'a [u8],
+    // Seconds since 1970 (UTC)
+    pub modified: u64,
+}
+
+// Adds the file to the document's embedded files name tree
+pub fn attach_file(document: &mut Document, attachment: &Attachment) -> Result<()> {
+    let date = Object::string_literal(pdf_date(attachment.modified));
+    let file = Stream::new(
+        dictionary! {
+            "Type" => "EmbeddedFile",
+            "Subtype" => Object::Name(attachment.mime_type.as_bytes().to_vec()),
+            "Params" => dictionary! {
+                "Size" => attachment.data.len() as i64,
+                "ModDate" => date,
+                "CheckSum" => Object::String(md5::compute(attachment.data).to_vec(), StringFormat::Hexadecimal),
+            },
+        },
+        attachment.data.to_vec(),
+    );
+    let file = document.add_object(file);
+    let specification = document.add_object(dictionary! {
+        "Type" => "Filespec",
+        // The ASCII file name for old readers, the Unicode one for the others
+        "F" => Object::string_literal(attachment.name.chars().map(|c| if c.is_ascii() { c } else { '_' }).collect::<String>()),
+        "UF" => text_string(attachment.name),
+        "Desc" => text_string(attachment.description),
+        "AFRelationship" => "Source",
+        "EF" => dictionary! { "F" => file },
+    });
+
+    let catalog = document.catalog_mut()?;
+    if !catalog.has(b"Names") {
+        catalog.set("Names", dictionary! {});
+    }
+    let names = catalog.get_mut(b"Names").and_then(Object::as_dict_mut)?;
+    if !names.has(b"EmbeddedFiles") {
+        names.set("EmbeddedFiles", dictionary! { "Names" => Vec::<Object>::new() });
+    }
+    let tree = names.get_mut(b"EmbeddedFiles").and_then(Object::as_dict_mut)?;
+    let entries = tree.get_mut(b"Names").and_then(Object::as_array_mut)?;
+    entries.push(text_string(attachment.name));
+    entries.push(Object::Reference(specification));
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// Pages for professional cutting (--bleed, --crop-marks): the page grows around the A4 trim
+// size, which becomes the TrimBox, and trim marks are drawn at its corners outside the bleed
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::{Document, Object};
+use printpdf::{Mm, Pt};
+
+// Trim marks start at least this far from the trimmed edge, outside the bleed
+const MARK_OFFSET: f32 = 3.0;
+const MARK_LENGTH: f32 = 5.0;
+// Hairline in points, as print shops expect
+const MARK_WIDTH: f32 = 0.25;
+
+// Extends every page by `bleed` millimeters (and the room for the marks) on all sides; the
+// content keeps its coordinates, as the boxes grow into negative ones
+pub fn add_bleed(document: &mut Document, bleed: f32, crop_marks: bool) -> Result<()> {
+    let points = |mm: f32| Pt::from(Mm(mm)).0;
+    let offset = bleed.max(MARK_OFFSET);
+    let margin = if crop_marks { points(offset + MARK_LENGTH) } else { points(bleed) };
+    for page_id in document.get_pages().into_values() {
+        let page = document.get_dictionary(page_id)?;
+        let media_box: Vec<f32> = page.get(b"MediaBox").and_then(Object::as_array)?.iter().filter_map(|value| value.as_float().ok()).collect();
+        let [left, bottom, right, top] = media_box[..] else {
+            return Err(anyhow!("Page without a valid MediaBox"));
+        };
+        let grown = |by: f32| vec![(left - by).into(), (bottom - by).into(), (right + by).into(), (top + by).into()];
+        let page = document.get_object_mut(page_id).and_then(Object::as_dict_mut)?;
+        page.set("TrimBox", grown(0.0));
+        page.set("BleedBox", grown(points(bleed)));
+        page.set("MediaBox", grown(margin));
+        page.set("CropBox", grown(margin));
+
+        if crop_marks {
+            // The page's own content is wrapped so that its graphics state cannot change the marks
+            let mut content = b"q\n".to_vec();
+            content.extend(document.get_page_content(page_id)?);
+            content.extend(format!("\nQ\nq 0 0 0 RG {} w\n", MARK_WIDTH).as_bytes());
+            let (near, far) = (points(offset), points(offset + MARK_LENGTH));
+            for (x, y, outward_x, outward_y) in [(left, bottom, -1.0, -1.0), (right, bottom, 1.0, -1.0), (left, top, -1.0, 1.0), (right, top, 1.0, 1.0)] {
+                // One mark along each edge meeting at the corner, continuing that edge
+                content.extend(format!("{} {} m {} {} l S\n", x + outward_x * near, y, x + outward_x * far, y).as_bytes());
+                content.extend(format!("{} {} m {} {} l S\n", x, y + outward_y * near, x, y + outward_y * far).as_bytes());
+            }
+            content.extend(b"Q\n");
+            document.change_page_content(page_id, content)?;
+        }
+    }
+    Ok(())
+}
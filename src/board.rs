@@ -0,0 +1,683 @@
+// Board images: rendering a position to pixels or SVG, the board cache, and placing a board
+// with its captions and decorations in a grid cell
+
+use crate::{BOARD_CACHE_CAPACITY, BOARD_CACHE_FORMAT, BOARD_DESC_GAP, BOARD_IMAGE_PX, BOARD_IMAGE_SIZE, BOARD_SIZE, CAPTURED_STRIP_GAP, CAPTURED_STRIP_PX, CHECKBOX_GAP, CHECKBOX_SIZE, ChessPosition, DRAFT_BOARD_PX, EVAL_BAR_GAP, EVAL_BAR_WIDTH, EXERCISE_ID_FONT_SIZE, IMAGE_DPI, ImageCompression, Options, PrintMode, QR_GAP, RenderError, SVG_COORDINATE_MARGIN, SVG_FONT_FAMILY, SVG_SQUARE, Theme, WORKSHEET_BOTTOM, WORKSHEET_LINES, WORKSHEET_LINE_SPACING, cmyk, difficulty, images, pieces, shapes};
+use crate::export::escape_xml;
+use crate::page::{decoration_width, draw_checkbox, draw_eval_bar, draw_qr_code, draw_ruling, draw_stars, number_width};
+use crate::solutions::{draw_board_border, draw_draft_pieces};
+use anyhow::{Result, anyhow};
+use printpdf::*;
+use std::fs;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Mutex;
+
+// The board image comes from generate_board_rgb_data, in RGB format for better Apple PDF viewer
+// compatibility. Returns the baseline of the caption's last line, as draw_coordinates_and_description does
+#[allow(clippy::too_many_arguments)]
+pub fn draw_chess_board(
+    layer: &PdfLayerReference,
+    x: f32,
+    y: f32,
+    pos: &ChessPosition,
+    (width, height, rgb_data): (u32, u32, Vec<u8>),
+    font: &printpdf::IndirectFontRef,
+    images: &images::ImageStore,
+    options: &Options,
+) -> Result<f32> {
+    let image = rgb_image(width, height, rgb_data, images, options)?;
+    
+    let scale_factor = options.board_scale();
+    
+    // PDF coordinates start from bottom-left, but our y is calculated from top
+    let pdf_y = options.pdf.page_height() - y - BOARD_SIZE; // Flip Y coordinate
+    
+    // A shrunk board keeps its top edge, so its bottom moves up
+    let pdf_y = pdf_y + BOARD_IMAGE_SIZE * (1.0 - scale_factor);
+    
+    image.add_to_layer(layer.clone(), ImageTransform {
+        translate_x: Some(Mm(x)),
+        translate_y: Some(Mm(pdf_y)),
+        scale_x: Some(scale_factor),
+        scale_y: Some(scale_factor),
+        // The board is BOARD_IMAGE_SIZE wide at any resolution (draft boards are coarser)
+        dpi: Some(IMAGE_DPI * width as f32 / BOARD_IMAGE_PX as f32),
+        ..Default::default()
+    });
+    if options.draft {
+        draw_draft_pieces(layer, x, pdf_y, BOARD_IMAGE_SIZE * scale_factor, pos, font, options);
+    }
+    if options.print_mode == PrintMode::Bw {
+        draw_board_border(layer, x, pdf_y, BOARD_IMAGE_SIZE * scale_factor);
+    }
+    if let Some(style) = &options.shapes {
+        shapes::draw_shapes(layer, x, pdf_y, BOARD_IMAGE_SIZE * scale_factor, &pos.shapes, pos.flipped(options), style);
+    }
+    
+    // Clicking the board in a PDF viewer opens the chapter; the link has no visible border
+    if let Some(url) = pos.url.as_ref().filter(|_| options.links) {
+        let size = BOARD_IMAGE_SIZE * scale_factor;
+        layer.add_link_annotation(LinkAnnotation::new(
+            printpdf::Rect::new(Mm(x), Mm(pdf_y), Mm(x + size), Mm(pdf_y + size)),
+            Some(BorderArray::Solid([0.0, 0.0, 0.0])),
+            Some(ColorArray::Transparent),
+            Actions::uri(url.clone()),
+            None,
+        ));
+    }
+    
+    // Eval bar, then the captured pieces strip to the right of the board, same height as the board
+    let mut right_edge = x + BOARD_IMAGE_SIZE * scale_factor;
+    if let Some(score) = pos.evaluation().filter(|_| options.eval_bar) {
+        draw_eval_bar(layer, right_edge + EVAL_BAR_GAP, pdf_y, BOARD_IMAGE_SIZE * scale_factor, score, pos.flipped(options));
+        right_edge += EVAL_BAR_GAP + EVAL_BAR_WIDTH;
+    }
+    if options.captured && pos.has_standard_material() {
+        if let Some((strip_width, strip_height, strip_data)) = generate_captured_strip_rgb_data(pos, options)? {
+            let strip_x = right_edge + CAPTURED_STRIP_GAP;
+            right_edge += CAPTURED_STRIP_GAP + BOARD_IMAGE_SIZE * scale_factor * strip_width as f32 / BOARD_IMAGE_PX as f32;
+            let strip = rgb_image(strip_width, strip_height, strip_data, images, options)?;
+            strip.add_to_layer(layer.clone(), ImageTransform {
+                translate_x: Some(Mm(strip_x)),
+                translate_y: Some(Mm(pdf_y)),
+                scale_x: Some(scale_factor),
+                scale_y: Some(scale_factor),
+                ..Default::default()
+            });
+        }
+    }
+    
+    if let Some(url) = pos.url.as_ref().filter(|_| options.qr) {
+        draw_qr_code(layer, right_edge + QR_GAP, pdf_y + BOARD_IMAGE_SIZE * scale_factor, url)?;
+    }
+    
+    // Draw coordinates and description
+    draw_coordinates_and_description(layer, x, y, pos, font, options)
+}
+
+// Create image from RGB data, 8 bits per component for Apple PDF viewer compatibility; raw
+// pixels are deflated right away and kept in `images` until the document is saved, JPEG
+// images are stored as such
+pub fn rgb_image(width: u32, height: u32, rgb_data: Vec<u8>, images: &images::ImageStore, options: &Options) -> Result<printpdf::Image> {
+    use printpdf::image_crate::{codecs::jpeg::JpegEncoder, ColorType};
+    // Gray printed from DeviceGray uses black toner only, and takes a third of the data
+    let grayscale = options.print_mode == PrintMode::Bw;
+    // Device CMYK for the print shop (--cmyk); the gray of bw mode is black ink already
+    if options.cmyk.is_some() && !grayscale {
+        return Ok(image_xobject(width, height, images.placeholder(cmyk::rgb_to_cmyk(&rgb_data)), ColorSpace::Cmyk, None));
+    }
+    let (data, color_type, color_space) = if grayscale {
+        (rgb_to_gray(&rgb_data), ColorType::L8, ColorSpace::Greyscale)
+    } else {
+        (rgb_data, ColorType::Rgb8, ColorSpace::Rgb)
+    };
+    if let ImageCompression::Jpeg(quality) = options.image_compression {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, quality).encode(&data, width, height, color_type)?;
+        return Ok(image_xobject(width, height, jpeg, color_space, Some(ImageFilter::DCT)));
+    }
+    if data.len() != (width * height) as usize * color_type.channel_count() as usize {
+        return Err(anyhow!("Failed to create image buffer from RGB data"));
+    }
+    // Smoothed when scaled, like the images printpdf converts itself
+    let mut image = image_xobject(width, height, images.placeholder(data), color_space, None);
+    image.image.interpolate = true;
+    Ok(image)
+}
+
+// 8-bit image data printpdf stores as it is
+fn image_xobject(width: u32, height: u32, image_data: Vec<u8>, color_space: ColorSpace, image_filter: Option<ImageFilter>) -> printpdf::Image {
+    printpdf::Image::from(ImageXObject {
+        width: Px(width as usize),
+        height: Px(height as usize),
+        color_space,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: false,
+        image_data,
+        image_filter,
+        smask: None,
+        clipping_bbox: None,
+    })
+}
+
+// Luma with the Rec. 601 weights, one byte per pixel
+fn rgb_to_gray(rgb_data: &[u8]) -> Vec<u8> {
+    rgb_data
+        .chunks_exact(3)
+        .map(|pixel| ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000) as u8)
+        .collect()
+}
+
+pub fn png_bytes(width: u32, height: u32, rgb_data: &[u8]) -> Result<Vec<u8>> {
+    use printpdf::image_crate::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(rgb_data, width, height, ColorType::Rgb8)?;
+    Ok(png)
+}
+
+// Convert pixmap to RGB data for Apple PDF viewer compatibility
+pub fn pixmap_to_rgb(pixmap: &tiny_skia::Pixmap) -> Vec<u8> {
+    let mut rgb_data = Vec::with_capacity((pixmap.width() * pixmap.height() * 3) as usize);
+    for pixel in pixmap.pixels() {
+        rgb_data.push(pixel.red());
+        rgb_data.push(pixel.green());
+        rgb_data.push(pixel.blue());
+        // Skip alpha channel for RGB format
+    }
+    rgb_data
+}
+
+// Pieces missing from each side compared to the starting set, as (white, black);
+// promoted pieces beyond the starting count are charged to the missing pawns
+fn captured_pieces(fen: &str) -> (Vec<char>, Vec<char>) {
+    const START_COUNTS: [usize; 4] = [1, 2, 2, 2];
+    
+    let board = parse_fen(fen.split(' ').next().unwrap_or(""));
+    let count = |piece: char| board.iter().flatten().filter(|&&square| square == piece).count();
+    
+    let lost = |pieces: [char; 4], pawn: char| {
+        let mut lost = Vec::new();
+        let mut promoted = 0;
+        for (&piece, &start) in pieces.iter().zip(START_COUNTS.iter()) {
+            let current = count(piece);
+            promoted += current.saturating_sub(start);
+            lost.extend(std::iter::repeat_n(piece, start.saturating_sub(current)));
+        }
+        let pawns_lost = 8usize.saturating_sub(count(pawn) + promoted);
+        lost.extend(std::iter::repeat_n(pawn, pawns_lost));
+        lost
+    };
+    
+    (lost(['Q', 'R', 'B', 'N'], 'P'), lost(['q', 'r', 'b', 'n'], 'p'))
+}
+
+// Column of miniature pieces; each side's captures sit at its own edge of the board.
+// None when nothing has been captured
+fn generate_captured_strip_rgb_data(pos: &ChessPosition, options: &Options) -> Result<Option<(u32, u32, Vec<u8>)>> {
+    let (white_lost, black_lost) = captured_pieces(&pos.fen);
+    if white_lost.is_empty() && black_lost.is_empty() {
+        return Ok(None);
+    }
+    let (top, bottom) = if pos.flipped(options) {
+        (black_lost, white_lost)
+    } else {
+        (white_lost, black_lost)
+    };
+    
+    let width = CAPTURED_STRIP_PX;
+    let height = BOARD_IMAGE_PX;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).unwrap();
+    pixmap.fill(tiny_skia::Color::WHITE);
+    
+    // Shrink the miniatures when both groups would not fit in one column
+    let total = (top.len() + bottom.len()).max(1) as u32;
+    let icon_size = width.min(height / total) as usize;
+    
+    for (i, &piece) in top.iter().enumerate() {
+        draw_piece_to_pixmap(&mut pixmap, piece, 0, i * icon_size, icon_size, [255; 3])?;
+    }
+    for (i, &piece) in bottom.iter().enumerate() {
+        draw_piece_to_pixmap(&mut pixmap, piece, 0, height as usize - (i + 1) * icon_size, icon_size, [255; 3])?;
+    }
+    
+    Ok(Some((width, height, pixmap_to_rgb(&pixmap))))
+}
+
+pub fn parse_fen(fen_board: &str) -> [[char; 8]; 8] {
+    let mut board = [[' '; 8]; 8];
+    let ranks: Vec<&str> = fen_board.split('/').collect();
+    
+    for (rank_idx, rank) in ranks.iter().enumerate().take(8) {
+        let mut file = 0;
+        for ch in rank.chars() {
+            if ch.is_ascii_digit() {
+                let empty_count = ch.to_digit(10).unwrap_or(0) as usize;
+                for _ in 0..empty_count {
+                    if file < 8 {
+                        board[rank_idx][file] = ' ';
+                        file += 1;
+                    }
+                }
+            } else if ch == '[' {
+                // Crazyhouse pockets follow the board in brackets
+                break;
+            } else if ch == '~' {
+                // Crazyhouse marks promoted pieces with a tilde
+                continue;
+            } else if file < 8 {
+                board[rank_idx][file] = ch;
+                file += 1;
+            }
+        }
+    }
+    
+    board
+}
+
+thread_local! {
+    // The pixmap generate_board_rgb_data draws into, kept for the thread's next board; every
+    // square is painted over, so nothing of the previous board remains
+    static BOARD_PIXMAP: RefCell<Option<tiny_skia::Pixmap>> = const { RefCell::new(None) };
+}
+
+pub fn generate_board_rgb_data(pos: &ChessPosition, options: &Options) -> Result<(u32, u32, Vec<u8>), RenderError> {
+    use tiny_skia::*;
+    
+    // Scale board image size to match the larger 75mm boards
+    // 75mm boards need higher resolution for crisp PDF embedding
+    let board_size_px = BOARD_IMAGE_PX;  // Increased from 400px to 600px for larger boards
+    let square_size_px = board_size_px / 8;
+    let mut scratch = BOARD_PIXMAP.with(RefCell::take);
+    let pixmap = scratch.get_or_insert_with(|| Pixmap::new(board_size_px, board_size_px).unwrap());
+    
+    // Parse FEN
+    let fen_parts: Vec<&str> = pos.fen.split(' ').collect();
+    if fen_parts.is_empty() {
+        return Ok((board_size_px, board_size_px, Vec::new()));
+    }
+    let board = parse_fen(fen_parts[0]);
+    
+    // Draw squares and pieces
+    let (light, dark) = options.pdf.theme().square_colors();
+    for rank in 0..8 {
+        for file in 0..8 {
+            let mut draw_rank = rank;
+            let mut draw_file = file;
+            
+            // Flip board if black to move
+            if pos.flipped(options) {
+                draw_rank = 7 - rank;
+                draw_file = 7 - file;
+            }
+            
+            let square_x = (file as u32) * square_size_px;
+            let square_y = (rank as u32) * square_size_px;
+            
+            // Draw square background
+            let square_color = if (draw_rank + draw_file) % 2 == 0 { light } else { dark };
+            let color = Color::from_rgba8(square_color[0], square_color[1], square_color[2], 255);
+            
+            // Fill square
+            let rect = Rect::from_xywh(square_x as f32, square_y as f32, square_size_px as f32, square_size_px as f32).unwrap();
+            let mut paint = Paint::default();
+            paint.set_color(color);
+            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+            
+            // Draw piece if present
+            let piece = board[draw_rank][draw_file];
+            if piece != ' ' {
+                draw_piece_to_pixmap(pixmap, piece, square_x as usize, square_y as usize, square_size_px as usize, square_color)?;
+            }
+        }
+    }
+    
+    let rgb_data = pixmap_to_rgb(pixmap);
+    BOARD_PIXMAP.with(|cell| cell.replace(scratch));
+    Ok((board_size_px, board_size_px, rgb_data))
+}
+
+// The board from the options' renderer as RGB data
+pub fn render_board(pos: &ChessPosition, options: &Options) -> Result<BoardImage> {
+    options.renderer.render(pos, options)?.into_rgb()
+}
+
+// Empty board for --draft, DRAFT_BOARD_PX wide; the pieces are letters drawn over it
+pub fn draft_board_rgb_data(theme: Theme) -> (u32, u32, Vec<u8>) {
+    let (light, dark) = theme.square_colors();
+    let square_size_px = DRAFT_BOARD_PX / 8;
+    let rgb_data = (0..DRAFT_BOARD_PX * DRAFT_BOARD_PX)
+        .flat_map(|i| {
+            let (row, column) = (i / DRAFT_BOARD_PX / square_size_px, i % DRAFT_BOARD_PX / square_size_px);
+            // Same colors as generate_board_rgb_data
+            if (row + column) % 2 == 0 { light } else { dark }
+        })
+        .collect();
+    (DRAFT_BOARD_PX, DRAFT_BOARD_PX, rgb_data)
+}
+
+// Width, height and RGB data of a rendered board
+type BoardImage = (u32, u32, Vec<u8>);
+
+// What a board image depends on: the board part of the FEN, the orientation, the colors and
+// the renderer's name
+type BoardKey = (String, bool, Theme, String);
+
+// The boards of a BoardCache, oldest first in `order`
+#[derive(Default)]
+struct CachedBoards {
+    boards: HashMap<BoardKey, BoardImage>,
+    order: std::collections::VecDeque<BoardKey>,
+}
+
+impl CachedBoards {
+    fn insert(&mut self, key: BoardKey, board: BoardImage) {
+        if self.boards.insert(key.clone(), board).is_some() {
+            return;
+        }
+        self.order.push_back(key);
+        if self.order.len() > BOARD_CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.boards.remove(&oldest);
+            }
+        }
+    }
+}
+
+// Board images rendered during a run, by the board part of the FEN, the orientation, the
+// theme and the renderer (all a board image depends on), so positions repeated across chapters, studies and
+// solution diagrams are rasterized once. With --cache-dir they are also kept on disk as PNGs,
+// so a rerun on a grown study only renders the new chapters' boards
+pub struct BoardCache {
+    boards: Mutex<CachedBoards>,
+    dir: Option<std::path::PathBuf>,
+    hits: AtomicUsize,
+    disk_hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl BoardCache {
+    pub fn new(dir: Option<&str>) -> Result<Self> {
+        if let Some(dir) = dir {
+            fs::create_dir_all(dir).map_err(|e| anyhow!("Cannot create cache directory '{}': {}", dir, e))?;
+        }
+        Ok(BoardCache {
+            boards: Mutex::new(CachedBoards::default()),
+            dir: dir.map(std::path::PathBuf::from),
+            hits: AtomicUsize::new(0),
+            disk_hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    pub(crate) fn get(&self, pos: &ChessPosition, options: &Options) -> Result<BoardImage> {
+        let key = (
+            pos.fen.split(' ').next().unwrap_or("").to_string(),
+            pos.flipped(options),
+            options.pdf.theme(),
+            options.renderer.name().to_string(),
+        );
+        if let Some(board) = self.boards.lock().unwrap().boards.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(board.clone());
+        }
+        // Rendered outside the lock; a board two threads miss at once is rendered twice
+        let file = self.dir.as_ref().map(|dir| {
+            let digest = md5::compute(format!("{}\n{}\n{}\n{}\n{}\n{}", BOARD_CACHE_FORMAT, BOARD_IMAGE_PX, key.0, key.1, key.2.name(), key.3));
+            dir.join(format!("{:x}.png", digest))
+        });
+        let board = match file.as_ref().and_then(|file| read_cached_board(file)) {
+            Some(board) => {
+                self.disk_hits.fetch_add(1, Ordering::Relaxed);
+                board
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                let board = render_board(pos, options)?;
+                // A board that cannot be stored is rendered again next time
+                if let Some(file) = &file {
+                    if let Err(e) = png_bytes(board.0, board.1, &board.2).and_then(|png| Ok(fs::write(file, png)?)) {
+                        eprintln!("Warning: cannot write {}: {}", file.display(), e);
+                    }
+                }
+                board
+            }
+        };
+        self.boards.lock().unwrap().insert(key, board.clone());
+        Ok(board)
+    }
+
+    // Hit rate for --verbose
+    pub(crate) fn report(&self) {
+        let (hits, disk_hits, misses) =
+            (self.hits.load(Ordering::Relaxed), self.disk_hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed));
+        let total = hits + disk_hits + misses;
+        if total == 0 {
+            return;
+        }
+        let from_disk = if self.dir.is_some() { format!(", {} read from the cache directory", disk_hits) } else { String::new() };
+        println!(
+            "Board cache: {} of {} boards reused ({:.0}%){}, {} rendered",
+            hits + disk_hits,
+            total,
+            100.0 * (hits + disk_hits) as f64 / total as f64,
+            from_disk,
+            misses
+        );
+    }
+}
+
+// A board stored by an earlier run; None when missing or unreadable (an interrupted write)
+fn read_cached_board(file: &std::path::Path) -> Option<BoardImage> {
+    let image = printpdf::image_crate::open(file).ok()?.into_rgb8();
+    Some((image.width(), image.height(), image.into_raw()))
+}
+
+// The board as an SVG document: squares as rectangles, the vector piece set and coordinates
+// in a margin on the left and bottom, oriented like the PDF diagram. Without coordinates it is
+// just the squares, as the PDF draws them beside the board itself
+pub fn board_svg(pos: &ChessPosition, options: &Options, coordinates: bool) -> String {
+    const SQUARE: usize = SVG_SQUARE;
+    let margin = if coordinates { SVG_COORDINATE_MARGIN } else { 0 };
+    let size = margin + 8 * SQUARE;
+    let flipped = pos.flipped(options);
+    let board = parse_fen(pos.fen.split(' ').next().unwrap_or(""));
+    let caption = if options.hide_captions { String::new() } else { pos.caption() };
+    
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" viewBox=\"0 0 {size} {size}\">\n<title>{}</title>\n",
+        escape_xml(&pos.numbered(options, &caption))
+    );
+    let (light, dark) = options.pdf.theme().square_colors();
+    let hex = |[r, g, b]: [u8; 3]| format!("#{:02x}{:02x}{:02x}", r, g, b);
+    let dark = hex(dark);
+    svg.push_str(&format!("<rect x=\"{margin}\" y=\"0\" width=\"{0}\" height=\"{0}\" fill=\"{1}\"/>\n", 8 * SQUARE, hex(light)));
+    for row in 0..8 {
+        for column in 0..8 {
+            let (rank, file) = if flipped { (7 - row, 7 - column) } else { (row, column) };
+            let (x, y) = (margin + column * SQUARE, row * SQUARE);
+            if (rank + file) % 2 == 1 {
+                svg.push_str(&format!("<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE}\" height=\"{SQUARE}\" fill=\"{dark}\"/>\n"));
+            }
+            if let Some(piece) = pieces::get_piece_svg_body(board[rank][file]) {
+                svg.push_str(&format!("<g transform=\"translate({x} {y})\">{}</g>\n", piece.trim()));
+            }
+        }
+    }
+    
+    // Files along the bottom, ranks along the left edge
+    if coordinates {
+        let text = |x: usize, y: usize, label: char| {
+            format!("<text x=\"{x}\" y=\"{y}\" font-family=\"{SVG_FONT_FAMILY}\" font-size=\"10\" text-anchor=\"middle\">{label}</text>\n")
+        };
+        for i in 0..8 {
+            let file = if flipped { (b'h' - i as u8) as char } else { (b'a' + i as u8) as char };
+            let rank = if flipped { (b'1' + i as u8) as char } else { (b'8' - i as u8) as char };
+            svg.push_str(&text(margin + i * SQUARE + SQUARE / 2, size - 3, file));
+            svg.push_str(&text(margin / 2, i * SQUARE + SQUARE / 2 + 4, rank));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Piece tiles: a piece scaled to a square size on the square's RGB color (None for characters
+// without a piece image)
+type PieceTiles = HashMap<(char, usize, [u8; 3]), Option<tiny_skia::Pixmap>>;
+
+thread_local! {
+    // The tiles each thread has drawn so far; a run needs the twelve pieces on both square
+    // colors at the board's size, plus the captured strip's sizes
+    static PIECE_TILES: RefCell<PieceTiles> = RefCell::new(HashMap::new());
+}
+
+// Draws the piece on the square at (x, y) by copying its pre-scaled tile over it
+fn draw_piece_to_pixmap(pixmap: &mut tiny_skia::Pixmap, piece: char, x: usize, y: usize, size: usize, square_color: [u8; 3]) -> Result<()> {
+    PIECE_TILES.with(|tiles| {
+        let mut tiles = tiles.borrow_mut();
+        let tile = match tiles.entry((piece, size, square_color)) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(piece_tile(piece, size, square_color)?),
+        };
+        let Some(tile) = tile else {
+            return Ok(());
+        };
+        
+        // Row by row, both being RGBA
+        let width = pixmap.width() as usize;
+        let data = pixmap.data_mut();
+        for (row, tile_row) in tile.data().chunks_exact(size * 4).enumerate() {
+            let start = ((y + row) * width + x) * 4;
+            data[start..start + size * 4].copy_from_slice(tile_row);
+        }
+        Ok(())
+    })
+}
+
+// The piece's PNG scaled to `size` on the square color; None for a piece without an image
+fn piece_tile(piece: char, size: usize, square_color: [u8; 3]) -> Result<Option<tiny_skia::Pixmap>> {
+    let Some(png_data) = pieces::get_piece_png_data(piece) else {
+        return Ok(None);
+    };
+    // Load PNG data from embedded bytes
+    let png_pixmap = tiny_skia::Pixmap::decode_png(png_data)
+        .map_err(|_| RenderError::PieceImage { piece })?;
+    
+    let mut tile = tiny_skia::Pixmap::new(size as u32, size as u32).unwrap();
+    tile.fill(tiny_skia::Color::from_rgba8(square_color[0], square_color[1], square_color[2], 255));
+    
+    // Scale the PNG to fit the square size
+    let scale_x = size as f32 / png_pixmap.width() as f32;
+    let scale_y = size as f32 / png_pixmap.height() as f32;
+    let transform = tiny_skia::Transform::from_scale(scale_x, scale_y);
+    tile.draw_pixmap(0, 0, png_pixmap.as_ref(), &tiny_skia::PixmapPaint::default(), transform, None);
+    Ok(Some(tile))
+}
+
+// Returns the baseline of the caption's last line; answers go below it
+fn draw_coordinates_and_description(layer: &PdfLayerReference, x: f32, y: f32, pos: &ChessPosition, font: &printpdf::IndirectFontRef, options: &Options) -> Result<f32> {
+    use printpdf::*;
+    
+    // Use chapter name with position number for board descriptions; hidden captions
+    // (which often give the theme away) leave just the number
+    let description = if options.hide_captions { String::new() } else { pos.caption() };
+    let mut first_text = description.as_str();
+    let mut second_line = String::new();
+    
+    // Split at colon if present
+    if let Some(colon_pos) = description.find(':') {
+        first_text = &description[..colon_pos + 1];
+        second_line = description[colon_pos + 1..].trim().to_string();
+    }
+    let first_line = pos.numbered(options, first_text);
+    
+    // Shrunk boards keep their top edge; everything below them moves up
+    let scale = options.board_scale();
+    let shift = BOARD_IMAGE_SIZE * (1.0 - scale);
+    
+    // Position text below the board with proper gap
+    let text_y = y + BOARD_SIZE + BOARD_DESC_GAP; // Below the board with gap
+    let pdf_text_y = options.pdf.page_height() - text_y + shift; // Flip Y coordinate for PDF
+    
+    // Add first line of text, with the ID, checkboxes and the star rating between number and description
+    let id = options.show_ids.then(|| pos.exercise_id());
+    let checkboxes = options.checkbox_count();
+    let stars = pos.rating.zip(options.star_thresholds).map(|(rating, thresholds)| difficulty::level_from_rating(rating, &thresholds));
+    if id.is_none() && checkboxes == 0 && stars.is_none() {
+        layer.use_text(first_line, 11.0, Mm(x), Mm(pdf_text_y), font);
+    } else {
+        let number = pos.exercise_mark(options);
+        let mut text_x = if number.is_empty() { x } else { x + number_width(&number, 11.0) + 1.5 };
+        let id_width = id.as_ref().map_or(0.0, |id| number_width(id, EXERCISE_ID_FONT_SIZE) + 1.5);
+        layer.use_text(first_text.trim(), 11.0, Mm(text_x + id_width + decoration_width(checkboxes, stars.is_some())), Mm(pdf_text_y), font);
+        layer.use_text(number, 11.0, Mm(x), Mm(pdf_text_y), font);
+        if let Some(id) = id {
+            layer.use_text(id, EXERCISE_ID_FONT_SIZE, Mm(text_x), Mm(pdf_text_y), font);
+            text_x += id_width;
+        }
+        if checkboxes > 0 {
+            layer.set_outline_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+            layer.set_outline_thickness(0.3);
+            for _ in 0..checkboxes {
+                draw_checkbox(layer, text_x, pdf_text_y - 0.5);
+                text_x += CHECKBOX_SIZE + CHECKBOX_GAP;
+            }
+            text_x += 1.0 - CHECKBOX_GAP;
+        }
+        if let Some(stars) = stars {
+            draw_stars(layer, text_x, pdf_text_y, stars);
+        }
+    }
+    
+    // Add second line if it exists
+    if !second_line.is_empty() {
+        let second_line_y = pdf_text_y - 5.0; // 5mm below first line
+        layer.use_text(second_line.clone(), 11.0, Mm(x), Mm(second_line_y), font);
+    }
+    
+    // Add variant and opening labels below the description
+    let mut label_y = if second_line.is_empty() { pdf_text_y - 5.0 } else { pdf_text_y - 10.0 };
+    for label in pos.caption_labels(options) {
+        layer.use_text(label, 9.0, Mm(x), Mm(label_y), font);
+        label_y -= 4.0;
+    }
+    let text_bottom = label_y + 4.0;
+    
+    // Ruled answer lines under the caption, as many as fit above the next row
+    if options.worksheet {
+        let lowest = options.pdf.page_height() - y - BOARD_SIZE - WORKSHEET_BOTTOM;
+        let mut line_y = text_bottom - WORKSHEET_LINE_SPACING;
+        layer.set_outline_thickness(0.3);
+        for _ in 0..WORKSHEET_LINES {
+            if line_y < lowest {
+                break;
+            }
+            draw_ruling(layer, x, x + BOARD_IMAGE_SIZE * scale, line_y, options.print_mode);
+            line_y -= WORKSHEET_LINE_SPACING;
+        }
+    }
+    
+    // Add chess board coordinates (a1-h8)
+    let square_size = BOARD_SIZE / 11.5 * scale;
+    
+    // Add file coordinates (a-h) at the bottom
+
+    if pos.flipped(options) {
+
+        for i in 0..8 {
+            let file_char = (b'h' - i) as char;
+            let coord_x = x + (i as f32 * square_size) + (square_size / 2.0) - 1.0; // Center in square
+            let coord_y = options.pdf.page_height() - (y + BOARD_SIZE + 4.0) + 1.5 + shift; // Just below board
+            layer.use_text(file_char.to_string(), 6.0, Mm(coord_x), Mm(coord_y), font);
+        }
+    }
+    else {
+                for i in 0..8 {
+            let file_char = (b'a' + i) as char;
+            let coord_x = x + (i as f32 * square_size) + (square_size / 2.0) - 1.0; // Center in square
+            let coord_y = options.pdf.page_height() - (y + BOARD_SIZE + 4.0) + 1.5 + shift; // Just below board
+            layer.use_text(file_char.to_string(), 6.0, Mm(coord_x), Mm(coord_y), font);
+        }
+    }
+    
+    // Add rank coordinates (1-8) on the left
+    if pos.flipped(options) {
+        for i in 0..8 {
+            let rank_char = (b'0' + 1 + i) as char; 
+            let coord_x = x - 2.5 ; // To the left of board
+            let coord_y = options.pdf.page_height() - 25.0 - (y + (i as f32 * square_size) + (square_size / 4.0) + 1.0); // Center in square
+            layer.use_text(rank_char.to_string(), 6.0, Mm(coord_x), Mm(coord_y), font);
+        }
+    }
+    else {
+        for i in 0..8 {
+            let rank_char = (b'1' + (7 - i)) as char; 
+            let coord_x = x - 2.5 ; // To the left of board
+            let coord_y = options.pdf.page_height() - 25.0 - (y + (i as f32 * square_size) + (square_size / 4.0) + 1.0); // Center in square
+            layer.use_text(rank_char.to_string(), 6.0, Mm(coord_x), Mm(coord_y), font);
+        }
+    }
+    
+    Ok(text_bottom)
+}
@@ -0,0 +1,220 @@
+// `fen2pdf book [--title <text>] [options] <study-id>...`: one PDF of several studies, a part
+// per study. A title page lists the parts; each part opens with a page of its study's name and
+// chapters, followed by the study's pages as fen2pdf makes them with the options given. The
+// pages are numbered through the whole book, and the parts' bookmarks follow each other.
+// What applies to a whole document (the CMYK output intent, attachments, --reproducible) is
+// done to the merged book, since merging keeps only the first document's catalog
+
+use crate::{BoardCache, MARGIN_LEFT, MARGIN_RIGHT, Options, Source, StudyData};
+use anyhow::{Result, anyhow};
+use printpdf::{Mm, PdfDocument, PdfLayerReference};
+
+const DEFAULT_TITLE: &str = "Chess Exercises";
+
+// Contents lines below the headings, in columns when they do not fit under one another
+const CONTENTS_TOP: f32 = 80.0;
+const CONTENTS_BOTTOM: f32 = 25.0;
+const CONTENTS_LINE_HEIGHT: f32 = 6.0;
+// Room for the page number at the right of a column
+const CONTENTS_PAGE_WIDTH: f32 = 10.0;
+
+// A title or part page: a heading over the contents, each line with its page in the book
+struct FrontPage {
+    kicker: Option<String>,
+    title: String,
+    contents: Vec<(String, usize)>,
+    bookmark: String,
+}
+
+pub fn make_book(args: &[String]) -> Result<()> {
+    let mut title = None;
+    let mut options_args = vec!["fen2pdf".to_string()];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--title" => title = Some(crate::next_value(&mut iter, arg)?),
+            _ => options_args.push(arg.clone()),
+        }
+    }
+    let options = crate::parse_args(&options_args)?;
+    let Source::Study(study_ids) = &options.source else { return Err(anyhow!("fen2pdf book takes study IDs")) };
+    if options.split_by.is_some() || options.append.is_some() || options.encryption.is_some() || options.linearize || options.watch.is_some() {
+        return Err(anyhow!("fen2pdf book writes one PDF; leave out --split-by, --append, --encrypt, --linearize and --watch"));
+    }
+    let title = title.unwrap_or_else(|| DEFAULT_TITLE.to_string());
+
+    // Each study with the options for its part (its grid with --grid auto)
+    let mut studies = Vec::new();
+    let mut part_options = Vec::new();
+    for (study_id, study_data) in study_ids.iter().zip(crate::fetch_lichess_studies(study_ids, &options)) {
+        let mut study_data = study_data.map_err(|e| anyhow!("Study '{}': {}", study_id, e))?;
+        crate::prepare_positions(&mut study_data, &options)?;
+        part_options.push(options.fitted_to(study_data.positions.len()));
+        studies.push(study_data);
+    }
+
+    // The title page, then each part's page and its study's pages
+    let plans: Vec<_> = studies.iter().zip(&part_options).map(|(study_data, options)| crate::page_plan(study_data, options)).collect();
+    let mut part_pages = Vec::new();
+    let mut page_count = 1;
+    for (_, pages) in &plans {
+        part_pages.push(page_count + 1);
+        page_count += 1 + pages;
+    }
+
+    let temp_file = |name: &str| std::env::temp_dir().join(format!("fen2pdf_book_{}_{}.pdf", std::process::id(), name)).to_string_lossy().to_string();
+    let metadata = book_metadata(&title, &studies, &options);
+    let mut files = Vec::new();
+    let result = (|| {
+        let contents = studies.iter().enumerate().map(|(i, study_data)| (format!("Part {}: {}", i + 1, study_data.name), part_pages[i])).collect();
+        let front = FrontPage { kicker: None, title: title.clone(), contents, bookmark: title.clone() };
+        files.push(temp_file("title"));
+        write_front_page(&front, &files[0], 1, page_count, &metadata, &options)?;
+
+        let boards = BoardCache::new(options.cache_dir.as_deref())?;
+        for (i, ((study_data, (sections, _)), part_options)) in studies.iter().zip(&plans).zip(part_options).enumerate() {
+            // The book attaches the PGNs itself
+            let part_options = Options { attach_pgn: false, ..part_options };
+            let part = FrontPage {
+                kicker: Some(format!("Part {}", i + 1)),
+                title: study_data.name.clone(),
+                contents: part_contents(study_data, sections, &part_options).into_iter().map(|(line, page)| (line, part_pages[i] + page)).collect(),
+                bookmark: format!("Part {}", i + 1),
+            };
+            files.push(temp_file(&format!("part{}", i + 1)));
+            write_front_page(&part, &files[files.len() - 1], part_pages[i], page_count, &metadata, &options)?;
+
+            let part_options = Options { book_pages: Some((part_pages[i], page_count)), ..part_options };
+            files.push(temp_file(&format!("study{}", i + 1)));
+            crate::create_pdf(study_data, &files[files.len() - 1], &part_options, &boards)?;
+            println!("Rendered part {} of {}: {}", i + 1, studies.len(), study_data.name);
+        }
+
+        // Merged beside the book and renamed when done, so that a failure leaves an earlier
+        // book in place
+        let filename = format!("{}.pdf", crate::pdf_base_name(&title));
+        let merged = format!("{}.{}.part", filename, std::process::id());
+        files.push(merged.clone());
+        for file in &files[..files.len() - 1] {
+            crate::merge::append_pdf(&merged, file)?;
+        }
+        // The parts' labels start at 1 as their sheets would; the book counts on
+        let mut book = printpdf::lopdf::Document::load(&merged)?;
+        crate::labels::set_page_labels(&mut book)?;
+        if options.attach_pgn {
+            for study_data in &studies {
+                crate::attach::attach_file(&mut book, &crate::attach::Attachment {
+                    name: &format!("{}.pgn", crate::pdf_base_name(&study_data.name)),
+                    description: "Source PGN",
+                    mime_type: "application/x-chess-pgn",
+                    data: study_data.pgn.as_bytes(),
+                    modified: options.created,
+                })?;
+            }
+        }
+        if options.reproducible {
+            crate::reproducible::make_reproducible(&mut book);
+        }
+        book.save(&merged).map_err(|e| anyhow!("Cannot write '{}': {}", merged, e))?;
+        std::fs::rename(&merged, &filename).map_err(|e| anyhow!("Cannot write '{}': {}", filename, e))?;
+        println!("Generated book: {} with {} parts on {} pages", filename, studies.len(), page_count);
+        for study_data in &studies {
+            crate::print_skipped(study_data);
+        }
+        Ok(())
+    })();
+    for file in &files {
+        let _ = std::fs::remove_file(file);
+    }
+    result
+}
+
+// A part's chapters and sections, with their pages counted from the part's first study page
+fn part_contents(study_data: &StudyData, sections: &[(&str, usize)], options: &Options) -> Vec<(String, usize)> {
+    let diagram_pages = if options.pdf.solutions() == Some(crate::SolutionsMode::Verso) { 2 } else { 1 };
+    let outline = crate::outline_entries(study_data, options, diagram_pages, sections);
+    // Exercises outside chapters are left to the bookmarks
+    outline
+        .children
+        .into_iter()
+        .filter(|entry| !entry.children.is_empty() || sections.iter().any(|(title, _)| *title == entry.title))
+        .map(|entry| (entry.title, entry.page))
+        .collect()
+}
+
+// The book's title, the studies' authors and the exercises; the title page's metadata becomes
+// the book's
+fn book_metadata(title: &str, studies: &[StudyData], options: &Options) -> crate::metadata::Metadata {
+    let mut authors: Vec<&str> = Vec::new();
+    for author in studies.iter().flat_map(|study_data| study_data.info.authors.iter()) {
+        if !authors.contains(&author.as_str()) {
+            authors.push(author);
+        }
+    }
+    let exercises: usize = studies.iter().map(|study_data| study_data.positions.len()).sum();
+    crate::metadata::Metadata {
+        title: title.to_string(),
+        author: options.author.clone().unwrap_or_else(|| authors.join(", ")),
+        subject: format!("{} chess exercises from {} studies", exercises, studies.len()),
+        keywords: ["chess".to_string(), "exercises".to_string()].into_iter().chain(studies.iter().map(|study_data| study_data.name.clone())).collect(),
+        created: options.created,
+        pdfa: options.pdfa,
+    }
+}
+
+fn write_front_page(front: &FrontPage, path: &str, page: usize, page_count: usize, metadata: &crate::metadata::Metadata, options: &Options) -> Result<()> {
+    let (width, height) = (options.pdf.page_width(), options.pdf.page_height());
+    let (doc, page_id, layer_id) = PdfDocument::new(&front.title, Mm(width), Mm(height), "Layer 1");
+    let (font, font_bold) = if options.pdfa {
+        let (regular, bold) = crate::pdfa::serif_fonts()?;
+        (doc.add_external_font(regular.as_slice())?, doc.add_external_font(bold.as_slice())?)
+    } else {
+        (doc.add_builtin_font(printpdf::BuiltinFont::TimesRoman)?, doc.add_builtin_font(printpdf::BuiltinFont::TimesBold)?)
+    };
+    let layer = doc.get_page(page_id).get_layer(layer_id);
+    let images = crate::images::ImageStore::default();
+    crate::draw_page_header(&layer, "", page, page_count, &font, &images, options)?;
+    draw_front_page(&layer, front, &font, &font_bold, options);
+
+    let mut pdf = printpdf::lopdf::Document::load_mem(&doc.save_to_bytes()?)?;
+    crate::images::fill_in(&mut pdf, images)?;
+    crate::outline::add_outline(&mut pdf, &[crate::outline::Entry { title: front.bookmark.clone(), page: 1, children: Vec::new() }])?;
+    crate::metadata::set_metadata(&mut pdf, metadata)?;
+    // Trimmed and colored like the study pages; the title page's output intent is the book's
+    if options.bleed > 0.0 || options.crop_marks {
+        crate::bleed::add_bleed(&mut pdf, options.bleed, options.crop_marks)?;
+    }
+    if let Some(profile) = &options.cmyk {
+        crate::cmyk::make_cmyk(&mut pdf, profile)?;
+    }
+    if options.pdfa {
+        crate::pdfa::make_conformant(&mut pdf)?;
+    }
+    crate::subset::subset_fonts(&mut pdf)?;
+    pdf.prune_objects();
+    pdf.compress();
+    pdf.save(path).map_err(|e| anyhow!("Cannot write '{}': {}", path, e))?;
+    Ok(())
+}
+
+fn draw_front_page(layer: &PdfLayerReference, front: &FrontPage, font: &printpdf::IndirectFontRef, font_bold: &printpdf::IndirectFontRef, options: &Options) {
+    let height = options.pdf.page_height();
+    if let Some(kicker) = &front.kicker {
+        layer.use_text(kicker, 14.0, Mm(MARGIN_LEFT), Mm(height - 40.0), font_bold);
+    }
+    layer.use_text(&front.title, 24.0, Mm(MARGIN_LEFT), Mm(height - 52.0), font);
+    if front.contents.is_empty() {
+        return;
+    }
+    layer.use_text("Contents", 14.0, Mm(MARGIN_LEFT), Mm(height - CONTENTS_TOP + 2.0 * CONTENTS_LINE_HEIGHT), font_bold);
+
+    let per_column = (((height - CONTENTS_TOP - CONTENTS_BOTTOM) / CONTENTS_LINE_HEIGHT) as usize).max(1);
+    let columns = front.contents.len().div_ceil(per_column);
+    let column_width = (options.pdf.page_width() - MARGIN_LEFT - MARGIN_RIGHT) / columns as f32;
+    for (i, (line, page)) in front.contents.iter().enumerate() {
+        let x = MARGIN_LEFT + (i / per_column) as f32 * column_width;
+        let y = height - CONTENTS_TOP - (i % per_column) as f32 * CONTENTS_LINE_HEIGHT;
+        layer.use_text(line, 11.0, Mm(x), Mm(y), font);
+        layer.use_text(page.to_string(), 11.0, Mm(x + column_width - CONTENTS_PAGE_WIDTH), Mm(y), font);
+    }
+}
@@ -0,0 +1,60 @@
+// Chess960 helpers: Shredder-FEN detection, castling mode and start position numbers
+
+use shakmaty::CastlingMode;
+
+// Shredder-FEN names the castling rook files (e.g. HAha) instead of using KQkq
+pub fn is_shredder_castling(fen: &str) -> bool {
+    match fen.split_whitespace().nth(2) {
+        Some(castling) => castling != "-" && castling.chars().any(|c| !"KQkq".contains(c)),
+        None => false,
+    }
+}
+
+pub fn castling_mode(chess960: bool) -> CastlingMode {
+    if chess960 {
+        CastlingMode::Chess960
+    } else {
+        CastlingMode::Standard
+    }
+}
+
+// Back rank of start position n (0-959) following Scharnagl's numbering
+fn back_rank(n: u32) -> [char; 8] {
+    let mut rank = [' '; 8];
+
+    // Light-squared bishop on b, d, f or h; dark-squared bishop on a, c, e or g
+    rank[(n % 4 * 2 + 1) as usize] = 'B';
+    let n = n / 4;
+    rank[(n % 4 * 2) as usize] = 'B';
+    let n = n / 4;
+
+    // Queen on one of the six remaining squares
+    let free: Vec<usize> = (0..8).filter(|&i| rank[i] == ' ').collect();
+    rank[free[(n % 6) as usize]] = 'Q';
+    let n = n / 6;
+
+    // Knights on two of the five remaining squares
+    const KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+    let free: Vec<usize> = (0..8).filter(|&i| rank[i] == ' ').collect();
+    let (first, second) = KNIGHTS[n as usize];
+    rank[free[first]] = 'N';
+    rank[free[second]] = 'N';
+
+    // King between the rooks on the last three squares
+    let free: Vec<usize> = (0..8).filter(|&i| rank[i] == ' ').collect();
+    rank[free[0]] = 'R';
+    rank[free[1]] = 'K';
+    rank[free[2]] = 'R';
+
+    rank
+}
+
+// Start position number if the FEN's board is one of the 960 initial arrangements
+pub fn start_position_number(fen: &str) -> Option<u32> {
+    let board = fen.split_whitespace().next()?;
+    (0..960).find(|&n| {
+        let white: String = back_rank(n).iter().collect();
+        let black = white.to_lowercase();
+        board == format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{}", black, white)
+    })
+}
@@ -0,0 +1,110 @@
+// Print shop output (--cmyk): device CMYK colors for the board images, text and lines, and
+// the ICC profile of the printing condition as the output intent
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::content::{Content, Operation};
+use printpdf::lopdf::{Document, Object, Stream, dictionary};
+use std::path::Path;
+
+#[derive(Clone)]
+pub struct Profile {
+    pub data: Vec<u8>,
+    // The profile's description ("Coated FOGRA39"), else its file name
+    pub name: String,
+}
+
+// Reads an ICC profile, which has to describe a CMYK output device
+pub fn read_profile(path: &str) -> Result<Profile> {
+    let data = std::fs::read(path).map_err(|e| anyhow!("Cannot read ICC profile {}: {}", path, e))?;
+    if data.len() < 132 || &data[36..40] != b"acsp" {
+        return Err(anyhow!("{} is not an ICC profile", path));
+    }
+    if &data[16..20] != b"CMYK" {
+        return Err(anyhow!("{} is not a CMYK profile (its color space is {})", path, String::from_utf8_lossy(&data[16..20]).trim()));
+    }
+    let name = description(&data).unwrap_or_else(|| Path::new(path).file_stem().unwrap_or_default().to_string_lossy().into_owned());
+    Ok(Profile { data, name })
+}
+
+// The 'desc' tag: ASCII text in version 2 profiles, UTF-16 in the multilingual version 4 ones
+fn description(data: &[u8]) -> Option<String> {
+    let number = |offset: usize| data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) as usize);
+    let tags = number(128)?;
+    let (offset, length) = (0..tags)
+        .map(|i| 132 + 12 * i)
+        .find(|&entry| data.get(entry..entry + 4) == Some(b"desc"))
+        .and_then(|entry| Some((number(entry + 4)?, number(entry + 8)?)))?;
+    let tag = data.get(offset..offset + length)?;
+    let text = match &tag[..4] {
+        b"desc" => {
+            let count = number(offset + 8)?;
+            String::from_utf8_lossy(tag.get(12..12 + count)?).trim_end_matches('\0').to_string()
+        }
+        b"mluc" => {
+            let (count, start) = (number(offset + 20)?, number(offset + 24)?);
+            let units: Vec<u16> = tag.get(start..start + count)?.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+// Naive conversion without the profile: gray comes out as black ink only, which keeps the
+// boards and pieces free of registration problems
+pub fn rgb_to_cmyk(rgb_data: &[u8]) -> Vec<u8> {
+    rgb_data
+        .chunks_exact(3)
+        .flat_map(|pixel| {
+            let max = *pixel.iter().max().unwrap() as u32;
+            let black = 255 - max;
+            let ink = |value: u8| ((max - value as u32) * 255).checked_div(max).unwrap_or(0) as u8;
+            [ink(pixel[0]), ink(pixel[1]), ink(pixel[2]), black as u8]
+        })
+        .collect()
+}
+
+// Rewrites the RGB and gray colors set on every page as CMYK (pages start with black ink
+// selected, for the text that sets no color) and declares the output intent
+pub fn make_cmyk(document: &mut Document, profile: &Profile) -> Result<()> {
+    for page_id in document.get_pages().into_values() {
+        let mut content = Content::decode(&document.get_page_content(page_id)?)?;
+        for operation in content.operations.iter_mut() {
+            convert_operation(operation);
+        }
+        let black = || vec![Object::Real(0.0), Object::Real(0.0), Object::Real(0.0), Object::Real(1.0)];
+        content.operations.splice(0..0, [Operation::new("k", black()), Operation::new("K", black())]);
+        document.change_page_content(page_id, content.encode()?)?;
+    }
+
+    let stream = document.add_object(Stream::new(dictionary! { "N" => 4 }, profile.data.clone()));
+    let intent = dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFX",
+        "OutputConditionIdentifier" => Object::string_literal(profile.name.clone()),
+        "Info" => Object::string_literal(profile.name.clone()),
+        "DestinationOutputProfile" => stream,
+    };
+    document.catalog_mut()?.set("OutputIntents", vec![Object::Dictionary(intent)]);
+    Ok(())
+}
+
+fn convert_operation(operation: &mut Operation) {
+    let values: Vec<f32> = operation.operands.iter().filter_map(|operand| operand.as_float().ok()).collect();
+    let (cmyk, operator) = match (operation.operator.as_str(), values.as_slice()) {
+        ("rg", &[r, g, b]) => (rgb_color(r, g, b), "k"),
+        ("RG", &[r, g, b]) => (rgb_color(r, g, b), "K"),
+        ("g", &[gray]) => ([0.0, 0.0, 0.0, 1.0 - gray], "k"),
+        ("G", &[gray]) => ([0.0, 0.0, 0.0, 1.0 - gray], "K"),
+        _ => return,
+    };
+    *operation = Operation::new(operator, cmyk.iter().map(|&value| Object::Real(value)).collect());
+}
+
+// Same conversion as for the images
+fn rgb_color(r: f32, g: f32, b: f32) -> [f32; 4] {
+    let max = r.max(g).max(b);
+    let ink = |value: f32| if max == 0.0 { 0.0 } else { (max - value) / max };
+    [ink(r), ink(g), ink(b), 1.0 - max]
+}
@@ -0,0 +1,100 @@
+// Critical moments of played games: the moves that threw away the most winning chances,
+// measured on Lichess' -1..1 winning chances scale
+
+use anyhow::{Result, anyhow};
+use shakmaty::{fen::Fen, san::SanPlus, CastlingMode, Chess, EnPassantMode};
+
+use crate::engine::{Analysis, Score};
+use crate::{pgn, variant};
+
+// Lichess' mistake and blunder thresholds on the winning chances scale
+const MISTAKE: f64 = 0.2;
+const BLUNDER: f64 = 0.3;
+
+// The mainline of a game: `fens[i]` is the position before `moves[i]`,
+// the last FEN the final position
+pub struct GameLine {
+    pub title: String,
+    pub fens: Vec<String>,
+    // Moves as played, in SAN
+    pub moves: Vec<String>,
+}
+
+pub struct CriticalMove {
+    pub index: usize,
+    pub loss: f64,
+    pub before: Score,
+    pub after: Score,
+}
+
+impl CriticalMove {
+    pub fn annotation(&self) -> &'static str {
+        if self.loss >= BLUNDER { "??" } else { "?" }
+    }
+}
+
+// Play through a standard chess game from `start_fen` (or the initial position)
+// and record every position
+pub fn game_line(game: &pgn::PgnGame, start_fen: Option<&str>) -> Result<GameLine> {
+    let title = format!("{} - {}", game.tag("White").unwrap_or("?"), game.tag("Black").unwrap_or("?"));
+    if variant::parse_variant_tag(game.tag("Variant"))? != shakmaty::variant::Variant::Chess {
+        return Err(anyhow!("only standard chess games can be analysed"));
+    }
+    let mut position: Chess = match start_fen {
+        Some(fen) => Fen::from_ascii(fen.as_bytes())
+            .map_err(|e| anyhow!("invalid FEN '{}': {}", fen, e))?
+            .into_position(CastlingMode::Standard)
+            .map_err(|e| anyhow!("invalid position '{}': {}", fen, e))?,
+        None => Chess::default(),
+    };
+
+    let mut fens = vec![Fen::from_position(&position, EnPassantMode::Legal).to_string()];
+    let mut moves = Vec::new();
+    for node in pgn::parse_movetext(&game.movetext).moves {
+        let m = SanPlus::from_ascii(node.san.as_bytes())
+            .ok()
+            .and_then(|san| san.san.to_move(&position).ok())
+            .ok_or_else(|| anyhow!("illegal move '{}'", node.san))?;
+        moves.push(SanPlus::from_move_and_play_unchecked(&mut position, m).to_string());
+        fens.push(Fen::from_position(&position, EnPassantMode::Legal).to_string());
+    }
+
+    Ok(GameLine { title, fens, moves })
+}
+
+// Winning chances for White: 1 is a certain win, -1 a certain loss
+pub fn winning_chances(score: Score) -> f64 {
+    // Mates count like a large material edge, shorter mates a little more
+    let cp = match score {
+        Score::Centipawns(cp) => cp.clamp(-1000, 1000),
+        Score::Mate(moves) => moves.signum() * (21 - moves.abs().min(10)) * 100,
+    };
+    2.0 / (1.0 + (-0.00368208 * cp as f64).exp()) - 1.0
+}
+
+// The `max` moves losing the most winning chances, at least a mistake's worth, in game order.
+// `analyses[i]` evaluates `line.fens[i]`; moves into a finished game are never critical,
+// nor are moves the engine itself prefers
+pub fn critical_moves(line: &GameLine, analyses: &[Option<Analysis>], max: usize) -> Vec<CriticalMove> {
+    let mut critical: Vec<CriticalMove> = (0..line.moves.len())
+        .filter_map(|index| {
+            let analysis = analyses.get(index)?.as_ref()?;
+            if analysis.pv.first() == line.moves.get(index) {
+                return None;
+            }
+            let before = analysis.score;
+            let after = analyses.get(index + 1)?.as_ref()?.score;
+
+            // Positions alternate sides; measure the loss for the player who moved
+            let white_moved = line.fens[index].split_whitespace().nth(1) != Some("b");
+            let swing = winning_chances(before) - winning_chances(after);
+            let loss = if white_moved { swing } else { -swing };
+            (loss >= MISTAKE).then_some(CriticalMove { index, loss, before, after })
+        })
+        .collect();
+
+    critical.sort_by(|a, b| b.loss.total_cmp(&a.loss));
+    critical.truncate(max);
+    critical.sort_by_key(|critical| critical.index);
+    critical
+}
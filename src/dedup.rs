@@ -0,0 +1,39 @@
+// Identical images stored once: printpdf writes a new image object for every use, so the same
+// position (a repeated diagram, a solution miniature of a later exercise) or the logo on every
+// page would otherwise be embedded again each time
+
+use printpdf::lopdf::{Document, Object, ObjectId};
+use std::collections::HashMap;
+use crate::linearize::renumber;
+
+// Points all references to a duplicate image at its first copy; the duplicates are left
+// unreferenced for prune_objects
+pub fn share_identical_images(document: &mut Document) {
+    let mut first_copies: HashMap<[u8; 16], ObjectId> = HashMap::new();
+    let mut duplicates: HashMap<ObjectId, ObjectId> = HashMap::new();
+    for (&id, object) in &document.objects {
+        let Object::Stream(stream) = object else { continue };
+        if stream.dict.get(b"Subtype").and_then(Object::as_name_str).ok() != Some("Image") {
+            continue;
+        }
+        let dictionary = format!("{:?}", stream.dict);
+        let mut digest = md5::Context::new();
+        digest.consume(&dictionary);
+        digest.consume(&stream.content);
+        let first = *first_copies.entry(digest.compute().0).or_insert(id);
+        // The digest only finds candidates; the copies have to match completely
+        if first != id {
+            if let Ok(Object::Stream(original)) = document.get_object(first) {
+                if format!("{:?}", original.dict) == dictionary && original.content == stream.content {
+                    duplicates.insert(id, first);
+                }
+            }
+        }
+    }
+    if duplicates.is_empty() {
+        return;
+    }
+    for object in document.objects.values_mut() {
+        renumber(object, &duplicates);
+    }
+}
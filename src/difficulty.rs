@@ -0,0 +1,150 @@
+// Puzzle difficulty on a 1-5 scale, from the Lichess puzzle database rating when the
+// position is found there, otherwise estimated from the solution
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use shakmaty::{fen::{Epd, Fen}, san::SanPlus, uci::UciMove, Chess, EnPassantMode, Position, Role};
+
+use crate::chess960;
+
+pub const MAX_LEVEL: u32 = 5;
+
+// Theme tags that describe the puzzle's length, phase or source rather than its idea
+const GENERIC_THEMES: [&str; 15] = [
+    "advantage", "crushing", "equality", "mate", "long", "short", "veryLong", "oneMove",
+    "opening", "middlegame", "endgame", "master", "masterVsMaster", "superGM", "playerGames",
+];
+
+struct Puzzle {
+    rating: u32,
+    themes: Vec<String>,
+}
+
+// Ratings and themes from the Lichess puzzle database (lichess_db_puzzle.csv)
+pub struct PuzzleDb {
+    // EPD of the puzzle position -> puzzle
+    puzzles: HashMap<String, Puzzle>,
+}
+
+impl PuzzleDb {
+    // Columns: PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,... The FEN
+    // is the position before the opponent's move that sets up the puzzle, so that move is played first
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path).map_err(|e| anyhow!("Cannot read puzzle database '{}': {}", path, e))?;
+        let mut puzzles = HashMap::new();
+
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split(',').collect();
+            let (Some(fen), Some(moves), Some(rating)) = (fields.get(1), fields.get(2), fields.get(3)) else {
+                continue;
+            };
+            let Ok(rating) = rating.parse() else {
+                continue; // Header row
+            };
+            let Some(mut position) = setup_position(fen, false) else {
+                continue;
+            };
+            let setup_move = moves
+                .split_whitespace()
+                .next()
+                .and_then(|uci| UciMove::from_ascii(uci.as_bytes()).ok())
+                .and_then(|uci| uci.to_move(&position).ok());
+            let themes = fields.get(7).map_or(Vec::new(), |themes| themes.split_whitespace().map(str::to_string).collect());
+            if let Some(m) = setup_move {
+                position.play_unchecked(m);
+                puzzles.insert(position_key(&position), Puzzle { rating, themes });
+            }
+        }
+
+        Ok(PuzzleDb { puzzles })
+    }
+
+    fn puzzle(&self, fen: &str, chess960: bool) -> Option<&Puzzle> {
+        let position = setup_position(fen, chess960)?;
+        self.puzzles.get(&position_key(&position))
+    }
+
+    pub fn rating(&self, fen: &str, chess960: bool) -> Option<u32> {
+        self.puzzle(fen, chess960).map(|puzzle| puzzle.rating)
+    }
+
+    // The puzzle's first specific theme, readable: "backRankMate" becomes "Back rank mate"
+    pub fn theme(&self, fen: &str, chess960: bool) -> Option<String> {
+        let puzzle = self.puzzle(fen, chess960)?;
+        let theme = puzzle.themes.iter().find(|theme| !GENERIC_THEMES.contains(&theme.as_str()))?;
+        Some(theme_name(theme))
+    }
+
+    pub fn len(&self) -> usize {
+        self.puzzles.len()
+    }
+}
+
+// "mateIn2" -> "Mate in 2", other camelCase keys split into lowercase words
+fn theme_name(key: &str) -> String {
+    if let Some(moves) = key.strip_prefix("mateIn") {
+        return format!("Mate in {}", moves);
+    }
+    let mut name = String::new();
+    for c in key.chars() {
+        if name.is_empty() {
+            name.push(c.to_ascii_uppercase());
+        } else if c.is_ascii_uppercase() {
+            name.push(' ');
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+// Lowest ratings of levels 2 to 5: 400 point steps, below 1400 is level 1
+pub const LEVEL_THRESHOLDS: [u32; 4] = [1400, 1800, 2200, 2600];
+
+// Level 1 plus one for every threshold the rating reaches
+pub fn level_from_rating(rating: u32, thresholds: &[u32; 4]) -> u32 {
+    1 + thresholds.iter().filter(|&&threshold| rating >= threshold).count() as u32
+}
+
+// One level per move of the solution, plus one each for a sacrifice and for a
+// quiet first move (neither check nor capture)
+pub fn estimate(fen: &str, chess960: bool, first_move: &str, solution_moves: u32) -> Option<u32> {
+    let mut position = setup_position(fen, chess960)?;
+    let m = SanPlus::from_ascii(first_move.as_bytes()).ok()?.san.to_move(&position).ok()?;
+    let mover = position.turn();
+    let captured = m.capture().map_or(0, piece_value);
+    let moved = piece_value(m.role());
+    let quiet = !m.is_capture();
+
+    position.play_unchecked(m);
+    let board = position.board();
+    let attacked = board.attacks_to(m.to(), !mover, board.occupied()).any();
+    let sacrifice = attacked && moved > captured;
+    let quiet = quiet && !position.is_check();
+
+    Some((solution_moves.max(1) + sacrifice as u32 + quiet as u32).clamp(1, MAX_LEVEL))
+}
+
+// Material value in pawns; the king cannot be sacrificed
+fn piece_value(role: Role) -> u32 {
+    match role {
+        Role::Pawn => 1,
+        Role::Knight | Role::Bishop => 3,
+        Role::Rook => 5,
+        Role::Queen => 9,
+        Role::King => 0,
+    }
+}
+
+fn setup_position(fen: &str, chess960: bool) -> Option<Chess> {
+    Fen::from_ascii(fen.as_bytes())
+        .ok()?
+        .into_position(chess960::castling_mode(chess960))
+        .ok()
+}
+
+fn position_key(position: &Chess) -> String {
+    Epd::from_position(position, EnPassantMode::Legal).to_string()
+}
@@ -0,0 +1,339 @@
+// The PDF document: pages of diagrams, solutions and schedules in the order page_plan gives,
+// then the post-processing steps that printpdf cannot do itself
+
+use crate::{BOARD_DESC_GAP, BOARD_IMAGE_SIZE, BOARD_SIZE, ChessPosition, DESC_HEIGHT, GRID_CELL_HEIGHT, GRID_CELL_WIDTH, GRID_MAX_SCALE, MARGIN_BOTTOM, MARGIN_LEFT, MARGIN_RIGHT, MARGIN_TOP, Options, PdfOptions, Progress, RenderError, SCHEDULE_ROW_HEIGHT, SCHEDULE_TOP, SOLUTION_BOTTOM, SOLUTION_COLUMN_GAP, SOLUTION_DIAGRAM_SIZE, SOLUTION_LINE_HEIGHT, SOLUTION_TOP, SolutionsMode, StudyData, attach, bleed, cmyk, dedup, encrypt, form, images, labels, linearize, metadata, notes, outline, pdfa, reproducible, subset};
+use crate::board::{BoardCache, draft_board_rgb_data, draw_chess_board};
+use crate::page::{draw_info_page, draw_page_header, draw_review_schedule};
+use crate::solutions::{answer_field, draw_solution_diagram, draw_upside_down_solutions, draw_verso_solutions, layout_solution_pages, solution_columns, solution_diagram_lines, solution_note};
+use anyhow::{Result, anyhow};
+use printpdf::*;
+use rayon::prelude::*;
+
+pub fn create_pdf(study_data: &StudyData, filename: &str, options: &Options, boards: &BoardCache) -> Result<(), RenderError> {
+    write_pdf(study_data, filename, options, boards).map_err(RenderError::from)
+}
+
+fn write_pdf(study_data: &StudyData, filename: &str, options: &Options, boards: &BoardCache) -> Result<()> {
+    let (doc, page1, layer1) = PdfDocument::new(&study_data.name, Mm(options.pdf.page_width()), Mm(options.pdf.page_height()), "Layer 1");
+    
+    // Add fonts for text rendering; PDF/A needs them embedded
+    let (font, font_bold) = if options.pdfa {
+        let (regular, bold) = pdfa::serif_fonts()?;
+        (doc.add_external_font(regular.as_slice())?, doc.add_external_font(bold.as_slice())?)
+    } else {
+        (doc.add_builtin_font(printpdf::BuiltinFont::TimesRoman)?, doc.add_builtin_font(printpdf::BuiltinFont::TimesBold)?)
+    };
+    let mut current_layer = doc.get_page(page1).get_layer(layer1);
+    
+    let positions = &study_data.positions;
+    let pages = diagram_page_ranges(positions, options);
+    let diagram_page_count = pages.len();
+    let solution_pages = layout_solution_pages(positions, options);
+    let schedule_rows = schedule_rows(options);
+    let schedule_page_count = if options.review_schedule.is_some() { positions.len().div_ceil(schedule_rows) } else { 0 };
+    let info_page_count = usize::from(options.info_page);
+    let (sections, page_count) = page_plan(study_data, options);
+    
+    // Raw image data is deflated page by page and kept out of printpdf's document
+    let images = images::ImageStore::default();
+    
+    let mut notes = Vec::new();
+    let mut fields = Vec::new();
+    for (page, range) in pages.into_iter().enumerate() {
+        if page > 0 {
+            let (page_id, layer_id) = doc.add_page(Mm(options.pdf.page_width()), Mm(options.pdf.page_height()), "Layer 1");
+            current_layer = doc.get_page(page_id).get_layer(layer_id);
+        }
+        
+        let page_number = if options.pdf.solutions() == Some(SolutionsMode::Verso) { 2 * page + 1 } else { page + 1 };
+        let page_positions = &positions[range];
+        let title = running_header(study_data, page_positions, options);
+        draw_page_header(&current_layer, title, page_number, page_count, &font, &images, options)?;
+        
+        // Rasterizing the boards takes most of the time; a page's boards are rendered on all
+        // cores, and their pixels are dropped once the page is drawn
+        let board_images = page_positions
+            .par_iter()
+            .map(|pos| if options.draft { Ok(draft_board_rgb_data(options.pdf.theme())) } else { boards.get(pos, options) })
+            .collect::<Result<Vec<_>>>()?;
+        
+        for (i, (pos, board)) in page_positions.iter().zip(board_images).enumerate() {
+            let (x, y) = grid_cell(i, &options.pdf);
+            let text_bottom = draw_chess_board(&current_layer, x, y, pos, board, &font, &images, options)?;
+            if options.fillable {
+                fields.push(answer_field(page, x, y, text_bottom, pos, options));
+            }
+            if options.pdf.solutions() == Some(SolutionsMode::Popup) {
+                notes.extend(solution_note(page, x, y, pos, options));
+            }
+        }
+        
+        if options.pdf.solutions() == Some(SolutionsMode::UpsideDown) {
+            draw_upside_down_solutions(&current_layer, page_positions, &font, options);
+        }
+        options.report(Progress::RenderedPage { page: page_number, pages: page_count });
+        
+        // The back of the page answers each diagram in the cell behind it
+        if options.pdf.solutions() == Some(SolutionsMode::Verso) {
+            let (page_id, layer_id) = doc.add_page(Mm(options.pdf.page_width()), Mm(options.pdf.page_height()), "Layer 1");
+            current_layer = doc.get_page(page_id).get_layer(layer_id);
+            draw_page_header(&current_layer, title, 2 * page + 2, page_count, &font, &images, options)?;
+            current_layer.use_text("Solutions", 14.0, Mm(MARGIN_LEFT), Mm(options.pdf.page_height() - 35.0), &font_bold);
+            draw_verso_solutions(&current_layer, page_positions, &font, &font_bold, options);
+            options.report(Progress::RenderedPage { page: 2 * page + 2, pages: page_count });
+        }
+    }
+    
+    // Solutions section after the diagrams
+    let (columns_per_page, _) = solution_columns(options);
+    let column_width = (options.pdf.page_width() - MARGIN_LEFT - MARGIN_RIGHT + SOLUTION_COLUMN_GAP) / columns_per_page as f32;
+    for (i, columns) in solution_pages.iter().enumerate() {
+        let (page_id, layer_id) = doc.add_page(Mm(options.pdf.page_width()), Mm(options.pdf.page_height()), "Layer 1");
+        current_layer = doc.get_page(page_id).get_layer(layer_id);
+        draw_page_header(&current_layer, &study_data.name, diagram_page_count + i + 1, page_count, &font, &images, options)?;
+        current_layer.use_text("Solutions", 14.0, Mm(MARGIN_LEFT), Mm(options.pdf.page_height() - 35.0), &font_bold);
+        
+        for (column, blocks) in columns.iter().enumerate() {
+            let x = MARGIN_LEFT + column as f32 * column_width;
+            let mut line_y = options.pdf.page_height() - SOLUTION_TOP;
+            for block in blocks {
+                current_layer.use_text(&block.heading, 11.0, Mm(x), Mm(line_y), &font_bold);
+                line_y -= SOLUTION_LINE_HEIGHT;
+                let text_top = line_y;
+                for line in &block.lines {
+                    current_layer.use_text(line, 11.0, Mm(x), Mm(line_y), &font);
+                    line_y -= SOLUTION_LINE_HEIGHT;
+                }
+                if let Some(diagram) = &block.diagram {
+                    let diagram_x = x + column_width - SOLUTION_COLUMN_GAP - SOLUTION_DIAGRAM_SIZE;
+                    draw_solution_diagram(&current_layer, diagram_x, text_top + 4.0, diagram, boards.get(diagram, options)?, &font, &images, options)?;
+                    line_y = line_y.min(text_top - solution_diagram_lines() as f32 * SOLUTION_LINE_HEIGHT);
+                }
+                line_y -= SOLUTION_LINE_HEIGHT; // Blank line between positions
+            }
+        }
+        options.report(Progress::RenderedPage { page: diagram_page_count + i + 1, pages: page_count });
+    }
+    
+    // Review schedule at the very end
+    if let Some(start) = options.review_schedule {
+        for (i, rows) in positions.chunks(schedule_rows).enumerate() {
+            let (page_id, layer_id) = doc.add_page(Mm(options.pdf.page_width()), Mm(options.pdf.page_height()), "Layer 1");
+            current_layer = doc.get_page(page_id).get_layer(layer_id);
+            let page = page_count - info_page_count - schedule_page_count + i + 1;
+            draw_page_header(&current_layer, &study_data.name, page, page_count, &font, &images, options)?;
+            draw_review_schedule(&current_layer, rows, start, &font, &font_bold, options);
+            options.report(Progress::RenderedPage { page, pages: page_count });
+        }
+    }
+    
+    if options.info_page {
+        let (page_id, layer_id) = doc.add_page(Mm(options.pdf.page_width()), Mm(options.pdf.page_height()), "Layer 1");
+        current_layer = doc.get_page(page_id).get_layer(layer_id);
+        draw_page_header(&current_layer, &study_data.name, page_count, page_count, &font, &images, options)?;
+        draw_info_page(&current_layer, study_data, &font, &font_bold, options);
+        options.report(Progress::RenderedPage { page: page_count, pages: page_count });
+    }
+    
+    // printpdf writes the pages; the bookmark tree is added to the document it produces
+    let mut pdf = printpdf::lopdf::Document::load_mem(&doc.save_to_bytes()?)?;
+    images::fill_in(&mut pdf, images)?;
+    let diagram_pages = if options.pdf.solutions() == Some(SolutionsMode::Verso) { 2 } else { 1 };
+    outline::add_outline(&mut pdf, &[outline_entries(study_data, options, diagram_pages, &sections)])?;
+    metadata::set_metadata(&mut pdf, &document_metadata(study_data, options))?;
+    labels::set_page_labels(&mut pdf)?;
+    notes::add_notes(&mut pdf, &notes)?;
+    form::add_text_fields(&mut pdf, &fields)?;
+    if options.attach_pgn {
+        let stem = std::path::Path::new(filename).file_stem().unwrap_or_default().to_string_lossy();
+        attach::attach_file(&mut pdf, &attach::Attachment {
+            name: &format!("{}.pgn", stem),
+            description: "Source PGN",
+            mime_type: "application/x-chess-pgn",
+            data: study_data.pgn.as_bytes(),
+            modified: options.created,
+        })?;
+    }
+    if options.bleed > 0.0 || options.crop_marks {
+        bleed::add_bleed(&mut pdf, options.bleed, options.crop_marks)?;
+    }
+    if let Some(profile) = &options.cmyk {
+        cmyk::make_cmyk(&mut pdf, profile)?;
+    }
+    if options.pdfa {
+        pdfa::make_conformant(&mut pdf)?;
+    }
+    subset::subset_fonts(&mut pdf)?;
+    dedup::share_identical_images(&mut pdf);
+    // Drops printpdf's empty outline and info dictionary and the duplicate images; the other
+    // streams are deflated here, as printpdf does so only in release builds
+    pdf.prune_objects();
+    pdf.compress();
+    if options.reproducible {
+        reproducible::make_reproducible(&mut pdf);
+    }
+    if options.linearize {
+        linearize::number_objects(&mut pdf)?;
+    }
+    let key = match &options.encryption {
+        Some(encryption) => Some(encrypt::encrypt(&mut pdf, encryption)?),
+        None => None,
+    };
+    let bytes = if options.linearize {
+        linearize::save_to_bytes(&mut pdf, key.as_deref())?
+    } else {
+        let mut bytes = Vec::new();
+        pdf.save_to(&mut bytes)?;
+        bytes
+    };
+    write_output(filename, &bytes, options)?;
+    options.report(Progress::Written { filename: filename.to_string() });
+    Ok(())
+}
+
+// The PDF and the exports go through the storage of the run, so that embedders receive them
+pub fn write_output(path: &str, data: &[u8], options: &Options) -> Result<()> {
+    options.storage.write(path, data).map_err(|e| anyhow!("Cannot write '{}': {}", path, e))
+}
+
+// Title, author (--author, else the study's), a subject counting the exercises and the
+// chapter names as keywords
+fn document_metadata(study_data: &StudyData, options: &Options) -> metadata::Metadata {
+    let mut chapters: Vec<String> = Vec::new();
+    for pos in &study_data.positions {
+        if !pos.chapter_name.is_empty() && !chapters.contains(&pos.chapter_name) {
+            chapters.push(pos.chapter_name.clone());
+        }
+    }
+    let subject = match chapters.len() {
+        0 | 1 => format!("{} chess exercises", study_data.positions.len()),
+        count => format!("{} chess exercises from {} chapters", study_data.positions.len(), count),
+    };
+    metadata::Metadata {
+        title: study_data.name.clone(),
+        author: options.author.clone().unwrap_or_else(|| study_data.author.clone()),
+        subject,
+        keywords: ["chess".to_string(), "exercises".to_string()].into_iter().chain(chapters).collect(),
+        created: options.created,
+        pdfa: options.pdfa,
+    }
+}
+
+// The sections after the diagram pages (and their verso pages) with their first pages, in the
+// order write_pdf adds them, and the document's page count
+pub fn page_plan(study_data: &StudyData, options: &Options) -> (Vec<(&'static str, usize)>, usize) {
+    let positions = &study_data.positions;
+    let diagram_pages = diagram_page_ranges(positions, options).len();
+    let mut page_count = if options.pdf.solutions() == Some(SolutionsMode::Verso) { 2 * diagram_pages } else { diagram_pages };
+    let schedule_pages = if options.review_schedule.is_some() { positions.len().div_ceil(schedule_rows(options)) } else { 0 };
+    let mut sections = Vec::new();
+    for (title, pages) in [
+        ("Solutions", layout_solution_pages(positions, options).len()),
+        ("Review schedule", schedule_pages),
+        ("About the study", usize::from(options.info_page)),
+    ] {
+        if pages > 0 {
+            sections.push((title, page_count + 1));
+            page_count += pages;
+        }
+    }
+    (sections, page_count)
+}
+
+// Exercises per review schedule page
+fn schedule_rows(options: &Options) -> usize {
+    ((options.pdf.page_height() - SCHEDULE_TOP - SOLUTION_BOTTOM) / SCHEDULE_ROW_HEIGHT) as usize
+}
+
+// The positions on each diagram page: as many as the grid holds, and with --section-breaks
+// only those of one chapter
+pub fn diagram_page_ranges(positions: &[ChessPosition], options: &Options) -> Vec<std::ops::Range<usize>> {
+    let mut pages = Vec::new();
+    let mut start = 0;
+    while start < positions.len() {
+        let mut end = (start + options.pdf.boards_per_page()).min(positions.len());
+        if options.section_breaks {
+            end = start + 1 + positions[start + 1..end].iter().take_while(|pos| pos.chapter == positions[start].chapter).count();
+        }
+        pages.push(start..end);
+        start = end;
+    }
+    pages
+}
+
+// The title over a diagram page: its chapter's name with --section-breaks, else the study's
+pub fn running_header<'a>(study_data: &'a StudyData, positions: &'a [ChessPosition], options: &Options) -> &'a str {
+    match positions.first() {
+        Some(pos) if options.section_breaks && !pos.chapter_name.is_empty() => &pos.chapter_name,
+        _ => &study_data.name,
+    }
+}
+
+// Bookmarks: the study, below it each run of exercises from one chapter (the exercises
+// directly when there are no chapters), then the sections after the diagrams
+pub fn outline_entries(study_data: &StudyData, options: &Options, diagram_pages: usize, sections: &[(&str, usize)]) -> outline::Entry {
+    let mut entries: Vec<outline::Entry> = Vec::new();
+    let mut chapter = None;
+    let pages = diagram_page_ranges(&study_data.positions, options);
+    for (i, pos) in study_data.positions.iter().enumerate() {
+        let page = pages.iter().position(|range| range.contains(&i)).unwrap_or(0) * diagram_pages + 1;
+        let exercise = outline::Entry { title: format!("Exercise {}", pos.exercise_label(options)), page, children: Vec::new() };
+        if pos.chapter_name.is_empty() {
+            entries.push(exercise);
+            chapter = None;
+            continue;
+        }
+        if chapter != Some(pos.chapter) {
+            entries.push(outline::Entry { title: pos.chapter_name.clone(), page, children: Vec::new() });
+            chapter = Some(pos.chapter);
+        }
+        if let Some(last) = entries.last_mut() {
+            last.children.push(exercise);
+        }
+    }
+    entries.extend(sections.iter().map(|&(title, page)| outline::Entry { title: title.to_string(), page, children: Vec::new() }));
+    outline::Entry { title: study_data.name.clone(), page: 1, children: entries }
+}
+
+// Board scale that fits the grid's cells, 1 for the default 3x3 grid on A4
+pub fn grid_scale(pdf: &PdfOptions) -> f32 {
+    let (col_width, row_height) = match pdf.template() {
+        Some(template) => (template.grid.width / pdf.columns() as f32, template.grid.height / pdf.rows() as f32),
+        None => (
+            (pdf.page_width() - MARGIN_LEFT - MARGIN_RIGHT) / pdf.columns() as f32,
+            (pdf.page_height() - MARGIN_TOP - 30.0 - MARGIN_BOTTOM) / pdf.rows() as f32,
+        ),
+    };
+    (col_width / GRID_CELL_WIDTH).min(row_height / GRID_CELL_HEIGHT).min(GRID_MAX_SCALE)
+}
+
+// Position (x, y from the top of the page) of the i-th board on a page
+pub fn grid_cell(i: usize, pdf: &PdfOptions) -> (f32, f32) {
+    // A template's cells are filled row by row from its grid region's top, each board and
+    // caption centered in its cell; boards are drawn BOARD_SIZE - BOARD_IMAGE_SIZE below y
+    if let Some(template) = pdf.template() {
+        let (col_width, row_height) = (template.grid.width / pdf.columns() as f32, template.grid.height / pdf.rows() as f32);
+        let (row, col) = (i / pdf.columns(), i % pdf.columns());
+        let scale = grid_scale(pdf);
+        let x = template.grid.x + col as f32 * col_width + (col_width - BOARD_IMAGE_SIZE * scale) / 2.0;
+        let board_top = template.grid.y + row as f32 * row_height + (row_height - GRID_CELL_HEIGHT * scale) / 2.0;
+        return (x, board_top - (BOARD_SIZE - BOARD_IMAGE_SIZE));
+    }
+    let row = pdf.rows() - 1 - (i / pdf.columns()); // Reverse row order: the top row gets the highest number
+    let col = i % pdf.columns();
+    
+    // Add more space before the first row of boards for better layout
+    let adjusted_margin_top = MARGIN_TOP + 30.0; // Add 30mm extra space at top
+    
+    // Layout calculation with balanced margins and adjusted top margin
+    let available_width = pdf.page_width() - MARGIN_LEFT - MARGIN_RIGHT;
+    let available_height = pdf.page_height() - adjusted_margin_top - MARGIN_BOTTOM;
+    let col_width = available_width / pdf.columns() as f32;
+    let row_height = available_height / pdf.rows() as f32;
+    
+    let x = MARGIN_LEFT + (col as f32) * col_width + (col_width - BOARD_SIZE * grid_scale(pdf)) / 2.0;
+    // Simplify Y calculation and add explicit top spacing
+    let top_spacing = 40.0; // 40mm from top of page
+    let y = pdf.page_height() - top_spacing - (row as f32) * row_height - (row_height - DESC_HEIGHT - BOARD_DESC_GAP) / 2.0 - BOARD_SIZE;
+    (x, y)
+}
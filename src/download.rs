@@ -0,0 +1,294 @@
+// Getting studies: downloads from Lichess with retries and rate limiting, local PGN files,
+// and --watch
+
+use crate::{DOWNLOAD_JOBS, FetchError, HttpResponse, LICHESS_URL, Network, Options, ParseError, Progress, RATE_LIMIT_PAUSE, RETRY_DELAY, STUDY_EXPORT, StudyData, platform, process_study, study_cache};
+use crate::board::BoardCache;
+use crate::selection::random_seed;
+use crate::study::read_lichess_study;
+use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+// Downloads and reads several studies, up to DOWNLOAD_JOBS at a time; get_with_retries spaces
+// their requests out so as not to strain Lichess. Results are in the order of the IDs
+pub fn fetch_lichess_studies(study_ids: &[String], options: &Options) -> Vec<Result<StudyData>> {
+    let next_study = AtomicUsize::new(0);
+    let (sender, results) = mpsc::channel();
+    
+    std::thread::scope(|scope| {
+        for _ in 0..DOWNLOAD_JOBS.min(study_ids.len()) {
+            let sender = sender.clone();
+            let next_study = &next_study;
+            scope.spawn(move || loop {
+                let index = next_study.fetch_add(1, Ordering::SeqCst);
+                let Some(study_id) = study_ids.get(index) else { break };
+                let _ = sender.send((index, fetch_lichess_study(study_id, options).map_err(anyhow::Error::from)));
+            });
+        }
+    });
+    drop(sender);
+    
+    let mut studies: Vec<(usize, Result<StudyData>)> = results.into_iter().collect();
+    studies.sort_by_key(|(index, _)| *index);
+    studies.into_iter().map(|(_, study_data)| study_data).collect()
+}
+
+pub fn fetch_lichess_study(study_id: &str, options: &Options) -> Result<StudyData, FetchError> {
+    let pgn_file = if is_pgn_file(study_id) {
+        println!("Using PGN file: {}", study_id);
+        study_id.to_string()
+    } else {
+        println!("Using Lichess study ID: {}", study_id);
+        download_study(study_id, options)?
+    };
+    
+    println!("Reading study positions...");
+    let study_data = read_lichess_study(&pgn_file, options).map_err(|e| match e {
+        ParseError::Io(e) if is_pgn_file(study_id) => ParseError::Io(std::io::Error::new(e.kind(), format!("Cannot read '{}': {}", study_id, e))),
+        e => e,
+    })?;
+    println!("Found {} positions in study: {}", study_data.positions.len(), study_data.name);
+    Ok(study_data)
+}
+
+// A study exported to a file, named in place of a study ID and read as it is, without a
+// download (and so also with --offline)
+fn is_pgn_file(study_id: &str) -> bool {
+    study_id.to_ascii_lowercase().ends_with(".pgn")
+}
+
+// The study's file in the download cache, else a temporary file, one per study for
+// concurrent downloads. Other instances than Lichess get a directory each, as their study
+// IDs are their own, and so do other export parameters, as a study unchanged since its
+// last download would otherwise keep the parameters it was downloaded with
+fn study_file(study_id: &str, options: &Options) -> (Option<study_cache::CachedStudy>, String) {
+    let cache = study_cache::studies_dir().map(|dir| {
+        let mut dir = match options.api_base.as_str() {
+            LICHESS_URL => dir,
+            api_base => dir.join(api_base.split("://").nth(1).unwrap_or(api_base).replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "_")),
+        };
+        if options.study_export != STUDY_EXPORT {
+            dir = dir.join(options.study_export.query().replace(['=', '&'], "_"));
+        }
+        study_cache::CachedStudy::new(&dir, study_id)
+    });
+    let pgn_file = match &cache {
+        Some(cache) => cache.pgn.to_string_lossy().to_string(),
+        None => {
+            let file_id: String = study_id.chars().filter(char::is_ascii_alphanumeric).collect();
+            std::env::temp_dir().join(format!("lichess_study_{}_{}.pgn", std::process::id(), file_id))
+                .to_string_lossy()
+                .to_string()
+        }
+    };
+    (cache, pgn_file)
+}
+
+// Downloads the study, from the mirrors if need be, or checks that the cache has it with
+// --offline; returns the file it is in
+fn download_study(study_id: &str, options: &Options) -> Result<String, FetchError> {
+    let query = options.study_export.query();
+    let lichess_url = format!("{}/study/{}.pgn?{}", options.api_base, study_id, query);
+    if !options.offline {
+        println!("Downloading from: {}", lichess_url);
+    }
+    let (cache, pgn_file) = study_file(study_id, options);
+    println!("Using file: {}", pgn_file);
+    
+    if options.offline {
+        match &cache {
+            Some(cache) if cache.exists() => println!("Offline, using the cached copy"),
+            Some(cache) => return Err(FetchError::NotCached { study_id: study_id.to_string(), path: cache.pgn.clone() }),
+            None => return Err(FetchError::NoCacheDir { study_id: study_id.to_string() }),
+        }
+    } else {
+        // Download the latest study data from Lichess, else from the mirrors in turn
+        println!("Downloading Lichess study data...");
+        let mut result = download_lichess_study(&lichess_url, &pgn_file, cache.as_ref(), options);
+        for mirror in &options.mirrors {
+            let Err(e) = &result else { break };
+            let mirror_url = format!("{}/study/{}.pgn?{}", mirror, study_id, query);
+            eprintln!("Warning: download of study {} failed ({}), trying mirror {}", study_id, e, mirror_url);
+            result = download_lichess_study(&mirror_url, &pgn_file, cache.as_ref(), options);
+            if result.is_ok() {
+                println!("Study {} downloaded from mirror {}", study_id, mirror);
+            }
+        }
+        result?;
+    }
+    options.report(Progress::Downloaded { study_id: study_id.to_string() });
+    Ok(pgn_file)
+}
+
+// --watch: downloads the studies again every `interval` and remakes the PDFs of those whose
+// PGN changed, until the process is stopped. Each download revalidates the cached copy, so
+// an unchanged study costs a 304 rather than the whole PGN. Failures are reported and
+// checked again next time
+pub fn watch_studies(study_ids: &[String], interval: Duration, options: &Options, boards: &BoardCache) {
+    let mut hashes: Vec<Option<String>> = study_ids
+        .iter()
+        .map(|study_id| {
+            let pgn_file = if is_pgn_file(study_id) { study_id.clone() } else { study_file(study_id, options).1 };
+            options.storage.read(&pgn_file).ok().map(|pgn| content_hash(&pgn))
+        })
+        .collect();
+    loop {
+        println!("Watching {} for changes, next check in {}s (Ctrl+C to stop)", study_ids.join(", "), interval.as_secs());
+        std::thread::sleep(interval);
+        for (study_id, hash) in study_ids.iter().zip(&mut hashes) {
+            match regenerate_if_changed(study_id, hash.as_deref(), options, boards) {
+                Ok(Some(new_hash)) => *hash = Some(new_hash),
+                Ok(None) => println!("Study {} unchanged", study_id),
+                Err(e) => eprintln!("Warning: study {}: {}", study_id, e),
+            }
+        }
+    }
+}
+
+// The new content hash when the study changed and its PDF was made again
+fn regenerate_if_changed(study_id: &str, hash: Option<&str>, options: &Options, boards: &BoardCache) -> Result<Option<String>> {
+    let pgn_file = if is_pgn_file(study_id) { study_id.to_string() } else { download_study(study_id, options)? };
+    let new_hash = content_hash(&options.storage.read(&pgn_file)?);
+    if hash == Some(new_hash.as_str()) {
+        return Ok(None);
+    }
+    println!("Study {} changed, regenerating...", study_id);
+    process_study(read_lichess_study(&pgn_file, options)?, options, boards)?;
+    Ok(Some(new_hash))
+}
+
+fn content_hash(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+// Downloads the study into `filename`, or into the cache, where a copy Lichess reports as
+// unchanged is kept as it is
+fn download_lichess_study(url: &str, filename: &str, cache: Option<&study_cache::CachedStudy>, options: &Options) -> Result<()> {
+    println!("Sending HTTP request to: {}", url);
+    let validators = cache.map(study_cache::CachedStudy::validators).unwrap_or_default();
+    let response = get_with_retries(network(options)?.as_ref(), url, &validators, options)?;
+    
+    if response.status == 304 {
+        println!("Study unchanged since the last download, using the cached copy");
+        return Ok(());
+    }
+    
+    // Check if the response is successful
+    if !response.is_success() {
+        return Err(FetchError::Status(response.status).into());
+    }
+    
+    println!("Got HTTP response, reading content...");
+    // Kept as it comes; read_lichess_study decodes it (pgn_reader)
+    let content = &response.body;
+    
+    // Check if content looks like a valid PGN (should contain study data)
+    let text = String::from_utf8_lossy(content);
+    if text.trim().is_empty() || (!text.contains("[Event") && !text.contains("[StudyName")) {
+        return Err(FetchError::InvalidContent.into());
+    }
+    
+    println!("Downloaded {} bytes, writing to file...", content.len());
+    
+    match cache {
+        Some(cache) => cache.store(content, &response)?,
+        None => options.storage.write(filename, content)?,
+    }
+    println!("File written successfully: {}", filename);
+    
+    Ok(())
+}
+
+// GET that is tried up to `retries` more times after network errors, server errors (5xx) and
+// 429 Too Many Requests, waiting RETRY_DELAY, then twice as long each time. Up to half the
+// wait is added at random, so parallel downloads do not retry in step. A 429 waits as long as
+// the server asks instead, and holds back the other downloads too. Other responses, such as
+// 404 for a missing study, are returned at once. Every attempt takes a slot from the --rate
+// schedule
+fn get_with_retries(network: &dyn Network, url: &str, headers: &[(String, String)], options: &Options) -> Result<HttpResponse> {
+    let mut attempt = 0;
+    loop {
+        wait_for_request_slot(options.request_interval);
+        let (error, pause) = match network.get(url, headers) {
+            Ok(response) if !is_retryable(response.status) => return Ok(response),
+            Ok(response) => (format!("HTTP {}", response.status), rate_limit_pause(&response)),
+            // A malformed address or the like fails the same way every time
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => return Err(e.into()),
+            Err(e) => (e.to_string(), None),
+        };
+        if attempt == options.retries {
+            return Err(FetchError::Network { attempts: attempt + 1, reason: error }.into());
+        }
+        let delay = match pause {
+            Some(pause) => {
+                hold_requests(pause);
+                pause
+            }
+            None => {
+                let delay = RETRY_DELAY * 2u32.saturating_pow(attempt);
+                delay + delay.mul_f64((random_seed() % 1000) as f64 / 2000.0)
+            }
+        };
+        eprintln!("Warning: download failed ({}), retrying in {:.1}s", error, delay.as_secs_f64());
+        std::thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+// Request slots shared by all download threads
+struct RequestSchedule {
+    // Start of the next free slot
+    next: Option<Instant>,
+    // No request starts before this, after a 429
+    held_until: Option<Instant>,
+}
+
+static REQUEST_SCHEDULE: Mutex<RequestSchedule> = Mutex::new(RequestSchedule { next: None, held_until: None });
+
+// Reserves the next request slot and waits for it outside the lock; a slot that a 429 put
+// on hold in the meantime is given up for a new one after the hold
+fn wait_for_request_slot(interval: Duration) {
+    loop {
+        let start = {
+            let mut schedule = REQUEST_SCHEDULE.lock().unwrap();
+            let start = [schedule.next, schedule.held_until].into_iter().flatten().fold(Instant::now(), Instant::max);
+            schedule.next = Some(start + interval);
+            start
+        };
+        std::thread::sleep(start.saturating_duration_since(Instant::now()));
+        if REQUEST_SCHEDULE.lock().unwrap().held_until.is_none_or(|held_until| held_until <= start) {
+            return;
+        }
+    }
+}
+
+// No request starts before `pause` has passed
+fn hold_requests(pause: Duration) {
+    let mut schedule = REQUEST_SCHEDULE.lock().unwrap();
+    let resume = Instant::now() + pause;
+    schedule.held_until = Some(schedule.held_until.map_or(resume, |held_until| held_until.max(resume)));
+}
+
+// The wait a 429 response asks for: its Retry-After in seconds, else RATE_LIMIT_PAUSE
+fn rate_limit_pause(response: &HttpResponse) -> Option<Duration> {
+    if response.status != 429 {
+        return None;
+    }
+    let seconds = response.header("Retry-After").and_then(|value| value.trim().parse::<u64>().ok());
+    Some(seconds.map_or(RATE_LIMIT_PAUSE, Duration::from_secs))
+}
+
+// Network for the requests of the run: the library user's, else reqwest with the User-Agent,
+// the --timeout limits and --proxy
+pub fn network(options: &Options) -> Result<Arc<dyn Network>> {
+    match &options.network {
+        Some(network) => Ok(network.clone()),
+        None => platform::http_network(&options.user_agent, options.timeout, options.proxy.as_deref()),
+    }
+}
+
+// Server errors (5xx) and 429 Too Many Requests
+fn is_retryable(status: u16) -> bool {
+    (500..600).contains(&status) || status == 429
+}
@@ -0,0 +1,55 @@
+// Compact ECO opening book bundled with the binary (assets/eco.tsv)
+// Openings are keyed by the position they reach, so transpositions are recognized
+
+use std::collections::HashMap;
+
+use shakmaty::{fen::Epd, san::SanPlus, Chess, EnPassantMode, Position};
+
+use crate::pgn;
+
+const ECO_TSV: &str = include_str!("../assets/eco.tsv");
+
+pub struct OpeningBook {
+    // EPD of the opening position -> "C50 Italian Game"
+    positions: HashMap<String, String>,
+}
+
+impl OpeningBook {
+    pub fn load() -> Self {
+        let mut positions = HashMap::new();
+
+        // Columns: eco, name, pgn (first line is the header)
+        for line in ECO_TSV.lines().skip(1) {
+            let mut fields = line.split('\t');
+            let (Some(eco), Some(name), Some(moves)) = (fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+
+            let mut position = Chess::default();
+            let mut legal = true;
+            for node in pgn::parse_movetext(moves).moves {
+                match SanPlus::from_ascii(node.san.as_bytes()).ok().and_then(|san| san.san.to_move(&position).ok()) {
+                    Some(m) => position.play_unchecked(m),
+                    None => {
+                        legal = false;
+                        break;
+                    }
+                }
+            }
+
+            if legal {
+                positions.insert(position_key(&position), format!("{} {}", eco, name));
+            }
+        }
+
+        OpeningBook { positions }
+    }
+
+    pub fn lookup<P: Position>(&self, position: &P) -> Option<&str> {
+        self.positions.get(&position_key(position)).map(String::as_str)
+    }
+}
+
+fn position_key<P: Position>(position: &P) -> String {
+    Epd::from_position(position, EnPassantMode::Legal).to_string()
+}
@@ -0,0 +1,219 @@
+// Password protection (--encrypt): the standard security handler with 128-bit RC4
+// (revision 3), which every viewer opens; lopdf itself can only decrypt
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::{Document, Object, ObjectId, StringFormat, dictionary};
+
+// Padding of passwords to 32 bytes, from the PDF specification
+const PADDING: [u8; 32] = [
+    0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41, 0x64, 0x00, 0x4E, 0x56, 0xFF, 0xFA, 0x01, 0x08,
+    0x2E, 0x2E, 0x00, 0xB6, 0xD0, 0x68, 0x3E, 0x80, 0x2F, 0x0C, 0xA9, 0xFE, 0x64, 0x53, 0x69, 0x7A,
+];
+
+const KEY_LENGTH: usize = 16;
+
+// Permission bits (1-based as in the specification) granted to readers without the owner
+// password: printing (3, and 12 for full quality), copying text (5), filling forms (9) and
+// extraction for accessibility (10); modifying, annotating and assembling stay reserved
+const PRINT_BITS: [u32; 2] = [3, 12];
+const GRANTED_BITS: [u32; 3] = [5, 9, 10];
+
+#[derive(Clone)]
+pub struct Encryption {
+    // Needed to change the document or its permissions
+    pub owner_password: String,
+    // Needed to open the document; empty opens it without asking
+    pub user_password: String,
+    pub allow_printing: bool,
+}
+
+// Passwords are PDFDocEncoding, which only agrees with UTF-8 for ASCII
+pub fn check_password(password: &str) -> Result<()> {
+    if !password.is_ascii() {
+        return Err(anyhow!("PDF passwords are limited to ASCII characters"));
+    }
+    Ok(())
+}
+
+// Encrypts every string and stream of the document (compressed beforehand, as encrypted
+// data no longer compresses) and adds the encryption dictionary; returns the file key
+pub fn encrypt(document: &mut Document, encryption: &Encryption) -> Result<Vec<u8>> {
+    let file_id = match document.trailer.get(b"ID").and_then(Object::as_array).map(|id| id.first().map(Object::as_str)) {
+        Ok(Some(Ok(file_id))) => file_id.to_vec(),
+        _ => return Err(anyhow!("The PDF has no file identifier to encrypt with")),
+    };
+    let permissions = permissions(encryption.allow_printing);
+    let owner_entry = owner_entry(&encryption.owner_password, &encryption.user_password);
+    let key = file_key(&encryption.user_password, &owner_entry, permissions, &file_id);
+    let user_entry = user_entry(&key, &file_id);
+
+    for (&id, object) in document.objects.iter_mut() {
+        encrypt_object(&key, id, object);
+    }
+    let dictionary = document.add_object(dictionary! {
+        "Filter" => "Standard",
+        "V" => 2,
+        "R" => 3,
+        "Length" => (KEY_LENGTH * 8) as i64,
+        "O" => Object::String(owner_entry, StringFormat::Hexadecimal),
+        "U" => Object::String(user_entry, StringFormat::Hexadecimal),
+        "P" => permissions as i32 as i64,
+    });
+    document.trailer.set("Encrypt", dictionary);
+    Ok(key)
+}
+
+// Encrypts data written outside of lopdf as part of object `id`
+pub fn encrypt_data(key: &[u8], id: ObjectId, data: &[u8]) -> Vec<u8> {
+    rc4(&object_key(key, id), data)
+}
+
+// The P entry: reserved bits 7-8 and 13-32 set, bits 1-2 clear
+fn permissions(allow_printing: bool) -> u32 {
+    let printing: &[u32] = if allow_printing { &PRINT_BITS } else { &[] };
+    GRANTED_BITS.iter().chain(printing).fold(0xFFFF_F0C0, |bits, bit| bits | 1 << (bit - 1))
+}
+
+fn padded(password: &str) -> Vec<u8> {
+    password.bytes().chain(PADDING).take(32).collect()
+}
+
+// Algorithm 3: the user password encrypted with a key derived from the owner password
+fn owner_entry(owner_password: &str, user_password: &str) -> Vec<u8> {
+    let mut digest = md5::compute(padded(owner_password)).0;
+    for _ in 0..50 {
+        digest = md5::compute(digest).0;
+    }
+    rc4_rounds(&digest[..KEY_LENGTH], padded(user_password))
+}
+
+// Algorithm 2: the file key from the user password and the other entries
+fn file_key(user_password: &str, owner_entry: &[u8], permissions: u32, file_id: &[u8]) -> Vec<u8> {
+    let mut context = md5::Context::new();
+    context.consume(padded(user_password));
+    context.consume(owner_entry);
+    context.consume(permissions.to_le_bytes());
+    context.consume(file_id);
+    let mut digest = context.compute().0;
+    for _ in 0..50 {
+        digest = md5::compute(&digest[..KEY_LENGTH]).0;
+    }
+    digest[..KEY_LENGTH].to_vec()
+}
+
+// Algorithm 5: lets viewers check a user password; the last 16 bytes are arbitrary
+fn user_entry(key: &[u8], file_id: &[u8]) -> Vec<u8> {
+    let mut context = md5::Context::new();
+    context.consume(PADDING);
+    context.consume(file_id);
+    let mut entry = rc4_rounds(key, context.compute().to_vec());
+    entry.extend(&PADDING[..16]);
+    entry
+}
+
+// RC4 with the key, then 19 more times with each key byte XORed with the round number
+fn rc4_rounds(key: &[u8], data: Vec<u8>) -> Vec<u8> {
+    (0..20u8).fold(data, |data, round| {
+        let round_key: Vec<u8> = key.iter().map(|byte| byte ^ round).collect();
+        rc4(&round_key, &data)
+    })
+}
+
+// Strings and streams are encrypted with a key for their object; the strings may sit
+// anywhere inside it
+fn encrypt_object(key: &[u8], id: ObjectId, object: &mut Object) {
+    encrypt_strings(&object_key(key, id), object);
+}
+
+// Algorithm 1: the whole digest for a 128-bit file key
+fn object_key(key: &[u8], id: ObjectId) -> [u8; 16] {
+    let mut object_key = key.to_vec();
+    object_key.extend(&id.0.to_le_bytes()[..3]);
+    object_key.extend(&id.1.to_le_bytes()[..2]);
+    md5::compute(object_key).0
+}
+
+fn encrypt_strings(key: &[u8], object: &mut Object) {
+    match object {
+        Object::String(bytes, _) => *bytes = rc4(key, bytes),
+        Object::Array(items) => items.iter_mut().for_each(|item| encrypt_strings(key, item)),
+        Object::Dictionary(dictionary) => dictionary.iter_mut().for_each(|(_, value)| encrypt_strings(key, value)),
+        Object::Stream(stream) => {
+            stream.dict.iter_mut().for_each(|(_, value)| encrypt_strings(key, value));
+            stream.content = rc4(key, &stream.content);
+        }
+        _ => {}
+    }
+}
+
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut state: Vec<u8> = (0..=255).collect();
+    let mut j = 0u8;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+    let (mut i, mut j) = (0u8, 0u8);
+    data.iter()
+        .map(|byte| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(state[i as usize]);
+            state.swap(i as usize, j as usize);
+            byte ^ state[state[i as usize].wrapping_add(state[j as usize]) as usize]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printpdf::lopdf::Stream;
+
+    #[test]
+    fn rc4_known_answers() {
+        // The test vectors of the RC4 article on Wikipedia
+        assert_eq!(rc4(b"Key", b"Plaintext"), [0xBB, 0xF3, 0x16, 0xE8, 0xD9, 0x40, 0xAF, 0x0A, 0xD3]);
+        assert_eq!(rc4(b"Wiki", b"pedia"), [0x10, 0x21, 0xBF, 0x04, 0x20]);
+        assert_eq!(rc4(b"Secret", b"Attack at dawn"), [0x45, 0xA0, 0x1F, 0x64, 0x5F, 0xC3, 0x5B, 0x38, 0x35, 0x52, 0x54, 0x4B, 0x9B, 0xF5]);
+    }
+
+    #[test]
+    fn permission_entries() {
+        assert_eq!(permissions(true) as i32, -1068);
+        assert_eq!(permissions(false) as i32, -3120);
+    }
+
+    #[test]
+    fn owner_entry_gives_back_the_user_password() {
+        // Algorithm 7, as viewers check an owner password: the rounds undone in reverse order
+        let mut digest = md5::compute(padded("owner")).0;
+        for _ in 0..50 {
+            digest = md5::compute(digest).0;
+        }
+        let user_password = (0..20u8).rev().fold(owner_entry("owner", "user"), |data, round| {
+            let round_key: Vec<u8> = digest[..KEY_LENGTH].iter().map(|byte| byte ^ round).collect();
+            rc4(&round_key, &data)
+        });
+        assert_eq!(user_password, padded("user"));
+    }
+
+    #[test]
+    fn lopdf_decrypts_with_the_user_password() {
+        let mut document = Document::with_version("1.5");
+        let text = document.add_object(Object::string_literal("Chapter 1"));
+        let content = document.add_object(Stream::new(dictionary! {}, b"BT (Hello) Tj ET".to_vec()));
+        let file_id = Object::String(b"0123456789abcdef".to_vec(), StringFormat::Hexadecimal);
+        document.trailer.set("ID", vec![file_id.clone(), file_id]);
+        let encryption = Encryption { owner_password: "owner".to_string(), user_password: "user".to_string(), allow_printing: true };
+        encrypt(&mut document, &encryption).unwrap();
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes).unwrap();
+        assert!(!bytes.windows(5).any(|window| window == b"Hello"));
+
+        assert!(Document::load_mem(&bytes).unwrap().decrypt("owner").is_err());
+        let mut document = Document::load_mem(&bytes).unwrap();
+        document.decrypt("user").unwrap();
+        assert_eq!(document.get_object(text).unwrap().as_str().unwrap(), b"Chapter 1");
+        assert_eq!(document.get_object(content).unwrap().as_stream().unwrap().content, b"BT (Hello) Tj ET");
+    }
+}
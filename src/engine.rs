@@ -0,0 +1,268 @@
+// UCI engine integration (e.g. Stockfish)
+// Each worker thread drives its own engine process; engine output is read on a
+// separate thread so every wait can time out instead of hanging on a stuck engine
+
+use anyhow::{Result, anyhow};
+use shakmaty::{fen::Fen, san::SanPlus, uci::UciMove, Chess, Position};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use crate::chess960;
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub path: String,
+    pub depth: u32,
+    pub timeout: Duration,
+    pub jobs: usize,
+}
+
+// Evaluation from White's point of view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    // Moves to mate; negative when Black mates
+    Mate(i32),
+}
+
+impl Score {
+    pub fn display(&self) -> String {
+        match self {
+            Score::Centipawns(cp) => format!("{:+.2}", *cp as f32 / 100.0),
+            Score::Mate(moves) => format!("#{}", moves),
+        }
+    }
+
+    // A score as PGN [%eval] commands give it, in pawns or as mate: "0.35", "-1.2", "#-3";
+    // Lichess appends the depth after a comma
+    pub fn parse(text: &str) -> Option<Score> {
+        let value = text.split(',').next()?.trim();
+        match value.strip_prefix('#') {
+            Some(moves) => moves.parse().ok().map(Score::Mate),
+            None => value.parse::<f32>().ok().filter(|pawns| pawns.is_finite()).map(|pawns| Score::Centipawns((pawns * 100.0).round() as i32)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub score: Score,
+    pub depth: u32,
+    // Principal variation in SAN, starting with the best move
+    pub pv: Vec<String>,
+}
+
+// A position to analyse: FEN and whether it uses Chess960 castling
+pub struct EngineJob {
+    pub fen: String,
+    pub chess960: bool,
+}
+
+struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    fn start(path: &str, timeout: Duration) -> Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start engine '{}': {}", path, e))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| anyhow!("Engine stdin unavailable"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("Engine stdout unavailable"))?;
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Engine { child, stdin, lines };
+        engine.send("uci")?;
+        engine.wait_for("uciok", timeout)?;
+        engine.send("setoption name Threads value 1")?;
+        engine.send("isready")?;
+        engine.wait_for("readyok", timeout)?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    // Collect output lines until one starts with `prefix`
+    fn wait_for(&self, prefix: &str, timeout: Duration) -> Result<Vec<String>> {
+        let deadline = Instant::now() + timeout;
+        let mut received = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = self
+                .lines
+                .recv_timeout(remaining)
+                .map_err(|_| anyhow!("Engine did not answer '{}' in time", prefix))?;
+            let done = line.starts_with(prefix);
+            received.push(line);
+            if done {
+                return Ok(received);
+            }
+        }
+    }
+
+    fn analyse(&mut self, job: &EngineJob, depth: u32, timeout: Duration) -> Result<Analysis> {
+        self.send(&format!("setoption name UCI_Chess960 value {}", job.chess960))?;
+        self.send("ucinewgame")?;
+        self.send(&format!("position fen {}", job.fen))?;
+        self.send(&format!("go depth {}", depth))?;
+
+        // On timeout stop the search and use what the engine found so far
+        let output = match self.wait_for("bestmove", timeout) {
+            Ok(output) => output,
+            Err(_) => {
+                self.send("stop")?;
+                self.wait_for("bestmove", Duration::from_secs(5))?
+            }
+        };
+
+        parse_analysis(&output, job)
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Use the last "info ... score ..." line before bestmove
+fn parse_analysis(output: &[String], job: &EngineJob) -> Result<Analysis> {
+    let info = output
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info") && line.contains(" score ") && line.contains(" pv "))
+        .ok_or_else(|| anyhow!("Engine returned no evaluation"))?;
+
+    let words: Vec<&str> = info.split_whitespace().collect();
+    let value_after = |key: &str| words.iter().position(|w| *w == key).and_then(|i| words.get(i + 1));
+
+    let depth = value_after("depth").and_then(|d| d.parse().ok()).unwrap_or(0);
+    let score_index = words.iter().position(|w| *w == "score").ok_or_else(|| anyhow!("Missing score"))?;
+    let value: i32 = words
+        .get(score_index + 2)
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid score in '{}'", info))?;
+
+    // Engines report from the side to move; convert to White's point of view
+    let sign = if job.fen.split_whitespace().nth(1) == Some("b") { -1 } else { 1 };
+    let score = match words.get(score_index + 1) {
+        Some(&"mate") => Score::Mate(sign * value),
+        _ => Score::Centipawns(sign * value),
+    };
+
+    let pv_index = words.iter().position(|w| *w == "pv").unwrap_or(words.len());
+    let uci_moves: Vec<&str> = words[pv_index + 1..].to_vec();
+    let pv = uci_to_san(&job.fen, job.chess960, &uci_moves);
+    if pv.is_empty() {
+        return Err(anyhow!("Engine returned no legal best move"));
+    }
+
+    Ok(Analysis { score, depth, pv })
+}
+
+fn setup_position(fen: &str, chess960: bool) -> Option<Chess> {
+    Fen::from_ascii(fen.as_bytes())
+        .ok()
+        .and_then(|fen| fen.into_position(chess960::castling_mode(chess960)).ok())
+}
+
+// Mate and stalemate positions have no best move to look for
+fn is_game_over(job: &EngineJob) -> bool {
+    setup_position(&job.fen, job.chess960).is_some_and(|position| position.is_game_over())
+}
+
+// Convert a line of UCI moves to SAN, stopping at the first illegal move
+pub fn uci_to_san(fen: &str, chess960: bool, uci_moves: &[&str]) -> Vec<String> {
+    let mut san_moves = Vec::new();
+    let mut position = match setup_position(fen, chess960) {
+        Some(position) => position,
+        None => return san_moves,
+    };
+
+    for uci in uci_moves {
+        let m = match UciMove::from_ascii(uci.as_bytes()).ok().and_then(|uci| uci.to_move(&position).ok()) {
+            Some(m) => m,
+            None => break,
+        };
+        san_moves.push(SanPlus::from_move_and_play_unchecked(&mut position, m).to_string());
+    }
+
+    san_moves
+}
+
+// Analyse all jobs with up to `config.jobs` engine processes in parallel.
+// Results are in job order; failed analyses are reported and left as None
+pub fn analyse_all(config: &EngineConfig, jobs: &[EngineJob]) -> Result<Vec<Option<Analysis>>> {
+    let next_job = AtomicUsize::new(0);
+    let (sender, results) = mpsc::channel();
+    let workers = config.jobs.clamp(1, jobs.len().max(1));
+
+    // Start every engine up front so a wrong path fails once, before any work
+    let engines = (0..workers)
+        .map(|_| Engine::start(&config.path, config.timeout))
+        .collect::<Result<Vec<_>>>()?;
+
+    std::thread::scope(|scope| {
+        for mut engine in engines {
+            let sender = sender.clone();
+            let next_job = &next_job;
+            scope.spawn(move || {
+                loop {
+                    let index = next_job.fetch_add(1, Ordering::SeqCst);
+                    let Some(job) = jobs.get(index) else { break };
+                    if is_game_over(job) {
+                        let _ = sender.send((index, None));
+                        continue;
+                    }
+                    let result = engine.analyse(job, config.depth, config.timeout);
+                    let failed = result.is_err();
+                    if let Err(e) = &result {
+                        eprintln!("Warning: engine analysis failed for '{}': {}", job.fen, e);
+                    }
+                    let _ = sender.send((index, result.ok()));
+
+                    // A failed search can leave the engine out of sync; start a fresh one
+                    if failed {
+                        engine = match Engine::start(&config.path, config.timeout) {
+                            Ok(engine) => engine,
+                            Err(e) => {
+                                eprintln!("Warning: {}", e);
+                                return;
+                            }
+                        };
+                    }
+                }
+            });
+        }
+    });
+    drop(sender);
+
+    let mut analyses = vec![None; jobs.len()];
+    for (index, analysis) in results {
+        analyses[index] = analysis;
+    }
+    Ok(analyses)
+}
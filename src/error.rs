@@ -0,0 +1,110 @@
+// Errors of the public entry points, by subsystem: fetch_lichess_study gives FetchError,
+// read_lichess_study ParseError and create_pdf RenderError, so programs embedding fen2pdf can
+// match on what went wrong. Inside, the crate works with anyhow; the failures below are raised
+// as these types where they happen and recovered from the anyhow error at the entry point
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    // The server answered, but not with the study (404 for a missing or private one)
+    #[error("Study not found: HTTP {0}")]
+    Status(u16),
+    // Network errors, server errors or rate limits on every attempt
+    #[error("Download failed after {attempts} attempts: {reason}")]
+    Network { attempts: u32, reason: String },
+    #[error("Study not found or invalid: no chess positions detected")]
+    InvalidContent,
+    // --offline and the study was never downloaded
+    #[error("Study {study_id} is not in the download cache ({}); run once without --offline to download it", path.display())]
+    NotCached { study_id: String, path: PathBuf },
+    #[error("Study {study_id} cannot be read offline: no cache directory (set XDG_CACHE_HOME or HOME)")]
+    NoCacheDir { study_id: String },
+    // No answer at all (e.g. a malformed address), or the download could not be stored
+    #[error(transparent)]
+    Io(std::io::Error),
+    // The study was downloaded but could not be read
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for FetchError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<FetchError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<ParseError>() {
+            Ok(error) => return FetchError::Parse(error),
+            Err(error) => error,
+        };
+        match error.downcast::<std::io::Error>() {
+            Ok(error) => FetchError::Io(error),
+            Err(error) => FetchError::Other(error),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    // The PGN file cannot be opened or read
+    #[error(transparent)]
+    Io(std::io::Error),
+    // The PGN has bytes its encoding (UTF-8, or the one given with --encoding) does not allow
+    #[error("The PGN is not valid {encoding}; give its encoding with --encoding, e.g. windows-1252")]
+    Encoding { encoding: &'static str },
+    // No chapter has both a name and a FEN, and no move is marked as a diagram
+    #[error("No chess positions found in the study")]
+    NoPositions,
+}
+
+// pgn::DecodeReader raises encoding errors as I/O errors of kind InvalidData
+impl From<std::io::Error> for ParseError {
+    fn from(error: std::io::Error) -> Self {
+        match error.get_ref().and_then(|inner| inner.downcast_ref::<ParseError>()) {
+            Some(ParseError::Encoding { encoding }) => ParseError::Encoding { encoding },
+            _ => ParseError::Io(error),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RenderError {
+    // --pdfa embeds a serif font, and none of these is installed
+    #[error("--pdfa needs one of these fonts installed: {families}")]
+    MissingFont { families: String },
+    #[error("PNG loading failed for piece '{piece}'")]
+    PieceImage { piece: char },
+    // Writing the PDF or reading one of its inputs (logo, ICC profile, --append file)
+    #[error(transparent)]
+    Io(std::io::Error),
+    // printpdf or lopdf could not build or write the document
+    #[error("PDF error: {0}")]
+    Pdf(String),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for RenderError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<RenderError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<std::io::Error>() {
+            Ok(error) => return RenderError::Io(error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<printpdf::lopdf::Error>() {
+            Ok(error) => return RenderError::Pdf(error.to_string()),
+            Err(error) => error,
+        };
+        match error.downcast::<printpdf::Error>() {
+            Ok(error) => RenderError::Pdf(error.to_string()),
+            Err(error) => RenderError::Other(error),
+        }
+    }
+}
@@ -0,0 +1,387 @@
+// Exports other than PDF (--export): HTML, PNG, SVG, Markdown, LaTeX, JSON, CSV and Anki
+
+use crate::{ChessPosition, Options, PNG_CAPTION_LINE_PX, SVG_FONT_FAMILY, StudyData, html};
+use crate::board::{board_svg, parse_fen, pixmap_to_rgb, png_bytes, render_board};
+use crate::document::write_output;
+use crate::solutions::{numbered_moves, solution_lines};
+use anyhow::{Result, anyhow};
+
+// Anki cards mirror the diagrams: board and labels on the front, caption and solution on the back
+#[cfg(not(target_arch = "wasm32"))]
+pub fn export_anki(study_data: &StudyData, path: &str, options: &Options) -> Result<()> {
+    let mut cards = Vec::new();
+    for pos in &study_data.positions {
+        let (width, height, rgb_data) = render_board(pos, options)?;
+        let mut front = vec![pos.exercise_mark(options)];
+        front.extend(pos.caption_labels(options));
+        
+        let mut back = vec![format!("<b>{}</b>", html::escape_html(&pos.caption()))];
+        back.extend(export_solution_lines(pos, options).iter().map(|line| html::escape_html(line)));
+        
+        cards.push(crate::anki::Card {
+            image: png_bytes(width, height, &rgb_data)?,
+            front: front.iter().map(|line| html::escape_html(line)).collect::<Vec<_>>().join("<br>"),
+            back: back.join("<br>"),
+        });
+    }
+    write_output(path, &crate::anki::deck_bytes(&study_data.name, &cards)?, options)
+}
+
+// The deck is an SQLite database, which the WebAssembly build does without
+#[cfg(target_arch = "wasm32")]
+pub fn export_anki(_study_data: &StudyData, _path: &str, _options: &Options) -> Result<()> {
+    Err(anyhow!("--export anki is not available in the WebAssembly build"))
+}
+
+// The PDF only prints the chapter's moves with --solutions, exports always include them
+fn export_solution_lines(pos: &ChessPosition, options: &Options) -> Vec<String> {
+    let mut lines = Vec::new();
+    if options.pdf.solutions().is_none() && !pos.solution.is_empty() {
+        lines.push(format!("Solution: {}", numbered_moves(&pos.fen, &pos.solution)));
+    }
+    lines.extend(solution_lines(pos, options));
+    lines
+}
+
+// A web page of the diagrams with the solutions folded under each one; hidden captions
+// move into the solution as in the PDF
+pub fn export_html(study_data: &StudyData, path: &str, options: &Options) -> Result<()> {
+    let diagrams: Vec<html::Diagram> = study_data
+        .positions
+        .iter()
+        .map(|pos| {
+            let caption = if options.hide_captions { String::new() } else { pos.caption() };
+            let mut solution = export_solution_lines(pos, options);
+            if options.hide_captions && !pos.caption().is_empty() {
+                solution.insert(0, pos.caption());
+            }
+            html::Diagram {
+                svg: board_svg(pos, options, true),
+                heading: pos.numbered(options, &caption),
+                labels: pos.caption_labels(options),
+                solution,
+            }
+        })
+        .collect();
+    write_output(path, html::page(&study_data.name, &diagrams).as_bytes(), options)
+}
+
+// Standalone board images named after their number and description ("007-back-rank.png"),
+// with the caption lines rendered below the board when asked for
+pub fn export_png(study_data: &StudyData, dir: &str, options: &Options) -> Result<()> {
+    options.storage.create_dir_all(dir).map_err(|e| anyhow!("Cannot create directory '{}': {}", dir, e))?;
+    let fontdb = options.png_captions.then(|| {
+        let mut fontdb = usvg::fontdb::Database::new();
+        fontdb.load_system_fonts();
+        if fontdb.is_empty() {
+            eprintln!("Warning: no system fonts found, PNG captions are left out");
+        }
+        fontdb
+    });
+    
+    for pos in &study_data.positions {
+        let (width, mut height, mut rgb_data) = render_board(pos, options)?;
+        if let Some(fontdb) = &fontdb {
+            let mut lines = vec![pos.numbered(options, &pos.caption())];
+            lines.extend(pos.caption_labels(options));
+            let (caption_height, caption_rgb) = caption_rgb_data(width, &lines, fontdb)?;
+            height += caption_height;
+            rgb_data.extend(caption_rgb);
+        }
+        let path = std::path::Path::new(dir).join(export_file_name(pos, "png"));
+        write_output(&path.to_string_lossy(), &png_bytes(width, height, &rgb_data)?, options)?;
+    }
+    Ok(())
+}
+
+// Markdown notes for wikis and static sites: a heading, the board image and the labels per
+// diagram, and the solutions at the end. The images are written as by --export png into a
+// directory beside the file ("study.md" links to "study-images/001-....png")
+pub fn export_markdown(study_data: &StudyData, path: &str, options: &Options) -> Result<()> {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().map_or("study".into(), |stem| stem.to_string_lossy());
+    let image_dir = format!("{}-images", stem);
+    export_png(study_data, &path.with_file_name(&image_dir).to_string_lossy(), options)?;
+    let image_link = image_dir.replace(' ', "%20");
+    
+    let mut markdown = format!("# {}\n", escape_markdown(&study_data.name));
+    let mut solutions = String::new();
+    for pos in &study_data.positions {
+        let caption = if options.hide_captions { String::new() } else { pos.caption() };
+        let heading = escape_markdown(&pos.numbered(options, &caption));
+        markdown.push_str(&format!("\n## {}\n\n![{}]({}/{})\n", heading, heading, image_link, export_file_name(pos, "png")));
+        for label in pos.caption_labels(options) {
+            markdown.push_str(&format!("\n*{}*\n", escape_markdown(&label)));
+        }
+        
+        let mut lines = export_solution_lines(pos, options);
+        if options.hide_captions && !pos.caption().is_empty() {
+            lines.insert(0, pos.caption());
+        }
+        if !lines.is_empty() {
+            let lines: Vec<String> = lines.iter().map(|line| escape_markdown(line)).collect();
+            let mark = pos.exercise_mark(options);
+            let mark = if mark.is_empty() { mark } else { format!("**{}** ", escape_markdown(&mark)) };
+            solutions.push_str(&format!("\n{}{}\n", mark, lines.join("  \n")));
+        }
+    }
+    if !solutions.is_empty() {
+        markdown.push_str(&format!("\n## Solutions\n{}", solutions));
+    }
+    write_output(&path.to_string_lossy(), markdown.as_bytes(), options)
+}
+
+// Backslash-escape the characters Markdown would read as formatting
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Editable LaTeX source for club magazines: xskak's \chessboard for every diagram, three
+// to a row with number, caption and labels below, and the solutions at the end
+pub fn export_latex(study_data: &StudyData, path: &str, options: &Options) -> Result<()> {
+    let mut tex = String::from("\\documentclass[a4paper]{article}\n\\usepackage[utf8]{inputenc}\n\\usepackage[T1]{fontenc}\n\\usepackage{xskak}\n\\usepackage[margin=2cm]{geometry}\n\\setlength{\\parindent}{0pt}\n\n\\begin{document}\n\n");
+    tex.push_str(&format!("\\section*{{{}}}\n\n", escape_latex(&study_data.name)));
+    
+    let mut solutions = String::new();
+    for (i, pos) in study_data.positions.iter().enumerate() {
+        let caption = if options.hide_captions { String::new() } else { pos.caption() };
+        let mut lines = vec![pos.numbered(options, &caption)];
+        lines.extend(pos.caption_labels(options));
+        let lines: Vec<String> = lines.iter().map(|line| escape_latex(line)).collect();
+        tex.push_str(&format!(
+            "\\begin{{minipage}}[t]{{0.32\\textwidth}}\n\\centering\n\\chessboard[setfen={}, boardfontsize=15pt, labelfontsize=6pt, showmover=false{}]\\\\\n\\small {}\n\\end{{minipage}}",
+            latex_board(&pos.fen),
+            if pos.flipped(options) { ", inverse" } else { "" },
+            lines.join("\\\\ \\footnotesize ")
+        ));
+        // Three diagrams to a row
+        tex.push_str(if i % 3 == 2 { "\n\n\\bigskip\n\n" } else { "\\hfill\n" });
+        
+        let mut answer = export_solution_lines(pos, options);
+        if options.hide_captions && !pos.caption().is_empty() {
+            answer.insert(0, pos.caption());
+        }
+        if !answer.is_empty() {
+            let answer: Vec<String> = answer.iter().map(|line| escape_latex(line)).collect();
+            let mark = pos.exercise_mark(options);
+            let mark = if mark.is_empty() { mark } else { format!("\\textbf{{{}}} ", escape_latex(&mark)) };
+            solutions.push_str(&format!("{}{}\n\n", mark, answer.join("\\\\\n")));
+        }
+    }
+    if !study_data.positions.len().is_multiple_of(3) {
+        tex.push_str("\\hspace*{\\fill}\n\n");
+    }
+    if !solutions.is_empty() {
+        tex.push_str(&format!("\\section*{{Solutions}}\n\n{}", solutions));
+    }
+    tex.push_str("\\end{document}\n");
+    write_output(path, tex.as_bytes(), options)
+}
+
+// The piece placement for \chessboard: its FEN parser knows neither crazyhouse pockets nor
+// promotion marks, and the other fields are not needed with showmover=false
+fn latex_board(fen: &str) -> String {
+    let board = parse_fen(fen.split(' ').next().unwrap_or(""));
+    let ranks: Vec<String> = board
+        .iter()
+        .map(|rank| {
+            let mut text = String::new();
+            let mut empty = 0;
+            for &square in rank {
+                if square == ' ' {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                text.push(square);
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            text
+        })
+        .collect();
+    ranks.join("/")
+}
+
+fn escape_latex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The parsed study for other tools: every position with its chapter, FEN, side to move,
+// labels and whatever solutions and analysis this run produced (null when absent)
+pub fn export_json(study_data: &StudyData, path: &str, options: &Options) -> Result<()> {
+    use serde_json::json;
+    
+    let positions: Vec<serde_json::Value> = study_data
+        .positions
+        .iter()
+        .map(|pos| {
+            json!({
+                "number": pos.number,
+                "id": pos.exercise_id(),
+                "chapter": { "number": pos.chapter, "name": pos.chapter_name },
+                "description": pos.description,
+                "caption": pos.caption(),
+                "labels": pos.caption_labels(options),
+                "fen": pos.fen,
+                "side_to_move": if pos.black_to_move { "black" } else { "white" },
+                "variant": pos.variant,
+                "chess960_position": pos.start_position,
+                "opening": pos.opening,
+                "theme": pos.theme,
+                "rating": pos.rating,
+                "difficulty": pos.difficulty,
+                "solution": pos.solution,
+                "solution_text": (!pos.solution.is_empty()).then(|| numbered_moves(&pos.fen, &pos.solution)),
+                "annotated_solution": pos.annotated_solution,
+                "game_move": pos.game_move,
+                "mate": pos.mate.as_ref().map(|mate| json!({ "moves": mate.moves, "first_move": mate.first_move })),
+                "eval": pos.eval.map(|score| score.display()),
+                "engine": pos.analysis.as_ref().map(|analysis| json!({
+                    "score": analysis.score.display(),
+                    "depth": analysis.depth,
+                    "pv": analysis.pv,
+                })),
+                "tablebase": pos.tablebase.as_ref().map(|result| json!({
+                    "result": result.display(),
+                    "dtm": result.dtm,
+                    "dtz": result.dtz,
+                    "best_move": result.best_move,
+                })),
+            })
+        })
+        .collect();
+    let document = json!({
+        "generator": format!("fen2pdf {}", env!("CARGO_PKG_VERSION")),
+        "name": study_data.name,
+        "positions": positions,
+        "skipped": study_data.info.skipped.iter().map(|chapter| json!({
+            "chapter": { "number": chapter.chapter, "name": chapter.name },
+            "reason": chapter.reason,
+        })).collect::<Vec<_>>(),
+    });
+    write_output(path, (serde_json::to_string_pretty(&document)? + "\n").as_bytes(), options)
+}
+
+// One row per position for spreadsheets and other training apps (RFC 4180 quoting)
+pub fn export_csv(study_data: &StudyData, path: &str, options: &Options) -> Result<()> {
+    let mut csv = String::from("number,id,chapter,fen,side_to_move,solution\r\n");
+    for pos in &study_data.positions {
+        let fields = [
+            pos.number.to_string(),
+            pos.exercise_id(),
+            pos.chapter_name.clone(),
+            pos.fen.clone(),
+            if pos.black_to_move { "black" } else { "white" }.to_string(),
+            if pos.solution.is_empty() { String::new() } else { numbered_moves(&pos.fen, &pos.solution) },
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    write_output(path, csv.as_bytes(), options)
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// Vector diagrams, named like the PNG images
+pub fn export_svg(study_data: &StudyData, dir: &str, options: &Options) -> Result<()> {
+    options.storage.create_dir_all(dir).map_err(|e| anyhow!("Cannot create directory '{}': {}", dir, e))?;
+    for pos in &study_data.positions {
+        let path = std::path::Path::new(dir).join(export_file_name(pos, "svg"));
+        write_output(&path.to_string_lossy(), board_svg(pos, options, true).as_bytes(), options)?;
+    }
+    Ok(())
+}
+
+// "007-back-rank.png": number and description, so the files sort in exercise order
+fn export_file_name(pos: &ChessPosition, extension: &str) -> String {
+    format!("{:03}-{}.{}", pos.number, file_slug(&pos.description), extension)
+}
+
+// Caption lines rendered as SVG text in a strip as wide as the board: (height, RGB data)
+fn caption_rgb_data(width: u32, lines: &[String], fontdb: &usvg::fontdb::Database) -> Result<(u32, Vec<u8>)> {
+    use usvg::{TreeParsing, TreePostProc};
+    
+    let height = PNG_CAPTION_LINE_PX * lines.len() as u32 + PNG_CAPTION_LINE_PX / 2;
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">", width, height);
+    for (i, line) in lines.iter().enumerate() {
+        // The first line (number and caption) is set larger, like in the PDF
+        let size = if i == 0 { PNG_CAPTION_LINE_PX * 3 / 4 } else { PNG_CAPTION_LINE_PX * 3 / 5 };
+        svg.push_str(&format!(
+            "<text x=\"0\" y=\"{}\" font-family=\"{}\" font-size=\"{}\">{}</text>",
+            PNG_CAPTION_LINE_PX * (i as u32 + 1),
+            SVG_FONT_FAMILY,
+            size,
+            escape_xml(line)
+        ));
+    }
+    svg.push_str("</svg>");
+    
+    let mut tree = usvg::Tree::from_str(&svg, &usvg::Options::default())?;
+    tree.postprocess(usvg::PostProcessingSteps::default(), fontdb);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| anyhow!("Invalid caption size"))?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(&tree, tiny_skia::Transform::identity(), &mut pixmap.as_mut());
+    Ok((height, pixmap_to_rgb(&pixmap)))
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Lowercase words joined by hyphens, for file names; letters of any script are kept
+fn file_slug(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let slug = words.join("-");
+    if slug.is_empty() { "position".to_string() } else { slug.chars().take(60).collect::<String>().trim_end_matches('-').to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_slugs_keep_letters_of_any_script() {
+        assert_eq!(file_slug("Ünïcode Openings"), "ünïcode-openings");
+        assert_eq!(file_slug("Защита Каро-Канн"), "защита-каро-канн");
+        assert_eq!(file_slug("象棋 残局 #1"), "象棋-残局-1");
+        assert_eq!(file_slug("White to move: mate in 2!"), "white-to-move-mate-in-2");
+        assert_eq!(file_slug("?!"), "position");
+        assert_eq!(file_slug(&"ab ".repeat(40)), "ab-".repeat(20).trim_end_matches('-'));
+    }
+}
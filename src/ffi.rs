@@ -0,0 +1,111 @@
+// C interface for chess GUIs embedding diagram sheets, declared in include/fen2pdf.h (made
+// with `cbindgen --config cbindgen.toml --output include/fen2pdf.h`). Each function returns
+// FEN2PDF_OK or one of the error codes and keeps the message for fen2pdf_last_error. Panics
+// are caught rather than unwound into the caller
+
+use crate::platform::OutputStorage;
+use crate::{BoardCache, Options, PASTED_PGN, StudyData};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::sync::Arc;
+
+pub const FEN2PDF_OK: i32 = 0;
+/// An argument is NULL or not UTF-8, or the options are invalid
+pub const FEN2PDF_ERROR_ARGUMENT: i32 = 1;
+/// The study could not be downloaded
+pub const FEN2PDF_ERROR_FETCH: i32 = 2;
+/// The PGN holds no positions
+pub const FEN2PDF_ERROR_PARSE: i32 = 3;
+/// The PDF could not be made
+pub const FEN2PDF_ERROR_RENDER: i32 = 4;
+/// The PDF could not be written to the output path
+pub const FEN2PDF_ERROR_OUTPUT: i32 = 5;
+/// fen2pdf panicked
+pub const FEN2PDF_ERROR_INTERNAL: i32 = 6;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Downloads the Lichess study `study_id` (through the download cache) and writes its PDF to
+/// `output_path`. `options` holds fen2pdf's command line options separated by spaces, e.g.
+/// "--grid 2x3 --solutions appendix", and may be NULL
+///
+/// # Safety
+/// Each argument is NULL or a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn fen2pdf_render_study_to_file(study_id: *const c_char, output_path: *const c_char, options: *const c_char) -> i32 {
+    let (study_id, output_path, options) = unsafe { (text(study_id), text(output_path), text(options)) };
+    call(|| {
+        let (study_id, output_path) = (required(study_id, "study_id")?, required(output_path, "output_path")?);
+        let storage = Arc::new(OutputStorage::default());
+        let options = embedded_options(options, study_id, &storage)?;
+        let study_data = crate::fetch_lichess_study(study_id, &options).map_err(|e| (FEN2PDF_ERROR_FETCH, e.to_string()))?;
+        write_pdf(study_data, output_path, &options, &storage)
+    })
+}
+
+/// Writes the PDF of `fens`, one FEN a line, to `output_path`; `options` as for
+/// fen2pdf_render_study_to_file
+///
+/// # Safety
+/// Each argument is NULL or a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn fen2pdf_render_fens(fens: *const c_char, output_path: *const c_char, options: *const c_char) -> i32 {
+    let (fens, output_path, options) = unsafe { (text(fens), text(output_path), text(options)) };
+    call(|| {
+        let (fens, output_path) = (required(fens, "fens")?, required(output_path, "output_path")?);
+        let storage = Arc::new(OutputStorage::default());
+        storage.written.insert(PASTED_PGN, crate::fen_list_pgn(fens).into_bytes());
+        let options = embedded_options(options, PASTED_PGN, &storage)?;
+        let study_data = crate::read_lichess_study(PASTED_PGN, &options).map_err(|e| (FEN2PDF_ERROR_PARSE, e.to_string()))?;
+        write_pdf(study_data, output_path, &options, &storage)
+    })
+}
+
+/// The message of the last failed call on this thread, or NULL after a successful one. The
+/// string belongs to fen2pdf and stays valid until the next call
+#[no_mangle]
+pub extern "C" fn fen2pdf_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
+
+// A string argument: None for NULL, Some(Err) when it is not UTF-8
+unsafe fn text<'a>(pointer: *const c_char) -> Option<Result<&'a str, std::str::Utf8Error>> {
+    (!pointer.is_null()).then(|| unsafe { CStr::from_ptr(pointer) }.to_str())
+}
+
+fn required<'a>(argument: Option<Result<&'a str, std::str::Utf8Error>>, name: &str) -> Result<&'a str, (i32, String)> {
+    match argument {
+        Some(Ok(value)) => Ok(value),
+        Some(Err(_)) => Err((FEN2PDF_ERROR_ARGUMENT, format!("{} is not UTF-8", name))),
+        None => Err((FEN2PDF_ERROR_ARGUMENT, format!("{} is NULL", name))),
+    }
+}
+
+fn embedded_options(args: Option<Result<&str, std::str::Utf8Error>>, study_id: &str, storage: &Arc<OutputStorage>) -> Result<Options, (i32, String)> {
+    let args = match args {
+        Some(args) => args.map_err(|_| (FEN2PDF_ERROR_ARGUMENT, "options is not UTF-8".to_string()))?,
+        None => "",
+    };
+    crate::embedded_options(args, study_id, storage).map_err(|e| (FEN2PDF_ERROR_ARGUMENT, e.to_string()))
+}
+
+fn write_pdf(study_data: StudyData, output_path: &str, options: &Options, storage: &OutputStorage) -> Result<(), (i32, String)> {
+    let render = |e: anyhow::Error| (FEN2PDF_ERROR_RENDER, e.to_string());
+    let boards = BoardCache::new(options.cache_dir.as_deref()).map_err(render)?;
+    let pdf = crate::pdf_bytes(study_data, options, storage, &boards).map_err(render)?;
+    std::fs::write(output_path, pdf).map_err(|e| (FEN2PDF_ERROR_OUTPUT, format!("Cannot write '{}': {}", output_path, e)))
+}
+
+// Runs `f`, keeps its error message for fen2pdf_last_error and returns its code
+fn call(f: impl FnOnce() -> Result<(), (i32, String)>) -> i32 {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err((FEN2PDF_ERROR_INTERNAL, "fen2pdf panicked".to_string())));
+    let (code, message) = match result {
+        Ok(()) => (FEN2PDF_OK, None),
+        Err((code, message)) => (code, Some(CString::new(message.replace('\0', "")).unwrap_or_default())),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
@@ -0,0 +1,136 @@
+// Fillable answer fields (--fillable): a multiline text field under each diagram, so students
+// working on screen can type their answers into the PDF and send it back
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::{Dictionary, Document, Object, dictionary};
+use std::collections::HashSet;
+use crate::outline::text_string;
+
+// Field flag for text fields that wrap and take line breaks
+const MULTILINE: i64 = 1 << 12;
+
+pub struct Field {
+    // Index of the page, from 0
+    pub page: usize,
+    // Left, bottom, right and top in points
+    pub rect: [f32; 4],
+    // Unique within the document
+    pub name: String,
+    // Shown by viewers when hovering the field
+    pub tooltip: String,
+}
+
+// Adds a text field widget for each field to its page and lists the fields in the document's
+// interactive form. Viewers draw the fields and their text themselves (NeedAppearances)
+pub fn add_text_fields(document: &mut Document, fields: &[Field]) -> Result<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let pages = document.get_pages();
+    let mut references = Vec::new();
+    for field in fields {
+        let page_id = *pages.get(&(field.page as u32 + 1)).ok_or_else(|| anyhow!("No page {} for a form field", field.page + 1))?;
+        let [left, bottom, right, top] = field.rect;
+        let widget = document.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Tx",
+            "Ff" => MULTILINE,
+            "T" => text_string(&field.name),
+            "TU" => text_string(&field.tooltip),
+            "Rect" => vec![left.into(), bottom.into(), right.into(), top.into()],
+            "P" => page_id,
+            // Printed, so that a printed copy shows the answers too
+            "F" => 4,
+            "DA" => Object::string_literal("/Helv 10 Tf 0 g"),
+            "MK" => dictionary! { "BC" => vec![0.6.into()] },
+            "BS" => dictionary! { "W" => 0.5, "S" => "S" },
+        });
+        references.push(Object::Reference(widget));
+
+        let page = document.get_object_mut(page_id).and_then(Object::as_dict_mut)?;
+        if !page.has(b"Annots") {
+            page.set("Annots", Vec::<Object>::new());
+        }
+        page.get_mut(b"Annots").and_then(Object::as_array_mut)?.push(Object::Reference(widget));
+    }
+
+    let font = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let form = document.add_object(dictionary! {
+        "Fields" => references,
+        "NeedAppearances" => true,
+        "DA" => Object::string_literal("/Helv 10 Tf 0 g"),
+        "DR" => dictionary! { "Font" => dictionary! { "Helv" => font } },
+    });
+    document.catalog_mut()?.set("AcroForm", form);
+    Ok(())
+}
+
+// Moves the fields of `added` (a catalog whose objects are already copied into `document`)
+// into the document's form. Fields named like existing ones would share their values, so
+// they get a number appended
+pub fn append_fields(document: &mut Document, added: &Dictionary) -> Result<()> {
+    let Some(added_form) = resolve(document, added.get(b"AcroForm").ok()).cloned() else {
+        return Ok(());
+    };
+    let Ok(added_fields) = added_form.get(b"Fields").and_then(Object::as_array).cloned() else {
+        return Ok(());
+    };
+    let catalog = document.catalog()?;
+    let Some(form) = resolve(document, catalog.get(b"AcroForm").ok()).cloned() else {
+        // The added document's form becomes the document's
+        let form = document.add_object(added_form);
+        document.catalog_mut()?.set("AcroForm", form);
+        return Ok(());
+    };
+    let mut fields = form.get(b"Fields").and_then(Object::as_array).cloned().unwrap_or_default();
+    let mut names: HashSet<Vec<u8>> = fields.iter().filter_map(|field| field_name(document, field)).collect();
+    for field in &added_fields {
+        let Some(name) = field_name(document, field) else { continue };
+        if names.contains(&name) {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let renamed = (2..).map(|number| format!("{} ({})", name, number)).find(|renamed| !names.contains(renamed.as_bytes())).unwrap();
+            if let Ok(id) = field.as_reference() {
+                document.get_dictionary_mut(id)?.set("T", Object::string_literal(renamed.as_str()));
+            }
+            names.insert(renamed.into_bytes());
+        } else {
+            names.insert(name);
+        }
+    }
+    fields.extend(added_fields);
+
+    let mut form = form;
+    form.set("Fields", fields);
+    // The added fields may use fonts only their own form's resources name
+    if !form.has(b"DR") {
+        if let Ok(resources) = added_form.get(b"DR") {
+            form.set("DR", resources.clone());
+        }
+    }
+    match document.catalog()?.get(b"AcroForm") {
+        Ok(Object::Reference(id)) => {
+            let id = *id;
+            document.objects.insert(id, Object::Dictionary(form));
+        }
+        _ => document.catalog_mut()?.set("AcroForm", form),
+    }
+    Ok(())
+}
+
+fn resolve<'a>(document: &'a Document, object: Option<&'a Object>) -> Option<&'a Dictionary> {
+    match object? {
+        Object::Reference(id) => document.get_dictionary(*id).ok(),
+        object => object.as_dict().ok(),
+    }
+}
+
+fn field_name(document: &Document, field: &Object) -> Option<Vec<u8>> {
+    let field = resolve(document, Some(field))?;
+    field.get(b"T").and_then(Object::as_str).ok().map(<[u8]>::to_vec)
+}
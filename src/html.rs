@@ -0,0 +1,54 @@
+// Static HTML export: one self-contained page with the diagrams as inline SVG,
+// their captions and the solutions folded away in <details> elements
+
+const PAGE_CSS: &str = "
+body { font-family: 'Times New Roman', serif; max-width: 60em; margin: 2em auto; padding: 0 1em; color: #111; }
+h1 { font-size: 1.6em; }
+.diagrams { display: grid; grid-template-columns: repeat(auto-fill, minmax(16em, 1fr)); gap: 2em 1.5em; }
+.diagram svg { width: 100%; height: auto; }
+.diagram h2 { font-size: 1.05em; font-weight: normal; margin: 0.3em 0 0.1em; }
+.label { font-size: 0.85em; margin: 0; }
+details { margin-top: 0.4em; font-size: 0.9em; }
+summary { cursor: pointer; color: #555; }
+details p { margin: 0.3em 0; }
+@media print { details { display: none; } .diagram { break-inside: avoid; } }
+";
+
+// One diagram: the board SVG, its heading ("7. Back rank"), smaller label lines and the
+// solution lines shown when unfolded (none leaves out the <details>)
+pub struct Diagram {
+    pub svg: String,
+    pub heading: String,
+    pub labels: Vec<String>,
+    pub solution: Vec<String>,
+}
+
+pub fn page(title: &str, diagrams: &[Diagram]) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>{1}</style>\n</head>\n<body>\n<h1>{0}</h1>\n<div class=\"diagrams\">\n",
+        escape_html(title),
+        PAGE_CSS
+    );
+    for diagram in diagrams {
+        html.push_str("<section class=\"diagram\">\n");
+        html.push_str(diagram.svg.trim());
+        html.push_str(&format!("\n<h2>{}</h2>\n", escape_html(&diagram.heading)));
+        for label in &diagram.labels {
+            html.push_str(&format!("<p class=\"label\">{}</p>\n", escape_html(label)));
+        }
+        if !diagram.solution.is_empty() {
+            html.push_str("<details>\n<summary>Solution</summary>\n");
+            for line in &diagram.solution {
+                html.push_str(&format!("<p>{}</p>\n", escape_html(line)));
+            }
+            html.push_str("</details>\n");
+        }
+        html.push_str("</section>\n");
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
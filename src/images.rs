@@ -0,0 +1,64 @@
+// Image data kept out of printpdf's document: printpdf holds every image's raw pixels until the
+// document is saved, and then writes them once more into the saved bytes. Each raw image is
+// deflated as soon as its page is drawn instead, and printpdf gets a short placeholder that is
+// swapped for the deflated data after the document is loaded, so memory grows with the size of
+// the compressed output rather than with the pixels of all the boards
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::{Dictionary, Document, Object, Stream};
+use std::cell::RefCell;
+
+const PLACEHOLDER_PREFIX: &str = "fen2pdf image ";
+
+// Stream content and whether it is deflated
+type ImageData = (Vec<u8>, bool);
+
+#[derive(Default)]
+pub struct ImageStore {
+    // Deflated data (or the raw data where deflating does not make it smaller), by placeholder
+    images: RefCell<Vec<Option<ImageData>>>,
+}
+
+impl ImageStore {
+    // Deflates the image data and returns the placeholder printpdf stores in its place
+    pub fn placeholder(&self, data: Vec<u8>) -> Vec<u8> {
+        let mut stream = Stream::new(Dictionary::new(), data);
+        // Fails only for streams that have a filter already
+        let _ = stream.compress();
+        let deflated = stream.dict.has(b"Filter");
+        let mut images = self.images.borrow_mut();
+        images.push(Some((stream.content, deflated)));
+        format!("{}{}", PLACEHOLDER_PREFIX, images.len() - 1).into_bytes()
+    }
+}
+
+// Puts the stored data into the image streams of the document printpdf produced
+pub fn fill_in(document: &mut Document, store: ImageStore) -> Result<()> {
+    let mut images = store.images.into_inner();
+    for object in document.objects.values_mut() {
+        let Object::Stream(stream) = object else { continue };
+        if stream.dict.get(b"Subtype").and_then(Object::as_name_str).ok() != Some("Image") {
+            continue;
+        }
+        // Too short for printpdf's release builds to deflate them
+        let Some(index) = std::str::from_utf8(&stream.content)
+            .ok()
+            .and_then(|content| content.strip_prefix(PLACEHOLDER_PREFIX))
+            .and_then(|index| index.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        let (data, deflated) = images
+            .get_mut(index)
+            .and_then(Option::take)
+            .ok_or_else(|| anyhow!("No data for image {}", index))?;
+        stream.dict.remove(b"DecodeParms");
+        if deflated {
+            stream.dict.set("Filter", "FlateDecode");
+        } else {
+            stream.dict.remove(b"Filter");
+        }
+        stream.set_content(data);
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+// Page labels: the page numbers viewers show, kept equal to the printed ones. A generated PDF
+// is numbered from 1; with --append every added sheet starts at 1 again, as printed, while the
+// existing pages keep their labels (roman numerals of a front matter, say)
+
+use anyhow::Result;
+use printpdf::lopdf::{Document, Object, dictionary};
+
+// Decimal labels from 1 for the whole document
+pub fn set_page_labels(document: &mut Document) -> Result<()> {
+    let ranges = vec![(0, Object::Dictionary(dictionary! { "S" => "D" }))];
+    write_ranges(document, ranges)
+}
+
+// The label ranges of a document: the index of each range's first page and its label
+// dictionary, from the flattened number tree
+pub fn label_ranges(document: &Document) -> Vec<(i64, Object)> {
+    let mut ranges = Vec::new();
+    if let Ok(tree) = document.catalog().and_then(|catalog| catalog.get(b"PageLabels")) {
+        collect_ranges(document, tree, &mut ranges);
+    }
+    ranges.sort_by_key(|(page, _)| *page);
+    ranges
+}
+
+fn collect_ranges(document: &Document, node: &Object, ranges: &mut Vec<(i64, Object)>) {
+    let node = match node {
+        Object::Reference(id) => match document.get_object(*id) {
+            Ok(object) => object,
+            Err(_) => return,
+        },
+        node => node,
+    };
+    let Ok(node) = node.as_dict() else { return };
+    if let Ok(numbers) = node.get(b"Nums").and_then(Object::as_array) {
+        for pair in numbers.chunks_exact(2) {
+            if let Ok(page) = pair[0].as_i64() {
+                ranges.push((page, pair[1].clone()));
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            collect_ranges(document, kid, ranges);
+        }
+    }
+}
+
+// Labels the `added` ranges (of pages appended after the first `existing_pages`); existing
+// pages without labels are numbered from 1, as viewers show them without labels
+pub fn append_page_labels(document: &mut Document, existing_pages: usize, added: Vec<(i64, Object)>) -> Result<()> {
+    let mut ranges = label_ranges(document);
+    if ranges.is_empty() && added.is_empty() {
+        return Ok(());
+    }
+    if ranges.first().map(|(page, _)| *page) != Some(0) && existing_pages > 0 {
+        ranges.insert(0, (0, Object::Dictionary(dictionary! { "S" => "D" })));
+    }
+    ranges.retain(|(page, _)| *page < existing_pages as i64);
+    // A sheet without labels of its own is still numbered from 1
+    let added = if added.is_empty() { vec![(0, Object::Dictionary(dictionary! { "S" => "D" }))] } else { added };
+    ranges.extend(added.into_iter().map(|(page, label)| (page + existing_pages as i64, label)));
+    write_ranges(document, ranges)
+}
+
+fn write_ranges(document: &mut Document, ranges: Vec<(i64, Object)>) -> Result<()> {
+    let numbers: Vec<Object> = ranges.into_iter().flat_map(|(page, label)| [Object::Integer(page), label]).collect();
+    document.catalog_mut()?.set("PageLabels", dictionary! { "Nums" => numbers });
+    Ok(())
+}
@@ -0,0 +1,1136 @@
+// fen2pdf as a library: the program in src/main.rs only calls run; parsing and rendering
+// steps are public for the benches, and PdfOptions configures the rendering for library users
+
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod anki;
+mod args;
+mod attach;
+#[cfg(not(target_arch = "wasm32"))]
+mod book;
+mod bleed;
+mod board;
+mod chess960;
+mod cmyk;
+mod critical;
+mod dedup;
+mod difficulty;
+mod document;
+mod download;
+mod eco;
+mod encrypt;
+mod engine;
+mod error;
+mod export;
+#[cfg(not(target_arch = "wasm32"))]
+mod ffi;
+mod form;
+mod html;
+mod images;
+mod labels;
+mod linearize;
+mod mate;
+mod merge;
+mod metadata;
+mod notes;
+mod outline;
+mod page;
+mod pgn;
+mod progress;
+mod pdf_options;
+mod pdfa;
+mod pieces;
+mod platform;
+#[cfg(not(target_arch = "wasm32"))]
+mod preview;
+mod qr;
+mod renderer;
+mod reproducible;
+mod schedule;
+mod selection;
+#[cfg(not(target_arch = "wasm32"))]
+mod server;
+mod shapes;
+mod solutions;
+mod study;
+mod study_cache;
+mod subset;
+mod tablebase;
+mod template;
+#[cfg(not(target_arch = "wasm32"))]
+mod tui;
+mod variant;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+use args::{next_number, next_value, print_usage};
+use board::{board_svg, pixmap_to_rgb};
+use document::{diagram_page_ranges, grid_cell, grid_scale, outline_entries, page_plan, running_header};
+use download::{fetch_lichess_studies, watch_studies};
+use export::{escape_xml, export_anki, export_csv, export_html, export_json, export_latex, export_markdown, export_png, export_svg};
+use page::draw_page_header;
+use selection::{analyse_positions, filter_positions, label_mates, number_within_chapters, order_positions, probe_tablebase, rate_difficulty, shuffle_positions, slice_positions, sort_by_difficulty};
+use solutions::numbered_moves;
+use study::{print_skipped, read_critical_positions};
+
+pub use args::{parse_args, parse_args_with_storage};
+pub use board::{BoardCache, generate_board_rgb_data, parse_fen};
+pub use document::create_pdf;
+pub use download::fetch_lichess_study;
+pub use error::{FetchError, ParseError, RenderError};
+pub use pdf_options::{PageSize, PdfOptions, PdfOptionsBuilder, SolutionsMode, Theme};
+pub use platform::{DiskStorage, HttpResponse, MemoryStorage, Network, Storage};
+pub use progress::Progress;
+pub use renderer::{BoardArtifact, BoardRenderer, RasterRenderer, VectorRenderer};
+pub use study::{fen_list_pgn, read_lichess_study};
+pub use template::{PageTemplate, Region};
+
+#[derive(Debug, Clone)]
+pub struct ChessPosition {
+    number: i32,
+    description: String,
+    fen: String,
+    black_to_move: bool,
+    variant: Option<String>,
+    start_position: Option<u32>,
+    opening: Option<String>,
+    analysis: Option<engine::Analysis>,
+    // Evaluation from the study's [%eval] comment, for --eval-bar without an engine
+    eval: Option<engine::Score>,
+    // Arrows and highlighted squares from the study's [%cal] and [%csl] comments, for --shapes
+    shapes: Vec<shapes::Shape>,
+    mate: Option<mate::Mate>,
+    difficulty: Option<u32>,
+    rating: Option<u32>,
+    tablebase: Option<tablebase::TablebaseResult>,
+    // Move played in the game, for positions taken from --games
+    game_move: Option<String>,
+    // Chapter mainline from this position in SAN (kept with --solutions and --hints)
+    solution: Vec<String>,
+    // Idea of the exercise, from the chapter's Theme tag or the puzzle database
+    theme: Option<String>,
+    // The solution with the chapter's comments, NAGs and variations (--solutions annotated)
+    annotated_solution: Option<String>,
+    // Position after the solution's key move, for --solution-diagrams
+    key_fen: Option<String>,
+    // Chapter (or game) the position comes from: its number in the file, counting from 1, and name
+    chapter: usize,
+    chapter_name: String,
+    // The position's place among its chapter's, counting from 1, for --numbering chapter
+    in_chapter: usize,
+    // Lichess page of the chapter, for --links
+    url: Option<String>,
+}
+
+impl ChessPosition {
+    // A standard chess position without labels or analysis
+    pub fn new(number: i32, description: String, fen: String) -> Self {
+        ChessPosition {
+            number,
+            description,
+            black_to_move: is_black_to_move(&fen),
+            fen,
+            variant: None,
+            start_position: None,
+            opening: None,
+            analysis: None,
+            eval: None,
+            shapes: Vec::new(),
+            mate: None,
+            difficulty: None,
+            rating: None,
+            tablebase: None,
+            game_move: None,
+            solution: Vec::new(),
+            theme: None,
+            annotated_solution: None,
+            key_fen: None,
+            chapter: 0,
+            chapter_name: String::new(),
+            in_chapter: 0,
+            url: None,
+        }
+    }
+    
+    // Variants whose material can be compared against the standard starting set
+    fn has_standard_material(&self) -> bool {
+        matches!(self.variant.as_deref(), None | Some("Chess960"))
+    }
+    
+    fn is_chess960(&self) -> bool {
+        self.variant.as_deref() == Some("Chess960")
+    }
+    
+    // Boards are shown from the side to move unless that is part of the exercise
+    fn flipped(&self, options: &Options) -> bool {
+        self.black_to_move && !options.hide_side_to_move
+    }
+    
+    // Short ID that stays with the exercise when the study is reordered or grows, e.g. "3FA90C":
+    // a hash of the chapter's Lichess ID (the end of its URL; the chapter name in PGNs without
+    // one) and the position, without the move counters
+    fn exercise_id(&self) -> String {
+        let chapter_id = self.url.as_deref().and_then(|url| url.rsplit('/').next()).unwrap_or(&self.chapter_name);
+        let fen = normalize_fen(&self.fen);
+        let position: Vec<&str> = fen.split(' ').take(4).collect();
+        let digest = sha1_smol::Sha1::from(format!("{}\x1f{}", chapter_id, position.join(" "))).digest().to_string();
+        digest[..EXERCISE_ID_LENGTH].to_ascii_uppercase()
+    }
+    
+    // The exercise's number: its place in the PDF (from --number-start), "3.2" for the second
+    // position of chapter 3 with --numbering chapter, or its ID with --numbering id. Bookmarks
+    // and form fields keep the place in the PDF with --numbering none
+    fn exercise_label(&self, options: &Options) -> String {
+        match options.numbering {
+            Numbering::Sequence => (self.number + options.number_start - 1).to_string(),
+            Numbering::Chapter if self.chapter > 0 => format!("{}.{}", self.chapter, self.in_chapter),
+            Numbering::Chapter | Numbering::None => self.number.to_string(),
+            Numbering::Id => self.exercise_id(),
+        }
+    }
+    
+    // The number as printed before captions and solutions, e.g. "Ex. 3.", or nothing with
+    // --numbering none
+    fn exercise_mark(&self, options: &Options) -> String {
+        match options.numbering {
+            Numbering::None => String::new(),
+            _ => format!("{}{}.", options.number_prefix, self.exercise_label(options)),
+        }
+    }
+    
+    // A caption or solution line after the printed number
+    fn numbered(&self, options: &Options, text: &str) -> String {
+        format!("{} {}", self.exercise_mark(options), text).trim().to_string()
+    }
+    
+    // Description printed under the board; a generic one gives way to "Mate in N"
+    fn caption(&self) -> String {
+        match &self.mate {
+            Some(mate) if is_generic_caption(&self.description) => format!("Mate in {}", mate.moves),
+            _ => self.description.clone(),
+        }
+    }
+    
+    // White's prospects for the eval bar: the engine's score, else the study's comment
+    fn evaluation(&self) -> Option<engine::Score> {
+        self.analysis.as_ref().map(|analysis| analysis.score).or(self.eval)
+    }
+    
+    // Forced mate for the side to move, found by the mate search or the engine
+    fn mate_moves(&self) -> Option<u32> {
+        if let Some(mate) = &self.mate {
+            return Some(mate.moves);
+        }
+        match self.analysis.as_ref()?.score {
+            engine::Score::Mate(moves) if (moves > 0) != self.black_to_move => Some(moves.unsigned_abs()),
+            _ => None,
+        }
+    }
+    
+    // What the solver is looking for, e.g. "Mate in 3" or "White wins material"
+    fn hint(&self, options: &Options) -> Option<String> {
+        let side = if self.black_to_move { "Black" } else { "White" };
+        match options.hints? {
+            HintMode::MoveCount => match self.mate_moves() {
+                Some(moves) => Some(format!("Mate in {}", moves)),
+                // Only the solver's own moves count
+                None if !self.solution.is_empty() => match self.solution.len().div_ceil(2) {
+                    1 => Some("Solution in 1 move".to_string()),
+                    moves => Some(format!("Solution in {} moves", moves)),
+                },
+                None => None,
+            },
+            HintMode::Theme => {
+                if let Some(theme) = &self.theme {
+                    return Some(theme.clone());
+                }
+                if let Some(moves) = self.mate_moves() {
+                    return Some(format!("Mate in {}", moves));
+                }
+                if let Some(result) = &self.tablebase {
+                    match result.outcome {
+                        tablebase::Outcome::Win => return Some(format!("{} to play and win", side)),
+                        tablebase::Outcome::Draw => return Some(format!("{} to play and draw", side)),
+                        tablebase::Outcome::Loss => {}
+                    }
+                }
+                // Engine scores are from White's point of view
+                let sign = if self.black_to_move { -1 } else { 1 };
+                match self.analysis.as_ref()?.score {
+                    engine::Score::Centipawns(cp) if sign * cp >= HINT_WINNING_CP => Some(format!("{} wins material", side)),
+                    _ => None,
+                }
+            }
+        }
+    }
+    
+    // Small label lines printed under the description (hint, mate, difficulty, variant, opening)
+    fn caption_labels(&self, options: &Options) -> Vec<String> {
+        let mut labels = Vec::new();
+        // A hint repeating the caption (e.g. "Mate in 2") is left out
+        if let Some(hint) = self.hint(options).filter(|hint| options.hide_captions || *hint != self.caption()) {
+            labels.push(hint);
+        }
+        if let Some(mate) = &self.mate {
+            let label = format!("Mate in {}", mate.moves);
+            if (options.hide_captions || !is_generic_caption(&self.description)) && !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        if let (true, Some(level)) = (options.difficulty, self.difficulty) {
+            match self.rating {
+                Some(rating) => labels.push(format!("Difficulty {}/{} (rating {})", level, difficulty::MAX_LEVEL, rating)),
+                None => labels.push(format!("Difficulty {}/{}", level, difficulty::MAX_LEVEL)),
+            }
+        }
+        match (&self.variant, self.start_position) {
+            (Some(variant), Some(number)) => labels.push(format!("{} #{}", variant, number)),
+            (Some(variant), None) => labels.push(variant.clone()),
+            (None, _) => {}
+        }
+        if let Some(opening) = &self.opening {
+            labels.push(opening.clone());
+        }
+        labels
+    }
+}
+
+// Chapter names that say nothing about the position, e.g. "Chapter 3" or "White to move"
+fn is_generic_caption(description: &str) -> bool {
+    const GENERIC: [&str; 9] = [
+        "", "chapter", "puzzle", "exercise", "position",
+        "white to move", "black to move", "white to play", "black to play",
+    ];
+    let name = description
+        .trim_end_matches(|c: char| c.is_ascii_digit() || c.is_whitespace() || c == '#' || c == '.')
+        .to_lowercase();
+    GENERIC.contains(&name.as_str())
+}
+
+#[derive(Debug, Clone)]
+pub struct StudyData {
+    name: String,
+    // Lichess user who made the study (from [Annotator]); empty when unknown
+    author: String,
+    positions: Vec<ChessPosition>,
+    // The PGN the positions were read from, kept only to be embedded with --attach-pgn
+    pgn: String,
+    info: StudyInfo,
+}
+
+// What the PGN tells about the study as a whole, for --info-page
+#[derive(Debug, Clone, Default)]
+struct StudyInfo {
+    // Every Annotator, in order of appearance
+    authors: Vec<String>,
+    // The study's Lichess page, from the chapter URLs
+    url: Option<String>,
+    // All chapters, those without positions included
+    chapters: usize,
+    // Chapters kept per variant, in order of appearance
+    variants: Vec<(String, usize)>,
+    // Chapters left out because they could not be read, listed at the end of the run
+    skipped: Vec<SkippedChapter>,
+}
+
+// A chapter (or game) left out, with its number in the study and why
+#[derive(Debug, Clone)]
+struct SkippedChapter {
+    chapter: usize,
+    name: String,
+    reason: String,
+}
+
+// Page margins in mm (f32 for printpdf compatibility); the paper size is in PdfOptions
+const MARGIN_LEFT: f32 = 30.0;    // Moderate left margin
+const MARGIN_RIGHT: f32 = 12.0; 
+const MARGIN_TOP: f32 = 35.0;     // Moderate top margin
+const MARGIN_BOTTOM: f32 = 10.0;
+
+// Cell of the default 3x3 grid on A4 that the boards are sized for; boards of other grids and
+// papers are scaled to their cells, up to GRID_MAX_SCALE as the images have a fixed resolution
+const GRID_CELL_WIDTH: f32 = 56.0;
+const GRID_CELL_HEIGHT: f32 = 74.0;
+const GRID_MAX_SCALE: f32 = 1.5;
+
+// Board spacing and sizing - improved layout
+const DESC_HEIGHT: f32 = 12.0;     // More space for larger text
+const BOARD_DESC_GAP: f32 = 10.0;   // Gap between board and description
+
+// Calculate maximum board size for A4: 
+// Width: (210mm - 20mm margins - 2*3mm spacing) / 3 = ~60mm per column
+// Height per row: (297mm - 20mm margins) / 3 = ~85mm per row
+// Board size: 85mm - 8mm text = ~77mm available
+// Let's use ~75mm for comfortable fit
+const BOARD_SIZE: f32 = 75.0;  // Much larger: 75mm x 75mm boards!
+
+// Rendered board image: 600px embedded at printpdf's default 300 DPI (50.8mm on the page)
+const BOARD_IMAGE_PX: u32 = 600;
+const BOARD_IMAGE_SIZE: f32 = 50.8;
+
+// Draft board image (--draft): 20px squares, the pieces are text
+const DRAFT_BOARD_PX: u32 = 160;
+
+// Default quality of JPEG board images; lower shows artifacts around the pieces
+const JPEG_QUALITY: u8 = 85;
+
+// Captured pieces strip: one column of miniatures just right of the board
+const CAPTURED_STRIP_PX: u32 = 28;
+const CAPTURED_STRIP_GAP: f32 = 0.4;
+
+// Solutions section: text blocks between the title and the page number
+const SOLUTION_TOP: f32 = 45.0;      // Below the page's top edge
+const SOLUTION_BOTTOM: f32 = 20.0;
+const SOLUTION_LINE_HEIGHT: f32 = 5.0;
+const SOLUTION_WRAP: usize = 90;   // Characters per line at 11pt
+const SOLUTION_COLUMN_GAP: f32 = 8.0;
+const SOLUTION_COLUMN_WRAP: usize = 44;  // Characters per line in two columns
+
+// Miniature diagrams in the solutions section, to the right of the solution text
+const SOLUTION_DIAGRAM_SIZE: f32 = 28.0;
+const SOLUTION_DIAGRAM_CHARS: usize = 20;      // Text characters the diagram takes from each line
+const SOLUTION_DIAGRAM_MIN_PLIES: usize = 4;   // Shorter solutions are easy to follow without one
+
+// Review schedule: a row per exercise with a checkbox for every review
+const SCHEDULE_TOP: f32 = 55.0;  // Baseline of the first row, below the page's top edge
+const SCHEDULE_ROW_HEIGHT: f32 = 6.0;
+const SCHEDULE_NUMBER_WIDTH: f32 = 18.0;
+
+// Study information page (--info-page): labels in a column of their own, the note wrapped
+const INFO_TOP: f32 = 50.0;
+const INFO_LABEL_WIDTH: f32 = 30.0;
+const INFO_WRAP: usize = 80;
+const INFO_LICENSE_NOTE: &str = "The positions, moves and comments come from the study and belong to its authors. \
+Lichess studies are shared for learning; please name the study and its authors when passing this PDF on, \
+and ask them before publishing it elsewhere.";
+const CHECKBOX_SIZE: f32 = 3.5;
+
+// Most major and minor pieces (both sides together) in an endgame (--only)
+const ENDGAME_MAX_PIECES: usize = 6;
+
+// Times like the PDF, with the serif fonts common on Linux as fallbacks for SVG renderers
+// that do not map the generic family (usvg maps it to Times New Roman only)
+const SVG_FONT_FAMILY: &str = "Times New Roman, Liberation Serif, DejaVu Serif, serif";
+// Square size of SVG boards, and the space for coordinates left of and below them
+const SVG_SQUARE: usize = 45;
+const SVG_COORDINATE_MARGIN: usize = 14;
+
+// Height of a caption line below --export png boards (600 px wide)
+const PNG_CAPTION_LINE_PX: u32 = 40;
+
+// Hex digits of an exercise ID, and the size of the ID beside the number (--ids)
+const EXERCISE_ID_LENGTH: usize = 6;
+const EXERCISE_ID_FONT_SIZE: f32 = 6.0;
+
+// Baseline of a template sidebar's heading below the region's top, and the space between its
+// ruled lines (--template)
+const SIDEBAR_HEADING_HEIGHT: f32 = 5.0;
+const SIDEBAR_LINE_SPACING: f32 = 8.0;
+
+// Checkboxes beside the exercise number (--checkboxes); flashcards get one per review
+const CHECKBOX_GAP: f32 = 1.0;
+const FLASHCARD_CHECKBOXES: usize = 3;
+
+// Star ratings next to the exercise number
+const STAR_RADIUS: f32 = 1.4;
+const STAR_SPACING: f32 = 3.0;
+
+// Upside-down solutions: two columns of small type between the bottom captions and the page number
+const UPSIDE_DOWN_MARGIN: f32 = 20.0;    // Left and right
+const UPSIDE_DOWN_BOTTOM: f32 = 15.0;
+const UPSIDE_DOWN_LINE_HEIGHT: f32 = 2.4;
+const UPSIDE_DOWN_LINES: usize = 8;      // Per column
+const UPSIDE_DOWN_FONT_SIZE: f32 = 6.0;
+const UPSIDE_DOWN_WRAP: usize = 80;      // Characters per column line at 6pt
+
+// Worksheet mode: boards shrink (keeping their top edge) to make room for answer lines
+const WORKSHEET_BOARD_SCALE: f32 = 0.8;
+const WORKSHEET_LINES: usize = 3;          // At most, as many as fit above the next row
+const WORKSHEET_LINE_SPACING: f32 = 5.5;
+const WORKSHEET_BOTTOM: f32 = 21.0;        // Lowest line, below the original board bottom
+
+// QR codes (--qr): boards shrink like worksheet boards to make room for the code at their
+// top right, beside the captured pieces strip if there is one
+const QR_BOARD_SCALE: f32 = 0.8;
+const QR_SIZE: f32 = 10.0;
+const QR_GAP: f32 = 1.5;
+
+// Eval bars (--eval-bar): a slim bar right of the board, as on Lichess; Black's share dark gray
+const EVAL_BAR_WIDTH: f32 = 1.6;
+const EVAL_BAR_GAP: f32 = 0.5;
+const EVAL_BAR_BLACK: f32 = 0.25;
+
+// Logos (--logo): top edge below the page's top, default and largest height (lower ones would
+// reach the review schedule title), widest banner before the height is reduced
+const LOGO_TOP: f32 = 10.0;
+const LOGO_HEIGHT: f32 = 12.0;
+const LOGO_MAX_HEIGHT: f32 = 18.0;
+const LOGO_MAX_WIDTH: f32 = 60.0;
+
+// Resolution printpdf places images at unless told otherwise
+const IMAGE_DPI: f32 = 300.0;
+
+// Watermarks (--watermark): light gray, across most of the page diagonal but not huge for short words
+const WATERMARK_GRAY: f32 = 0.85;
+const WATERMARK_FILL: f32 = 0.75;
+const WATERMARK_MAX_SIZE: f32 = 150.0;
+
+// Where studies are downloaded from by default (--api-base)
+const LICHESS_URL: &str = "https://lichess.org";
+
+// Length of a Lichess study ID, which a --shuffle seed cannot have
+const STUDY_ID_LENGTH: usize = 8;
+
+// Several studies are downloaded this many at a time, and at most this many requests are
+// started per minute by default (--rate)
+const DOWNLOAD_JOBS: usize = 4;
+const REQUESTS_PER_MINUTE: u32 = 60;
+
+// Pause after 429 Too Many Requests without a Retry-After header, as Lichess's API guidelines ask
+const RATE_LIMIT_PAUSE: Duration = Duration::from_secs(60);
+
+// Time between the checks of --watch without --interval
+const WATCH_INTERVAL: Duration = Duration::from_secs(300);
+
+// Port of the page preview (--preview-server)
+const PREVIEW_PORT: u16 = 8000;
+
+// Failed downloads are tried again this many times by default (--retries), the first time
+// after this long and then after twice the previous wait
+const DOWNLOAD_RETRIES: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Longest wait for a connection or for the next data of a response (--timeout)
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Version of the board images in --cache-dir; raised when generate_board_rgb_data draws
+// differently, so that older images are not reused
+const BOARD_CACHE_FORMAT: u32 = 1;
+
+// Boards kept in memory during a run (about 1 MB each); beyond that the oldest are dropped,
+// so a large study does not hold all its boards at once
+const BOARD_CACHE_CAPACITY: usize = 64;
+
+// Engine advantage (centipawns, side to move) hinted as winning material
+const HINT_WINNING_CP: i32 = 200;
+
+// Verso solutions: 9pt text in the grid cell behind each diagram
+const VERSO_LINE_HEIGHT: f32 = 4.0;
+const VERSO_LINES: usize = 15;
+const VERSO_WRAP: usize = 36;
+
+// Popup solutions: the note icon in the margin left of the exercise number
+const NOTE_ICON_SIZE: f32 = 5.0;
+
+#[derive(Clone)]
+pub struct Options {
+    source: Source,
+    // Encoding of PGN files (--encoding); UTF-8, or Windows-1252 where that is not valid
+    encoding: Option<&'static encoding_rs::Encoding>,
+    diagram_keyword: Option<String>,
+    eco: bool,
+    captured: bool,
+    // A bar beside each board filled to White's winning chances, from the engine or [%eval] comments
+    eval_bar: bool,
+    // Diagrams from the study's variations as well as its mainlines (--variation-diagrams)
+    variation_diagrams: bool,
+    // The study's arrows and highlighted squares over the boards, in these colors (--shapes)
+    shapes: Option<shapes::ShapeStyle>,
+    // Link each board to its Lichess chapter (--links), or print its address as a QR code (--qr)
+    links: bool,
+    qr: bool,
+    engine: Option<engine::EngineConfig>,
+    mate_labels: bool,
+    mate_depth: u32,
+    difficulty: bool,
+    sort: Option<SortKey>,
+    split_by: Option<SplitKey>,
+    // Every chapter starts a page, headed by its name instead of the study's (--section-breaks)
+    section_breaks: bool,
+    // Existing PDF the generated pages are added to (--append)
+    append: Option<String>,
+    // Archival PDF/A-2b output (--pdfa)
+    pdfa: bool,
+    // How the board images are stored (--image-compression)
+    image_compression: ImageCompression,
+    // Quick layout preview: coarse boards with letters for pieces, no solutions or extras (--draft)
+    draft: bool,
+    // Grayscale ink-saving output (--print-mode bw)
+    print_mode: PrintMode,
+    // Device CMYK colors and this output profile for print shops (--cmyk)
+    cmyk: Option<cmyk::Profile>,
+    // Embed the source PGN as a file attachment (--attach-pgn)
+    attach_pgn: bool,
+    // Text across every page beneath the content (--watermark)
+    watermark: Option<String>,
+    // Club logo in the page header (--logo)
+    logo: Option<Logo>,
+    // Extra paper around the trimmed page in mm (--bleed) and trim marks (--crop-marks)
+    bleed: f32,
+    crop_marks: bool,
+    // Password protection (--encrypt)
+    encryption: Option<encrypt::Encryption>,
+    // Fast web view: first page first (--linearize)
+    linearize: bool,
+    // Byte-identical output for the same input (--reproducible)
+    reproducible: bool,
+    // Extra statistics (--verbose)
+    verbose: bool,
+    // Board images kept between runs (--cache-dir)
+    cache_dir: Option<String>,
+    // Further download attempts after network errors and server errors (--retries)
+    retries: u32,
+    // Limit for connecting and for each wait for data (--timeout)
+    timeout: Duration,
+    // Proxy for all requests instead of the one from HTTP_PROXY/HTTPS_PROXY (--proxy)
+    proxy: Option<String>,
+    // Lichess or a Lila instance to download studies from, without a trailing slash (--api-base)
+    api_base: String,
+    // Hosts with the same study paths, tried in order when the download from api_base fails (--mirror)
+    mirrors: Vec<String>,
+    // Sent with every request: the program's name and version, and the --contact string
+    user_agent: String,
+    // Query parameters of the study download
+    study_export: StudyExport,
+    // Least time between the starts of two requests (--rate)
+    request_interval: Duration,
+    // No network access: studies only from the download cache (--offline)
+    offline: bool,
+    // Time between checks of the studies for changes after the first PDFs (--watch, --interval)
+    watch: Option<Duration>,
+    // Files of options read with --config, checked for changes by the preview server
+    config_files: Vec<String>,
+    // Port of the live page preview shown instead of writing the PDF (--preview-server, --preview-port)
+    preview: Option<u16>,
+    // Creation time in seconds since 1970: SOURCE_DATE_EPOCH, else now (0 when reproducible)
+    created: u64,
+    // Author in the document properties instead of the study's (--author)
+    author: Option<String>,
+    // Seed of the random diagram order (--shuffle, --seed)
+    shuffle: Option<u64>,
+    // Keep only positions with this side to move (--side)
+    side: Option<shakmaty::Color>,
+    max_pieces: Option<usize>,
+    phase: Option<GamePhase>,
+    // Pieces (FEN letters, repeated for several) every position must have (--must-contain)
+    must_contain: Vec<char>,
+    // File listing chapters by name or number in the order to print them (--order)
+    order: Option<String>,
+    // Keep the chapters the order file leaves out, after the listed ones
+    order_unlisted: bool,
+    // Slice of the selected positions to render (--skip, --take, --max-pages)
+    skip: usize,
+    take: Option<usize>,
+    max_pages: Option<usize>,
+    puzzle_db: Option<String>,
+    tablebase: bool,
+    critical_max: usize,
+    // Paper, grid, board colors and solutions (--page-size, --grid, --theme, --solutions)
+    pdf: PdfOptions,
+    // Draws the boards (--renderer, or Options::with_renderer)
+    renderer: Arc<dyn BoardRenderer>,
+    // Told of each finished phase (Options::with_progress)
+    progress: Option<progress::ProgressCallback>,
+    // Where the PGN is read and the PDF written (the disk, or Options::with_storage)
+    storage: Arc<dyn Storage>,
+    // Downloads through reqwest unless Options::with_network gives another
+    network: Option<Arc<dyn Network>>,
+    worksheet: bool,
+    // Text fields for typed answers under the captions (--fillable)
+    fillable: bool,
+    hide_captions: bool,
+    hide_side_to_move: bool,
+    hints: Option<HintMode>,
+    solution_diagrams: bool,
+    // Start date of the review schedule page (--review-schedule)
+    review_schedule: Option<schedule::Date>,
+    // A last page about the study: authors, address, chapters and variants (--info-page)
+    info_page: bool,
+    // Pages of the book before this study's and in the whole book, to number the pages
+    // through a `fen2pdf book`
+    book_pages: Option<(usize, usize)>,
+    exports: Vec<Export>,
+    // Lowest ratings for 2 to 5 stars next to the exercise number (--stars)
+    star_thresholds: Option<[u32; 4]>,
+    checkboxes: bool,
+    // Exercise IDs as the printed numbers (--numbering id), or in small print beside them (--ids)
+    numbering: Numbering,
+    show_ids: bool,
+    // Number of the first exercise, to continue the numbering of earlier sheets (--number-start)
+    number_start: i32,
+    // Printed before each number, e.g. "Ex. " (--number-prefix)
+    number_prefix: String,
+    // Render the caption below the board in --export png images
+    png_captions: bool,
+}
+
+impl Options {
+    // The same options with other rendering settings, for library users who configure the PDF
+    // through PdfOptions::builder() instead of flags
+    pub fn with_pdf_options(self, pdf: PdfOptions) -> Self {
+        Options { pdf, ..self }
+    }
+
+    // The same options with another board renderer, e.g. one of the library user's own
+    pub fn with_renderer(self, renderer: impl BoardRenderer + 'static) -> Self {
+        Options { renderer: Arc::new(renderer), ..self }
+    }
+
+    // The same options reporting each finished phase to `callback`
+    pub fn with_progress(self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Options { progress: Some(Arc::new(callback)), ..self }
+    }
+
+    // The same options reading and writing their files through `storage`, e.g. a MemoryStorage
+    pub fn with_storage(self, storage: Arc<dyn Storage>) -> Self {
+        Options { storage, ..self }
+    }
+
+    // The same options downloading through `network` instead of reqwest
+    pub fn with_network(self, network: impl Network + 'static) -> Self {
+        Options { network: Some(Arc::new(network)), ..self }
+    }
+
+    // The options for a study of `positions` diagrams, with the grid --grid auto chooses for it
+    fn fitted_to(&self, positions: usize) -> Options {
+        Options { pdf: self.pdf.fitted_to(positions), ..self.clone() }
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
+
+    // Board scale on the page: fitted to the grid's cells, and worksheets and fillable answer
+    // fields shrink boards to fit below them, QR codes to fit beside them
+    fn board_scale(&self) -> f32 {
+        let scale = match (self.worksheet || self.fillable, self.qr) {
+            (true, _) => WORKSHEET_BOARD_SCALE,
+            (false, true) => QR_BOARD_SCALE,
+            (false, false) => 1.0,
+        };
+        scale * grid_scale(&self.pdf)
+    }
+
+    // Checkboxes beside each exercise number; flashcards (answers on the back)
+    // are reviewed repeatedly, so they get one per review
+    fn checkbox_count(&self) -> usize {
+        match (self.checkboxes, self.pdf.solutions()) {
+            (false, _) => 0,
+            (true, Some(SolutionsMode::Verso)) => FLASHCARD_CHECKBOXES,
+            (true, _) => 1,
+        }
+    }
+}
+
+// What the hint under each diagram tells
+#[derive(Clone, Copy, PartialEq)]
+enum HintMode {
+    // "Mate in 3" or the number of moves in the solution
+    MoveCount,
+    // The theme from the chapter or puzzle database, else the result the engine or tablebase expects
+    Theme,
+}
+
+// Additional output formats written along with the PDF (--export <format> <path>)
+#[derive(Clone, Copy, PartialEq)]
+enum ExportFormat {
+    // Anki deck (.apkg): board on the front, caption and solution on the back
+    Anki,
+    // One PNG per diagram in a directory
+    Png,
+    // One SVG per diagram in a directory
+    Svg,
+    // A single web page with inline SVG diagrams and folded solutions
+    Html,
+    // Markdown with links to PNG diagrams written beside it
+    Markdown,
+    // LaTeX source using the xskak/chessboard packages
+    Latex,
+    // The parsed positions and results as JSON
+    Json,
+    // Number, chapter, FEN, side to move and solution per position
+    Csv,
+}
+
+#[derive(Clone)]
+struct Export {
+    format: ExportFormat,
+    path: String,
+}
+
+// Where the positions come from
+#[derive(Clone)]
+enum Source {
+    // One or more study IDs; several are downloaded concurrently and give one PDF each
+    Study(Vec<String>),
+    // Played games searched for critical moments
+    Games(String),
+}
+
+// Game phase for --only, told apart by the pieces left on the board
+#[derive(Clone, Copy, PartialEq)]
+enum GamePhase {
+    Middlegame,
+    Endgame,
+}
+
+// How the board images are stored in the PDF (--image-compression)
+#[derive(Clone, Copy, PartialEq)]
+enum ImageCompression {
+    // Lossless, like all other streams
+    Flate,
+    // Lossy, with the quality from 1 to 100
+    Jpeg(u8),
+}
+
+// What the study export includes, as query parameters of the download (--no-comments,
+// --no-variations, --no-clocks, --orientation)
+#[derive(Clone, Copy, PartialEq)]
+struct StudyExport {
+    // Diagram markers and annotated solutions are read from the comments
+    comments: bool,
+    // Annotated solutions print the variations
+    variations: bool,
+    // [%clk] commands in the comments
+    clocks: bool,
+    // The chapter's board orientation as an Orientation tag
+    orientation: bool,
+}
+
+// Everything fen2pdf reads, asked for explicitly rather than left to the server's defaults
+const STUDY_EXPORT: StudyExport = StudyExport { comments: true, variations: true, clocks: true, orientation: false };
+
+impl StudyExport {
+    fn query(&self) -> String {
+        format!(
+            "comments={}&variations={}&clocks={}&orientation={}",
+            self.comments, self.variations, self.clocks, self.orientation
+        )
+    }
+}
+
+// Image in the header of every page (--logo), ready for rgb_image
+#[derive(Clone)]
+struct Logo {
+    width: u32,
+    height: u32,
+    // Flattened onto white paper, as printpdf cannot store the alpha channel
+    rgb_data: Vec<u8>,
+    position: LogoPosition,
+    // Printed size in mm
+    print_width: f32,
+    print_height: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LogoPosition {
+    TopLeft,
+    TopRight,
+}
+
+// Colors of the output (--print-mode)
+#[derive(Clone, Copy, PartialEq)]
+enum PrintMode {
+    Color,
+    // Grayscale images, a black border around the boards and dashed black rulings instead
+    // of gray ones, which cheap laser printers render faint or dithered
+    Bw,
+}
+
+// How --split-by divides the output into several PDFs
+#[derive(Clone, Copy, PartialEq)]
+enum SplitKey {
+    Chapter,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortKey {
+    Difficulty,
+}
+
+// What the printed exercise numbers are (--numbering)
+#[derive(Clone, Copy, PartialEq)]
+enum Numbering {
+    Sequence,
+    Chapter,
+    Id,
+    None,
+}
+
+// The command line program (src/main.rs): parses the arguments, converts the studies and
+// writes the PDFs and exports
+pub fn run() -> Result<()> {
+    // Parse command line arguments
+    let args: Vec<String> = std::env::args().collect();
+    #[cfg(not(target_arch = "wasm32"))]
+    if args.get(1).is_some_and(|command| command == "serve") {
+        return server::serve(&args[2..]);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if args.get(1).is_some_and(|command| command == "tui") {
+        return tui::run_tui(&args[2..]);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if args.get(1).is_some_and(|command| command == "book") {
+        return book::make_book(&args[2..]);
+    }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            print_usage(&args[0]);
+            std::process::exit(1);
+        }
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(port) = options.preview {
+        return preview::serve_preview(&args, port, &options);
+    }
+    
+    // Shared by all studies and parts of the run
+    let boards = BoardCache::new(options.cache_dir.as_deref())?;
+    match &options.source {
+        Source::Study(study_ids) if study_ids.len() > 1 => {
+            // A study that cannot be downloaded or read does not stop the others
+            let mut created = 0;
+            for (study_id, study_data) in study_ids.iter().zip(fetch_lichess_studies(study_ids, &options)) {
+                match study_data.and_then(|study_data| process_study(study_data, &options, &boards)) {
+                    Ok(()) => created += 1,
+                    Err(e) => eprintln!("Warning: skipping study '{}': {}", study_id, e),
+                }
+            }
+            if created == 0 {
+                return Err(anyhow!("None of the {} studies could be converted", study_ids.len()));
+            }
+            println!("Converted {} of {} studies", created, study_ids.len());
+        }
+        Source::Study(study_ids) => process_study(fetch_lichess_study(&study_ids[0], &options)?, &options, &boards)?,
+        Source::Games(games_file) => process_study(read_critical_positions(games_file, &options)?, &options, &boards)?,
+    }
+    if let (Some(interval), Source::Study(study_ids)) = (options.watch, &options.source) {
+        watch_studies(study_ids, interval, &options, &boards);
+    }
+    if options.verbose {
+        boards.report();
+    }
+    Ok(())
+}
+
+// Selection, analysis and output of one study's positions: the PDF and the exports
+fn process_study(mut study_data: StudyData, options: &Options, boards: &BoardCache) -> Result<()> {
+    prepare_positions(&mut study_data, options)?;
+    write_study(study_data, options, boards)
+}
+
+// The PDF and the exports of positions already prepared
+fn write_study(study_data: StudyData, options: &Options, boards: &BoardCache) -> Result<()> {
+    println!("Creating PDF...");
+    let base_name = pdf_base_name(&study_data.name);
+    let mut generated = Vec::new();
+    match options.split_by {
+        Some(SplitKey::Chapter) => {
+            for (chapter, part) in split_by_chapter(&study_data) {
+                let pdf_filename = format!("{}_Ch{:02}_{}.pdf", base_name, chapter, pdf_base_name(&part.positions[0].chapter_name));
+                create_pdf(&part, &pdf_filename, &options.fitted_to(part.positions.len()), boards)?;
+                println!("Generated PDF: {} with {} chess positions", pdf_filename, part.positions.len());
+                generated.push(pdf_filename);
+            }
+        }
+        None => {
+            let pdf_filename = format!("{}.pdf", base_name);
+            create_pdf(&study_data, &pdf_filename, &options.fitted_to(study_data.positions.len()), boards)?;
+            println!("Generated PDF: {} with {} chess positions", pdf_filename, study_data.positions.len());
+            generated.push(pdf_filename);
+        }
+    }
+    if let Some(target) = &options.append {
+        for pdf_filename in &generated {
+            let pages = merge::append_pdf(target, pdf_filename)?;
+            println!("Appended {} pages of {} to {}", pages, pdf_filename, target);
+        }
+    }
+    
+    for export in &options.exports {
+        match export.format {
+            ExportFormat::Anki => export_anki(&study_data, &export.path, options)?,
+            ExportFormat::Png => export_png(&study_data, &export.path, options)?,
+            ExportFormat::Svg => export_svg(&study_data, &export.path, options)?,
+            ExportFormat::Html => export_html(&study_data, &export.path, options)?,
+            ExportFormat::Markdown => export_markdown(&study_data, &export.path, options)?,
+            ExportFormat::Latex => export_latex(&study_data, &export.path, options)?,
+            ExportFormat::Json => export_json(&study_data, &export.path, options)?,
+            ExportFormat::Csv => export_csv(&study_data, &export.path, options)?,
+        }
+        println!("Exported {}", export.path);
+    }
+    print_skipped(&study_data);
+    Ok(())
+}
+
+// The positions the PDF shows: ordered, filtered, shuffled and sliced as the options say,
+// with the engine, tablebase, mate and difficulty labels they ask for
+fn prepare_positions(study_data: &mut StudyData, options: &Options) -> Result<()> {
+    select_positions(study_data, options)?;
+    label_positions(study_data, options)
+}
+
+// Ordered, filtered, shuffled and sliced, but not yet analysed
+fn select_positions(study_data: &mut StudyData, options: &Options) -> Result<()> {
+    // Counted in study order, so that "3.2" stays the same however the positions are selected
+    if options.numbering == Numbering::Chapter {
+        number_within_chapters(&mut study_data.positions);
+    }
+    if let Some(path) = &options.order {
+        order_positions(&mut study_data.positions, path, options.order_unlisted, options.storage.as_ref())?;
+    }
+    filter_positions(&mut study_data.positions, options)?;
+    
+    // Shuffled before slicing, so that consecutive slices of one seed share no positions,
+    // and before sorting, so positions of equal difficulty end up in random order
+    if let Some(seed) = options.shuffle {
+        shuffle_positions(&mut study_data.positions, seed);
+        println!("Shuffled the positions (--shuffle {})", seed);
+    }
+    slice_positions(&mut study_data.positions, options)
+}
+
+// The engine, tablebase, mate and difficulty labels, and the --sort difficulty order
+fn label_positions(study_data: &mut StudyData, options: &Options) -> Result<()> {
+    if let Some(config) = &options.engine {
+        analyse_positions(&mut study_data.positions, config)?;
+    }
+    
+    if options.tablebase {
+        println!("Querying the endgame tablebase...");
+        probe_tablebase(&mut study_data.positions, options)?;
+    }
+    
+    if options.mate_labels {
+        println!("Looking for forced mates...");
+        label_mates(&mut study_data.positions, options.mate_depth);
+    }
+    
+    let uses_puzzle_db = options.difficulty
+        || options.sort == Some(SortKey::Difficulty)
+        || options.hints == Some(HintMode::Theme)
+        || options.star_thresholds.is_some();
+    let puzzle_db = match &options.puzzle_db {
+        Some(path) if uses_puzzle_db => {
+            let db = difficulty::PuzzleDb::load(path)?;
+            println!("Loaded {} puzzles from {}", db.len(), path);
+            Some(db)
+        }
+        _ => None,
+    };
+    
+    // Ratings and themes given in the chapters take precedence
+    if let Some(db) = &puzzle_db {
+        for pos in study_data.positions.iter_mut().filter(|pos| pos.has_standard_material()) {
+            pos.rating = pos.rating.or_else(|| db.rating(&pos.fen, pos.is_chess960()));
+            if options.hints == Some(HintMode::Theme) && pos.theme.is_none() {
+                pos.theme = db.theme(&pos.fen, pos.is_chess960());
+            }
+        }
+    }
+    
+    if options.difficulty || options.sort == Some(SortKey::Difficulty) {
+        rate_difficulty(&mut study_data.positions, options.mate_depth);
+        if options.sort == Some(SortKey::Difficulty) {
+            sort_by_difficulty(&mut study_data.positions);
+        }
+    }
+    Ok(())
+}
+
+// Name of a pasted PGN in the storage of a run, standing in for the study ID that parse_args
+// asks for
+const PASTED_PGN: &str = "pasted.pgn";
+
+// Options of a run handing its PDF to the caller (the WebAssembly and C entry points): the
+// command line flags in `args`, separated by whitespace, and the study ID or PASTED_PGN
+fn embedded_options(args: &str, study_id: &str, storage: &Arc<platform::OutputStorage>) -> Result<Options> {
+    let argv: Vec<String> = std::iter::once("fen2pdf").chain(args.split_whitespace()).chain([study_id]).map(str::to_string).collect();
+    // Files on the machine are not the caller's to name
+    if argv.iter().any(|arg| arg == "--config") {
+        return Err(anyhow!("--config is not available here"));
+    }
+    let options = parse_args_with_storage(&argv, storage.clone())?;
+    // Only the PDF is handed back
+    if !options.exports.is_empty() {
+        return Err(anyhow!("--export is not available here; only the PDF is returned"));
+    }
+    Ok(options)
+}
+
+// Runs process_study and takes the PDF it wrote out of `storage`
+fn pdf_bytes(study_data: StudyData, options: &Options, storage: &platform::OutputStorage, boards: &BoardCache) -> Result<Vec<u8>> {
+    let filename = format!("{}.pdf", pdf_base_name(&study_data.name));
+    process_study(study_data, options, boards)?;
+    storage.written.take(&filename).ok_or_else(|| anyhow!("No single PDF was written; leave out --split-by"))
+}
+
+// PDF file names follow the titles, spaces replaced with underscores; dots and characters
+// that are not allowed in file names are dropped
+fn pdf_base_name(title: &str) -> String {
+    title.replace(' ', "_").chars().filter(|c| !matches!(c, '.' | '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')).collect()
+}
+
+// One document per chapter (or game), in the order the chapters first appear, titled
+// "Study: Chapter" and numbered from 1; paired with the chapter's number in the study
+fn split_by_chapter(study_data: &StudyData) -> Vec<(usize, StudyData)> {
+    let mut parts: Vec<(usize, StudyData)> = Vec::new();
+    for pos in &study_data.positions {
+        let index = match parts.iter().position(|(chapter, _)| *chapter == pos.chapter) {
+            Some(index) => index,
+            None => {
+                let name = if pos.chapter_name.is_empty() { study_data.name.clone() } else { format!("{}: {}", study_data.name, pos.chapter_name) };
+                parts.push((pos.chapter, StudyData { name, author: study_data.author.clone(), positions: Vec::new(), pgn: study_data.pgn.clone(), info: study_data.info.clone() }));
+                parts.len() - 1
+            }
+        };
+        let positions = &mut parts[index].1.positions;
+        positions.push(ChessPosition { number: positions.len() as i32 + 1, ..pos.clone() });
+    }
+    parts
+}
+
+// Complete board-only or partial FENs with defaults: white to move, no castling,
+// no en passant square, move counters 0 and 1
+fn normalize_fen(fen: &str) -> String {
+    const DEFAULTS: [&str; 6] = ["", "w", "-", "-", "0", "1"];
+    
+    // Underscores are accepted as field separators, as in Lichess URLs
+    let fen = fen.replace('_', " ");
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.is_empty() {
+        return String::new();
+    }
+    while fields.len() < DEFAULTS.len() {
+        fields.push(DEFAULTS[fields.len()]);
+    }
+    fields.join(" ")
+}
+
+fn is_black_to_move(fen: &str) -> bool {
+    fen.split_whitespace().nth(1) == Some("b")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_fens_are_completed() {
+        assert_eq!(normalize_fen("8/8/8/8/8/8/8/K6k"), "8/8/8/8/8/8/8/K6k w - - 0 1");
+        assert_eq!(normalize_fen("8/8/8/8/8/8/8/K6k_b"), "8/8/8/8/8/8/8/K6k b - - 0 1");
+        assert_eq!(normalize_fen("r3k2r/8/8/8/8/8/8/R3K2R_w_KQkq"), "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(normalize_fen("  8/8/8/8/8/8/8/K6k   b  -  -  5  40 "), "8/8/8/8/8/8/8/K6k b - - 5 40");
+        assert_eq!(normalize_fen(""), "");
+        assert_eq!(normalize_fen(" _ "), "");
+    }
+}
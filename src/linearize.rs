@@ -0,0 +1,502 @@
+// Linearized ("fast web view") output (--linearize): the catalog and everything the first
+// page needs come first with their own cross-reference table, followed by the other pages
+// one after another, so that viewers show the first page before the download completes.
+// lopdf cannot write this layout; it serializes the objects and the file is put together here
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::xref::XrefType;
+use printpdf::lopdf::{Document, Object, ObjectId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+
+// Width of the numbers only known once the file is laid out, padded so that the
+// linearization dictionary and first trailer keep their length
+const NUMBER_WIDTH: usize = 10;
+
+// The offsets and lengths in the linearization dictionary
+#[derive(Default)]
+struct Layout {
+    file_length: usize,
+    hint_offset: usize,
+    hint_length: usize,
+    first_page_end: usize,
+    // The whitespace before the first entry of the main cross-reference table
+    main_entries: usize,
+}
+
+// The objects of the document by the part of the linearized file they belong to
+struct Sections {
+    // The catalog, the encryption dictionary and, when shown on opening, the outline
+    document_level: Vec<ObjectId>,
+    // Everything the first page uses, its page object first
+    first_page: Vec<ObjectId>,
+    // The objects only used by each further page, page object first
+    pages: Vec<Vec<ObjectId>>,
+    // Objects used by several of the further pages, but not the first
+    shared: Vec<ObjectId>,
+    // The page tree, document information, metadata and so on
+    other: Vec<ObjectId>,
+    // Per further page, its shared objects as indexes into first_page followed by shared
+    shared_references: Vec<Vec<usize>>,
+}
+
+impl Sections {
+    fn main(&self) -> impl Iterator<Item = &ObjectId> {
+        self.pages.iter().flatten().chain(&self.shared).chain(&self.other)
+    }
+
+    fn first(&self) -> impl Iterator<Item = &ObjectId> {
+        self.document_level.iter().chain(&self.first_page)
+    }
+}
+
+// Renumbers the objects in the order `save` writes them: the further pages, shared and
+// other objects from 1, then the catalog and the first page. Done before encrypting, as
+// the encryption depends on the object numbers
+pub fn number_objects(document: &mut Document) -> Result<()> {
+    let sections = sections(document)?;
+    let numbers: HashMap<ObjectId, ObjectId> =
+        sections.main().chain(sections.first()).enumerate().map(|(i, &id)| (id, (i as u32 + 1, 0))).collect();
+    let objects = std::mem::take(&mut document.objects);
+    document.objects = objects
+        .into_iter()
+        .filter_map(|(id, mut object)| {
+            renumber(&mut object, &numbers);
+            numbers.get(&id).map(|&id| (id, object))
+        })
+        .collect();
+    for (_, value) in document.trailer.iter_mut() {
+        renumber(value, &numbers);
+    }
+    document.max_id = numbers.len() as u32;
+    Ok(())
+}
+
+// Writes the document numbered by `number_objects` (and maybe encrypted since, with the file
+// key `key`) as the bytes of a linearized file
+pub fn save_to_bytes(document: &mut Document, key: Option<&[u8]>) -> Result<Vec<u8>> {
+    let sections = sections(document)?;
+    let main_count = sections.main().count() as u32;
+    let in_order = sections.main().all(|id| id.0 <= main_count)
+        && sections.first().all(|id| id.0 > main_count)
+        && sections.main().count() + sections.first().count() == document.max_id as usize;
+    if !in_order {
+        return Err(anyhow!("The PDF objects are not numbered for linearization"));
+    }
+    let first_page_object = sections.first_page[0].0;
+    let page_count = sections.pages.len() + 1;
+    let linearization_id = document.max_id + 1;
+    let hint_id = document.max_id + 2;
+
+    // lopdf writes the objects in order of their numbers; each one is cut out of its output,
+    // found through its cross-reference table (rather than a stream)
+    document.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    let mut written = Vec::new();
+    document.save_to(&mut written)?;
+    let objects = object_ranges(&written)?;
+    let bytes = |id: &ObjectId| &written[objects[&id.0].clone()];
+    let length = |id: &ObjectId| objects[&id.0].len();
+
+    let mut header = format!("%PDF-{}\n", document.version).into_bytes();
+    if !document.version.contains('%') {
+        header.extend(b"%\xE2\xE3\xCF\xD3\n");
+    }
+    let dictionary = |layout| linearization_dictionary(linearization_id, layout, first_page_object, page_count);
+    let trailer = |prev| first_trailer(document, hint_id + 1, prev);
+    let first_entries = sections.first().count() + 2;
+    let first_table_start = header.len() + dictionary(Layout::default()).len();
+    let first_part = first_table_start + xref_table_length(main_count + 1, first_entries) + trailer(0).len();
+
+    // Offsets as if the hint stream were not there, which is how the hint tables give them
+    let document_level_length: usize = sections.document_level.iter().map(length).sum();
+    let first_page_start = first_part + document_level_length;
+    let mut offsets = HashMap::new();
+    let mut position = first_part;
+    for id in sections.first().chain(sections.main()) {
+        offsets.insert(id.0, position);
+        position += length(id);
+    }
+    let hints = hint_stream(&sections, &offsets, &length, first_page_start);
+    let mut hint_data = hints.data;
+    if let Some(key) = key {
+        hint_data = crate::encrypt::encrypt_data(key, (hint_id, 0), &hint_data);
+    }
+    let mut hint_object = format!("{} 0 obj\n<</Length {} /S {}>>\nstream\n", hint_id, hint_data.len(), hints.shared_table).into_bytes();
+    hint_object.extend(hint_data);
+    hint_object.extend(b"\nendstream\nendobj\n");
+
+    // The actual offsets: everything from the first page on moves by the hint stream
+    let shift = hint_object.len();
+    for (_, offset) in offsets.iter_mut().filter(|(_, offset)| **offset >= first_page_start) {
+        *offset += shift;
+    }
+    let first_page_end = first_page_start + shift + sections.first_page.iter().map(length).sum::<usize>();
+    let main_table = position + shift;
+    let file_length = main_table + xref_table_length(0, main_count as usize + 1) + main_trailer(main_count + 1, first_table_start).len();
+    let layout = Layout {
+        file_length,
+        hint_offset: first_page_start,
+        hint_length: shift,
+        first_page_end,
+        main_entries: main_table + format!("xref\n0 {}", main_count + 1).len(),
+    };
+
+    let mut first_table: BTreeMap<u32, usize> = sections.first().map(|id| (id.0, offsets[&id.0])).collect();
+    first_table.insert(linearization_id, header.len());
+    first_table.insert(hint_id, first_page_start);
+    let mut output = header;
+    output.extend(dictionary(layout));
+    write!(output, "xref\n{} {}\n", main_count + 1, first_entries)?;
+    for offset in first_table.values() {
+        writeln!(output, "{:010} 00000 n ", offset)?;
+    }
+    output.extend(trailer(main_table));
+    for id in &sections.document_level {
+        output.extend(bytes(id));
+    }
+    output.extend(&hint_object);
+    for id in sections.first_page.iter().chain(sections.main()) {
+        output.extend(bytes(id));
+    }
+    write!(output, "xref\n0 {}\n0000000000 65535 f \n", main_count + 1)?;
+    for id in 1..=main_count {
+        writeln!(output, "{:010} 00000 n ", offsets[&id])?;
+    }
+    output.extend(main_trailer(main_count + 1, first_table_start));
+    Ok(output)
+}
+
+fn sections(document: &Document) -> Result<Sections> {
+    let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+    if pages.is_empty() {
+        return Err(anyhow!("Cannot linearize a PDF without pages"));
+    }
+    // Page objects and page tree nodes end the search for what a page or the outline uses
+    let page_tree: HashSet<ObjectId> = document
+        .objects
+        .iter()
+        .filter(|(_, object)| matches!(object.type_name(), Ok("Page" | "Pages")))
+        .map(|(&id, _)| id)
+        .collect();
+    let catalog = document.trailer.get(b"Root")?.as_reference()?;
+
+    let mut document_level = vec![catalog];
+    if let Ok(encryption) = document.trailer.get(b"Encrypt").and_then(Object::as_reference) {
+        document_level.push(encryption);
+    }
+    let catalog_dictionary = document.get_dictionary(catalog)?;
+    if catalog_dictionary.get(b"PageMode").and_then(Object::as_name_str).ok() == Some("UseOutlines") {
+        if let Ok(outline) = catalog_dictionary.get(b"Outlines").and_then(Object::as_reference) {
+            document_level.extend(reachable(document, outline, &page_tree));
+        }
+    }
+    let mut placed: HashSet<ObjectId> = document_level.iter().copied().collect();
+    let first_page: Vec<ObjectId> = reachable(document, pages[0], &page_tree).into_iter().filter(|id| !placed.contains(id)).collect();
+    placed.extend(&first_page);
+
+    let used: Vec<Vec<ObjectId>> = pages[1..]
+        .iter()
+        .map(|&page| reachable(document, page, &page_tree).into_iter().filter(|id| !document_level.contains(id)).collect())
+        .collect();
+    let mut users: HashMap<ObjectId, usize> = HashMap::new();
+    for id in used.iter().flatten().filter(|id| !placed.contains(id)) {
+        *users.entry(*id).or_default() += 1;
+    }
+    let mut shared = Vec::new();
+    for id in used.iter().flatten() {
+        if users.get(id).is_some_and(|&count| count > 1) && !shared.contains(id) {
+            shared.push(*id);
+        }
+    }
+    let pages: Vec<Vec<ObjectId>> = used.iter().map(|ids| ids.iter().filter(|id| users.get(id) == Some(&1)).copied().collect()).collect();
+    let shared_references = used
+        .iter()
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| match first_page.iter().position(|first| first == id) {
+                    Some(index) => Some(index),
+                    None => shared.iter().position(|other| other == id).map(|index| first_page.len() + index),
+                })
+                .collect()
+        })
+        .collect();
+    placed.extend(pages.iter().flatten().chain(&shared));
+    let other = document.objects.keys().filter(|id| !placed.contains(id)).copied().collect();
+    Ok(Sections { document_level, first_page, pages, shared, other, shared_references })
+}
+
+// The objects `start` uses directly or indirectly, itself first, without entering `stops`
+fn reachable(document: &Document, start: ObjectId, stops: &HashSet<ObjectId>) -> Vec<ObjectId> {
+    let mut found = vec![start];
+    let mut seen: HashSet<ObjectId> = found.iter().copied().collect();
+    let mut i = 0;
+    while i < found.len() {
+        if let Ok(object) = document.get_object(found[i]) {
+            let mut references = Vec::new();
+            collect_references(object, &mut references);
+            for id in references {
+                if !stops.contains(&id) && document.objects.contains_key(&id) && seen.insert(id) {
+                    found.push(id);
+                }
+            }
+        }
+        i += 1;
+    }
+    found
+}
+
+pub fn collect_references(object: &Object, references: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => references.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| collect_references(item, references)),
+        Object::Dictionary(dictionary) => dictionary.iter().for_each(|(_, value)| collect_references(value, references)),
+        Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| collect_references(value, references)),
+        _ => {}
+    }
+}
+
+pub fn renumber(object: &mut Object, numbers: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(&number) = numbers.get(id) {
+                *id = number;
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| renumber(item, numbers)),
+        Object::Dictionary(dictionary) => dictionary.iter_mut().for_each(|(_, value)| renumber(value, numbers)),
+        Object::Stream(stream) => stream.dict.iter_mut().for_each(|(_, value)| renumber(value, numbers)),
+        _ => {}
+    }
+}
+
+// Byte ranges of the objects in a file lopdf wrote, from its cross-reference table
+fn object_ranges(written: &[u8]) -> Result<BTreeMap<u32, std::ops::Range<usize>>> {
+    let keyword = written.windows(9).rposition(|window| window == b"startxref").ok_or_else(|| anyhow!("Cannot find the cross-reference table"))?;
+    let table_start: usize = String::from_utf8_lossy(&written[keyword + 9..])
+        .split_whitespace()
+        .next()
+        .and_then(|offset| offset.parse().ok())
+        .ok_or_else(|| anyhow!("Cannot find the cross-reference table"))?;
+    let table = String::from_utf8_lossy(&written[table_start..]).into_owned();
+    let mut starts = Vec::new();
+    let mut lines = table.lines().skip(1);
+    while let Some(subsection) = lines.next() {
+        let mut numbers = subsection.split_whitespace().map(|number| number.parse::<u32>());
+        let (Some(Ok(first)), Some(Ok(count))) = (numbers.next(), numbers.next()) else {
+            break;
+        };
+        for id in first..first + count {
+            // Offset, generation and type, separated by whitespace
+            let entry: Vec<&str> = lines.next().ok_or_else(|| anyhow!("Truncated cross-reference table"))?.split_whitespace().collect();
+            match entry[..] {
+                [offset, _, "n"] => starts.push((offset.parse::<usize>()?, id)),
+                [_, _, "f"] => {}
+                _ => return Err(anyhow!("Invalid cross-reference entry for object {}", id)),
+            }
+        }
+    }
+    starts.sort();
+    let ends = starts.iter().skip(1).map(|&(start, _)| start).chain([table_start]);
+    Ok(starts.iter().zip(ends).map(|(&(start, id), end)| (id, start..end)).collect())
+}
+
+fn linearization_dictionary(id: u32, layout: Layout, first_page_object: u32, page_count: usize) -> Vec<u8> {
+    let w = NUMBER_WIDTH;
+    format!(
+        "{} 0 obj\n<</Linearized 1 /L {:w$} /H [{:w$} {:w$}] /O {} /E {:w$} /N {} /T {:w$}>>\nendobj\n",
+        id, layout.file_length, layout.hint_offset, layout.hint_length, first_page_object, layout.first_page_end, page_count, layout.main_entries
+    )
+    .into_bytes()
+}
+
+fn xref_table_length(first: u32, count: usize) -> usize {
+    format!("xref\n{} {}\n", first, count).len() + 20 * count
+}
+
+// The trailer of the first-page table, with the root, information, identifier and encryption
+fn first_trailer(document: &Document, size: u32, prev: usize) -> Vec<u8> {
+    let mut entries = String::new();
+    for key in ["Root", "Info", "Encrypt"] {
+        if let Ok(id) = document.trailer.get(key.as_bytes()).and_then(Object::as_reference) {
+            entries += &format!(" /{} {} {} R", key, id.0, id.1);
+        }
+    }
+    if let Ok(ids) = document.trailer.get(b"ID").and_then(Object::as_array) {
+        let ids: Vec<String> = ids
+            .iter()
+            .filter_map(|id| id.as_str().ok())
+            .map(|id| format!("<{}>", id.iter().map(|byte| format!("{:02X}", byte)).collect::<String>()))
+            .collect();
+        entries += &format!(" /ID [{}]", ids.join(" "));
+    }
+    format!("trailer\n<</Size {}{} /Prev {:w$}>>\nstartxref\n0\n%%EOF\n", size, entries, prev, w = NUMBER_WIDTH).into_bytes()
+}
+
+fn main_trailer(size: u32, first_table: usize) -> Vec<u8> {
+    format!("trailer\n<</Size {}>>\nstartxref\n{}\n%%EOF\n", size, first_table).into_bytes()
+}
+
+struct HintStream {
+    data: Vec<u8>,
+    // Offset of the shared object hint table in the data
+    shared_table: usize,
+}
+
+// The page offset and shared object hint tables; as usual, the content stream items repeat
+// the page lengths, and shared objects form groups of one. Items of zero bits (the shared
+// object positions, content offsets and group sizes) have no rows
+fn hint_stream(sections: &Sections, offsets: &HashMap<u32, usize>, length: &dyn Fn(&ObjectId) -> usize, first_page_start: usize) -> HintStream {
+    let first_page_length: usize = sections.first_page.iter().map(length).sum();
+    let object_counts: Vec<usize> = [sections.first_page.len()].into_iter().chain(sections.pages.iter().map(Vec::len)).collect();
+    let page_lengths: Vec<usize> =
+        [first_page_length].into_iter().chain(sections.pages.iter().map(|ids| ids.iter().map(length).sum())).collect();
+    let references: Vec<&[usize]> = [&[][..]].into_iter().chain(sections.shared_references.iter().map(Vec::as_slice)).collect();
+
+    let least_objects = *object_counts.iter().min().unwrap_or(&0);
+    let most_objects = *object_counts.iter().max().unwrap_or(&0);
+    let least_length = *page_lengths.iter().min().unwrap_or(&0);
+    let most_length = *page_lengths.iter().max().unwrap_or(&0);
+    let object_bits = bits(most_objects - least_objects);
+    let length_bits = bits(most_length - least_length);
+    let reference_count_bits = bits(references.iter().map(|ids| ids.len()).max().unwrap_or(0));
+    let group_count = sections.first_page.len() + sections.shared.len();
+    let identifier_bits = bits(group_count.saturating_sub(1));
+
+    let mut table = BitWriter::default();
+    table.write(least_objects, 32);
+    table.write(first_page_start, 32);
+    table.write(object_bits, 16);
+    table.write(least_length, 32);
+    table.write(length_bits, 16);
+    table.write(0, 32); // Least content stream offset
+    table.write(0, 16);
+    table.write(least_length, 32); // Least content stream length
+    table.write(length_bits, 16);
+    table.write(reference_count_bits, 16);
+    table.write(identifier_bits, 16);
+    table.write(0, 16); // Numerators of the shared object positions
+    table.write(1, 16);
+    table.row(object_counts.iter().map(|count| count - least_objects), object_bits);
+    table.row(page_lengths.iter().map(|page_length| page_length - least_length), length_bits);
+    table.row(references.iter().map(|ids| ids.len()), reference_count_bits);
+    table.row(references.iter().flat_map(|ids| ids.iter().copied()), identifier_bits);
+    table.row(page_lengths.iter().map(|page_length| page_length - least_length), length_bits);
+    let shared_table = table.data.len();
+
+    let group_lengths: Vec<usize> = sections.first_page.iter().chain(&sections.shared).map(length).collect();
+    let least_group = *group_lengths.iter().min().unwrap_or(&0);
+    let group_bits = bits(group_lengths.iter().max().unwrap_or(&0) - least_group);
+    let shared_start = sections.shared.first().map_or((0, 0), |id| (id.0 as usize, offsets[&id.0]));
+    table.write(shared_start.0, 32);
+    table.write(shared_start.1, 32);
+    table.write(sections.first_page.len(), 32);
+    table.write(group_count, 32);
+    table.write(0, 16); // Objects per group, all one
+    table.write(least_group, 32);
+    table.write(group_bits, 16);
+    table.row(group_lengths.iter().map(|group_length| group_length - least_group), group_bits);
+    table.row(group_lengths.iter().map(|_| 0), 1); // No MD5 signatures
+    HintStream { data: table.data, shared_table }
+}
+
+fn bits(value: usize) -> usize {
+    (usize::BITS - value.leading_zeros()) as usize
+}
+
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    byte: u8,
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: usize, bits: usize) {
+        for bit in (0..bits).rev() {
+            self.byte = self.byte << 1 | (value >> bit & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                self.data.push(self.byte);
+                self.byte = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    // One item for every page or group; the next item starts on a new byte
+    fn row(&mut self, values: impl Iterator<Item = usize>, bits: usize) {
+        for value in values {
+            self.write(value, bits);
+        }
+        if self.used > 0 {
+            self.data.push(self.byte << (8 - self.used));
+            self.byte = 0;
+            self.used = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printpdf::lopdf::xref::XrefEntry;
+    use printpdf::lopdf::{Stream, dictionary};
+
+    // Two pages sharing a font, each with its own content
+    fn two_pages() -> Document {
+        let mut document = Document::with_version("1.5");
+        let pages = document.new_object_id();
+        let font = document.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+        let kids: Vec<Object> = ["First", "Second"]
+            .iter()
+            .map(|text| {
+                let content = format!("BT /F1 24 Tf 72 720 Td ({}) Tj ET", text);
+                let content = document.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+                let page = document.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                    "Resources" => dictionary! { "Font" => dictionary! { "F1" => font } },
+                    "Contents" => content,
+                });
+                page.into()
+            })
+            .collect();
+        document.objects.insert(pages, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => 2, "Kids" => kids }));
+        let catalog = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages });
+        document.trailer.set("Root", catalog);
+        document
+    }
+
+    #[test]
+    fn linearized_file_reloads_with_its_offsets() {
+        let mut document = two_pages();
+        number_objects(&mut document).unwrap();
+        let bytes = save_to_bytes(&mut document, None).unwrap();
+
+        let loaded = Document::load_mem(&bytes).unwrap();
+        assert_eq!(loaded.get_pages().len(), 2);
+        let offset = |id: u32| match loaded.reference_table.get(id) {
+            Some(XrefEntry::Normal { offset, .. }) => *offset as usize,
+            _ => panic!("object {} is not in the cross-reference tables", id),
+        };
+        let (&linearization_id, _) = loaded.objects.iter().find(|(_, object)| object.as_dict().is_ok_and(|d| d.has(b"Linearized"))).unwrap();
+        // Right after the header
+        assert_eq!(offset(linearization_id.0), b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n".len());
+        let linearization = loaded.get_dictionary(linearization_id).unwrap();
+        let number = |key: &[u8]| linearization.get(key).unwrap().as_i64().unwrap() as usize;
+        assert_eq!(number(b"L"), bytes.len());
+        assert_eq!(number(b"N"), 2);
+        // The first page comes before /E, and the second page's objects (numbered from 1) start there
+        let first_page = number(b"O") as u32;
+        assert_eq!(loaded.get_dictionary((first_page, 0)).unwrap().get(b"Type").unwrap().as_name_str().unwrap(), "Page");
+        assert!(offset(first_page) < number(b"E"));
+        assert_eq!(offset(1), number(b"E"));
+        assert!(bytes[number(b"E")..].starts_with(b"1 0 obj"));
+        // /T is the end of the main table's first line
+        let main_table = number(b"T");
+        let before = String::from_utf8_lossy(&bytes[main_table - 12..main_table]).into_owned();
+        assert!(before.rsplit_once("xref\n0 ").is_some_and(|(_, count)| count.parse::<usize>().is_ok()));
+        assert!(bytes[main_table..].starts_with(b"\n0000000000 65535 f "));
+    }
+}
@@ -0,0 +1,50 @@
+// Short forced mate search for labeling tactics diagrams without an engine
+
+use shakmaty::{fen::Fen, san::SanPlus, Chess, Move, Position};
+
+use crate::chess960;
+
+// A forced mate for the side to move
+#[derive(Debug, Clone)]
+pub struct Mate {
+    pub moves: u32,
+    // First move of the mate in SAN
+    pub first_move: String,
+}
+
+// Fewest moves in which the side to move forces mate, up to `max_moves`
+pub fn find_mate(fen: &str, chess960: bool, max_moves: u32) -> Option<Mate> {
+    let position: Chess = Fen::from_ascii(fen.as_bytes())
+        .ok()?
+        .into_position(chess960::castling_mode(chess960))
+        .ok()?;
+    (1..=max_moves).find_map(|moves| {
+        mating_move(&position, moves).map(|m| Mate {
+            moves,
+            first_move: SanPlus::from_move(position.clone(), m).to_string(),
+        })
+    })
+}
+
+// A move after which the side to move mates within `moves` moves against every defence
+fn mating_move(position: &Chess, moves: u32) -> Option<Move> {
+    position.legal_moves().into_iter().find(|&m| {
+        let mut after = position.clone();
+        after.play_unchecked(m);
+        if after.is_checkmate() {
+            return true;
+        }
+        if moves == 1 {
+            return false;
+        }
+
+        // Stalemate is no mate; otherwise every reply must still lose
+        let replies = after.legal_moves();
+        !replies.is_empty()
+            && replies.into_iter().all(|reply| {
+                let mut next = after.clone();
+                next.play_unchecked(reply);
+                mating_move(&next, moves - 1).is_some()
+            })
+    })
+}
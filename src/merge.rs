@@ -0,0 +1,84 @@
+// Appending generated PDFs to an existing one (--append), so that weekly sheets can grow
+// into one booklet: the new document's objects are renumbered past the existing ones and
+// its pages hung into the existing page tree
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::{Dictionary, Document, Object, ObjectId};
+
+// Page attributes a page may inherit from its parent node in the page tree
+const INHERITED_KEYS: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+// Adds the pages of `source` after those of `target` (a copy of `source` when `target`
+// does not exist yet); returns the number of pages added
+pub fn append_pdf(target: &str, source: &str) -> Result<usize> {
+    let load = |path: &str| match Document::load(path) {
+        Ok(document) if document.is_encrypted() => Err(anyhow!("Cannot append to or from '{}': it is encrypted", path)),
+        document => document.map_err(|e| anyhow!("Cannot read PDF '{}': {}", path, e)),
+    };
+    let mut added = load(source)?;
+    let added_pages = added.get_pages().len();
+    if !std::path::Path::new(target).exists() {
+        std::fs::copy(source, target).map_err(|e| anyhow!("Cannot write '{}': {}", target, e))?;
+        return Ok(added_pages);
+    }
+    let mut document = load(target)?;
+    let existing_pages = document.get_pages().len();
+
+    added.renumber_objects_with(document.max_id + 1);
+    let added_catalog = added.catalog()?.clone();
+    let added_labels = crate::labels::label_ranges(&added);
+    let pages: Vec<ObjectId> = added.get_pages().into_values().collect();
+    for &page in &pages {
+        let inherited = inherited_attributes(&added, page);
+        let page = added.get_dictionary_mut(page)?;
+        for (key, value) in inherited {
+            page.set(key, value);
+        }
+    }
+
+    let tree_id = document.catalog()?.get(b"Pages")?.as_reference()?;
+    for &page in &pages {
+        added.get_dictionary_mut(page)?.set("Parent", tree_id);
+    }
+    document.max_id = document.max_id.max(added.max_id);
+    // The added document's catalog, page tree and info become unreferenced and are pruned;
+    // its bookmarks follow the existing ones, its form fields join the existing form
+    document.objects.extend(added.objects);
+    crate::outline::append_outline(&mut document, &added_catalog)?;
+    crate::labels::append_page_labels(&mut document, existing_pages, added_labels)?;
+    crate::form::append_fields(&mut document, &added_catalog)?;
+
+    let tree = document.get_dictionary_mut(tree_id)?;
+    let count = tree.get(b"Count")?.as_i64()?;
+    tree.get_mut(b"Kids")?.as_array_mut()?.extend(pages.iter().map(|&page| Object::Reference(page)));
+    tree.set("Count", count + pages.len() as i64);
+    document.prune_objects();
+    document.compress();
+    document.save(target).map_err(|e| anyhow!("Cannot write '{}': {}", target, e))?;
+    Ok(added_pages)
+}
+
+// Attributes a page takes from its ancestors, nearest first, that it does not set itself
+fn inherited_attributes(document: &Document, page: ObjectId) -> Vec<(Vec<u8>, Object)> {
+    let mut attributes = Vec::new();
+    let Ok(dictionary) = document.get_dictionary(page) else {
+        return attributes;
+    };
+    let mut missing: Vec<&[u8]> = INHERITED_KEYS.into_iter().filter(|key| !dictionary.has(key)).collect();
+    let mut parent = parent_of(dictionary);
+    while let Some(node) = parent.and_then(|id| document.get_dictionary(id).ok()) {
+        missing.retain(|key| match node.get(key) {
+            Ok(value) => {
+                attributes.push((key.to_vec(), value.clone()));
+                false
+            }
+            Err(_) => true,
+        });
+        parent = parent_of(node);
+    }
+    attributes
+}
+
+fn parent_of(node: &Dictionary) -> Option<ObjectId> {
+    node.get(b"Parent").and_then(Object::as_reference).ok()
+}
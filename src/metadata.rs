@@ -0,0 +1,104 @@
+// Document metadata: the info dictionary and the matching XMP packet, written into the
+// document printpdf produced (its own info strings are not encoded for non-ASCII text
+// and it only writes XMP for PDF/X)
+
+use anyhow::Result;
+use printpdf::lopdf::{Document, Object, Stream, dictionary};
+use crate::escape_xml;
+use crate::outline::text_string;
+use crate::schedule::Date;
+
+pub struct Metadata {
+    pub title: String,
+    pub author: String,
+    pub subject: String,
+    pub keywords: Vec<String>,
+    // Seconds since 1970 (UTC)
+    pub created: u64,
+    // Declare PDF/A-2b conformance (--pdfa)
+    pub pdfa: bool,
+}
+
+pub fn set_metadata(document: &mut Document, metadata: &Metadata) -> Result<()> {
+    let tool = format!("fen2pdf {}", env!("CARGO_PKG_VERSION"));
+    let date = pdf_date(metadata.created);
+    let mut info = dictionary! {
+        "Title" => text_string(&metadata.title),
+        "Subject" => text_string(&metadata.subject),
+        "Keywords" => text_string(&metadata.keywords.join(", ")),
+        "Creator" => text_string(&tool),
+        "Producer" => text_string(&tool),
+        "CreationDate" => Object::string_literal(date.clone()),
+        "ModDate" => Object::string_literal(date),
+    };
+    if !metadata.author.is_empty() {
+        info.set("Author", text_string(&metadata.author));
+    }
+    let info = document.add_object(info);
+    document.trailer.set("Info", info);
+
+    let packet = xmp_packet(metadata, &tool);
+    let stream = Stream::new(dictionary! { "Type" => "Metadata", "Subtype" => "XML" }, packet.into_bytes());
+    // Left uncompressed so that tools scanning for XMP packets find it
+    let stream = document.add_object(stream.with_compression(false));
+    document.catalog_mut()?.set("Metadata", stream);
+    Ok(())
+}
+
+fn xmp_packet(metadata: &Metadata, tool: &str) -> String {
+    let date = format!("{}Z", date_time(metadata.created));
+    let creator = if metadata.author.is_empty() {
+        String::new()
+    } else {
+        format!("<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n", escape_xml(&metadata.author))
+    };
+    let conformance = if metadata.pdfa {
+        "<rdf:Description rdf:about=\"\" xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n<pdfaid:part>2</pdfaid:part>\n<pdfaid:conformance>B</pdfaid:conformance>\n</rdf:Description>\n"
+    } else {
+        ""
+    };
+    let subjects: String = metadata.keywords.iter().map(|keyword| format!("<rdf:li>{}</rdf:li>", escape_xml(keyword))).collect();
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\">\n",
+            "<dc:format>application/pdf</dc:format>\n",
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{title}</rdf:li></rdf:Alt></dc:title>\n",
+            "{creator}",
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{subject}</rdf:li></rdf:Alt></dc:description>\n",
+            "<dc:subject><rdf:Bag>{subjects}</rdf:Bag></dc:subject>\n",
+            "<pdf:Keywords>{keywords}</pdf:Keywords>\n",
+            "<pdf:Producer>{tool}</pdf:Producer>\n",
+            "<xmp:CreatorTool>{tool}</xmp:CreatorTool>\n",
+            "<xmp:CreateDate>{date}</xmp:CreateDate>\n",
+            "<xmp:ModifyDate>{date}</xmp:ModifyDate>\n",
+            "<xmp:MetadataDate>{date}</xmp:MetadataDate>\n",
+            "</rdf:Description>\n",
+            "{conformance}",
+            "</rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"r\"?>"
+        ),
+        title = escape_xml(&metadata.title),
+        creator = creator,
+        subject = escape_xml(&metadata.subject),
+        subjects = subjects,
+        keywords = escape_xml(&metadata.keywords.join(", ")),
+        tool = tool,
+        date = date,
+        conformance = conformance,
+    )
+}
+
+// A PDF date string, D:YYYYMMDDHHMMSSZ
+pub fn pdf_date(seconds: u64) -> String {
+    format!("D:{}Z", date_time(seconds).replace(['-', 'T', ':'], ""))
+}
+
+// YYYY-MM-DDTHH:MM:SS in UTC
+fn date_time(seconds: u64) -> String {
+    let time = seconds % 86_400;
+    format!("{}T{:02}:{:02}:{:02}", Date::from_unix_time(seconds), time / 3600, time / 60 % 60, time % 60)
+}
@@ -0,0 +1,59 @@
+// Note annotations (--solutions popup): the solution of each diagram sits in a closed note
+// that PDF viewers open on click. Notes are not printed, so paper copies stay spoiler-free
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::{Document, Object, dictionary};
+use crate::outline::text_string;
+
+// Size of the popup window viewers open, in points
+const POPUP_WIDTH: f32 = 240.0;
+const POPUP_HEIGHT: f32 = 120.0;
+
+pub struct Note {
+    // Index of the page, from 0
+    pub page: usize,
+    // Left, bottom, right and top of the icon in points
+    pub rect: [f32; 4],
+    // The popup's title bar
+    pub title: String,
+    pub contents: String,
+}
+
+// Adds a text annotation with its popup for each note to the page's annotations
+pub fn add_notes(document: &mut Document, notes: &[Note]) -> Result<()> {
+    let pages = document.get_pages();
+    for note in notes {
+        let page_id = *pages.get(&(note.page as u32 + 1)).ok_or_else(|| anyhow!("No page {} for a note", note.page + 1))?;
+        let [left, bottom, right, top] = note.rect;
+        let text = document.new_object_id();
+        let popup = document.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Popup",
+            "Rect" => vec![right.into(), (top - POPUP_HEIGHT).into(), (right + POPUP_WIDTH).into(), top.into()],
+            "Parent" => text,
+            "Open" => false,
+        });
+        document.objects.insert(text, Object::Dictionary(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![left.into(), bottom.into(), right.into(), top.into()],
+            "Contents" => text_string(&note.contents),
+            "T" => text_string(&note.title),
+            "Name" => "Key",
+            "Open" => false,
+            "Popup" => popup,
+            // Without the print flag the icon is left off paper; NoZoom and NoRotate
+            "F" => 24,
+        }));
+
+        // printpdf writes the links inline
+        let page = document.get_object_mut(page_id).and_then(Object::as_dict_mut)?;
+        if !page.has(b"Annots") {
+            page.set("Annots", Vec::<Object>::new());
+        }
+        let annotations = page.get_mut(b"Annots").and_then(Object::as_array_mut)?;
+        annotations.push(Object::Reference(text));
+        annotations.push(Object::Reference(popup));
+    }
+    Ok(())
+}
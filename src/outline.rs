@@ -0,0 +1,127 @@
+// PDF outline (the bookmark tree in a viewer's sidebar), added to the document printpdf
+// wrote: printpdf only knows a flat list of bookmarks, and lopdf's writes titles as UTF-8
+
+use anyhow::{Result, anyhow};
+use printpdf::lopdf::{Dictionary, Document, Object, ObjectId, StringFormat, dictionary};
+use std::collections::BTreeMap;
+
+// One bookmark: its title, the (1-based) page it jumps to and the bookmarks below it
+pub struct Entry {
+    pub title: String,
+    pub page: usize,
+    pub children: Vec<Entry>,
+}
+
+// Replaces the document's outline with `entries` and opens it when the document is opened;
+// only the top level is expanded
+pub fn add_outline(document: &mut Document, entries: &[Entry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let pages = document.get_pages();
+    let root = document.new_object_id();
+    let (first, last, visible) = add_entries(document, root, entries, &pages, true)?;
+    document.objects.insert(root, Object::Dictionary(dictionary! {
+        "Type" => "Outlines",
+        "First" => first,
+        "Last" => last,
+        "Count" => visible as i64,
+    }));
+    let catalog = document.catalog_mut()?;
+    catalog.set("Outlines", root);
+    catalog.set("PageMode", "UseOutlines");
+    Ok(())
+}
+
+// Writes one level of the tree below `parent`; returns its first and last items and how
+// many items show while it is open
+fn add_entries(
+    document: &mut Document,
+    parent: ObjectId,
+    entries: &[Entry],
+    pages: &BTreeMap<u32, ObjectId>,
+    open: bool,
+) -> Result<(ObjectId, ObjectId, usize)> {
+    let ids: Vec<ObjectId> = entries.iter().map(|_| document.new_object_id()).collect();
+    let mut visible = entries.len();
+    for (i, entry) in entries.iter().enumerate() {
+        let page = *pages.get(&(entry.page as u32)).ok_or_else(|| anyhow!("Bookmark '{}' points past the last page", entry.title))?;
+        let mut item = dictionary! {
+            "Title" => text_string(&entry.title),
+            "Parent" => parent,
+            "Dest" => vec![page.into(), "Fit".into()],
+        };
+        if i > 0 {
+            item.set("Prev", ids[i - 1]);
+        }
+        if i + 1 < ids.len() {
+            item.set("Next", ids[i + 1]);
+        }
+        if !entry.children.is_empty() {
+            let (first, last, below) = add_entries(document, ids[i], &entry.children, pages, false)?;
+            item.set("First", first);
+            item.set("Last", last);
+            // A negative count marks a closed item
+            item.set("Count", if open { below as i64 } else { -(entry.children.len() as i64) });
+            if open {
+                visible += below;
+            }
+        }
+        document.objects.insert(ids[i], Object::Dictionary(item));
+    }
+    Ok((ids[0], ids[ids.len() - 1], visible))
+}
+
+// PDF text strings are PDFDocEncoding, which agrees with ASCII, or UTF-16BE after a byte order mark
+pub fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let bytes = [0xFE, 0xFF].into_iter().chain(text.encode_utf16().flat_map(u16::to_be_bytes)).collect();
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+// Appends the outline items of `added` (renumbered objects already copied in) after those of
+// `document`, for merged documents
+pub fn append_outline(document: &mut Document, added: &Dictionary) -> Result<()> {
+    let Ok(added_root) = added.get(b"Outlines").and_then(Object::as_reference) else {
+        return Ok(());
+    };
+    let added_items = document.get_dictionary(added_root)?.clone();
+    let (Ok(added_first), Ok(added_last)) = (added_items.get(b"First"), added_items.get(b"Last")) else {
+        return Ok(());
+    };
+    let (added_first, added_last) = (added_first.as_reference()?, added_last.as_reference()?);
+    let added_count = added_items.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+
+    let root = match document.catalog()?.get(b"Outlines").and_then(Object::as_reference) {
+        Ok(root) => root,
+        Err(_) => {
+            let root = document.add_object(dictionary! { "Type" => "Outlines", "Count" => 0 });
+            document.catalog_mut()?.set("Outlines", root);
+            root
+        }
+    };
+    let items = document.get_dictionary(root)?;
+    let last = items.get(b"Last").and_then(Object::as_reference).ok();
+    let count = items.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+
+    let mut item = Some(added_first);
+    while let Some(id) = item {
+        let dictionary = document.get_dictionary_mut(id)?;
+        dictionary.set("Parent", root);
+        item = if id == added_last { None } else { dictionary.get(b"Next").and_then(Object::as_reference).ok() };
+    }
+    match last {
+        Some(last) => {
+            document.get_dictionary_mut(last)?.set("Next", added_first);
+            document.get_dictionary_mut(added_first)?.set("Prev", last);
+        }
+        None => document.get_dictionary_mut(root)?.set("First", added_first),
+    }
+    let items = document.get_dictionary_mut(root)?;
+    items.set("Last", added_last);
+    items.set("Count", count + added_count);
+    document.catalog_mut()?.set("PageMode", "UseOutlines");
+    Ok(())
+}
@@ -0,0 +1,312 @@
+// Page furniture around the diagrams: headers, template regions, watermarks, the info and
+// review schedule pages, and the marks beside an exercise number
+
+use crate::{CHECKBOX_GAP, CHECKBOX_SIZE, ChessPosition, EVAL_BAR_BLACK, EVAL_BAR_WIDTH, IMAGE_DPI, INFO_LABEL_WIDTH, INFO_LICENSE_NOTE, INFO_TOP, INFO_WRAP, LOGO_TOP, LogoPosition, MARGIN_LEFT, MARGIN_RIGHT, Options, PageTemplate, PdfOptions, PrintMode, QR_SIZE, Region, SCHEDULE_NUMBER_WIDTH, SCHEDULE_ROW_HEIGHT, SCHEDULE_TOP, SIDEBAR_HEADING_HEIGHT, SIDEBAR_LINE_SPACING, SOLUTION_LINE_HEIGHT, STAR_RADIUS, STAR_SPACING, StudyData, WATERMARK_FILL, WATERMARK_GRAY, WATERMARK_MAX_SIZE, critical, difficulty, engine, images, qr, schedule};
+use crate::board::rgb_image;
+use crate::solutions::wrap_text;
+use anyhow::Result;
+use printpdf::*;
+
+// Study name centered at the top and "page/total" centered at the bottom
+pub fn draw_page_header(
+    layer: &PdfLayerReference,
+    title: &str,
+    page: usize,
+    page_count: usize,
+    font: &printpdf::IndirectFontRef,
+    images: &images::ImageStore,
+    options: &Options,
+) -> Result<()> {
+    // First on the page, so everything else is drawn over it
+    if let Some(text) = &options.watermark {
+        draw_watermark(layer, text, font, &options.pdf);
+    }
+    if let Some(logo) = &options.logo {
+        let x = match logo.position {
+            LogoPosition::TopLeft => MARGIN_LEFT,
+            LogoPosition::TopRight => options.pdf.page_width() - MARGIN_RIGHT - logo.print_width,
+        };
+        let image = rgb_image(logo.width, logo.height, logo.rgb_data.clone(), images, options)?;
+        place_image(layer, image, x, options.pdf.page_height() - LOGO_TOP - logo.print_height, logo.print_width, logo.print_height);
+    }
+    
+    let page_info = match options.book_pages {
+        Some((before, total)) => format!("{}/{}", before + page, total),
+        None => format!("{}/{}", page, page_count),
+    };
+    if let Some(template) = options.pdf.template() {
+        draw_template_regions(layer, template, title, &page_info, font, options);
+        return Ok(());
+    }
+    
+    // Add study name centered before the first boards
+    let study_name_y = options.pdf.page_height() - 25.0; // 25mm from top
+    let title_width_estimate = title.len() as f32 * 1.8; // Rough estimate
+    let study_name_x = (options.pdf.page_width() - title_width_estimate) / 2.0; // Centered
+    layer.use_text(title, 18.0, Mm(study_name_x), Mm(study_name_y), font);
+    
+    // Add page number centered at the bottom
+    let page_info_width_estimate = page_info.len() as f32 * 1.2;
+    let page_info_x = (options.pdf.page_width() - page_info_width_estimate) / 2.0; // Centered
+    let page_info_y = 10.0; // 10mm from bottom
+    layer.use_text(page_info, 14.0, Mm(page_info_x), Mm(page_info_y), font);
+    Ok(())
+}
+
+// The title and page number centered in the template's title and footer regions, and the
+// sidebar's heading over ruled note lines
+fn draw_template_regions(layer: &PdfLayerReference, template: &PageTemplate, title: &str, page_info: &str, font: &printpdf::IndirectFontRef, options: &Options) {
+    let page_height = options.pdf.page_height();
+    // Text of this size and estimated width in the middle of the region
+    let centered = |region: Region, text: &str, font_size: f32, width_estimate: f32| {
+        let baseline = region.y + region.height / 2.0 + font_size * 25.4 / 72.0 * 0.35;
+        layer.use_text(text, font_size, Mm(region.x + (region.width - width_estimate) / 2.0), Mm(page_height - baseline), font);
+    };
+    if let Some(region) = template.title {
+        centered(region, title, 18.0, title.len() as f32 * 1.8);
+    }
+    if let Some(region) = template.footer {
+        centered(region, page_info, 14.0, page_info.len() as f32 * 1.2);
+    }
+    if let Some((region, heading)) = &template.sidebar {
+        let mut line_y = region.y;
+        if !heading.is_empty() {
+            line_y += SIDEBAR_HEADING_HEIGHT;
+            layer.use_text(heading, 11.0, Mm(region.x), Mm(page_height - line_y), font);
+        }
+        layer.set_outline_thickness(0.3);
+        line_y += SIDEBAR_LINE_SPACING;
+        while line_y <= region.bottom() {
+            draw_ruling(layer, region.x, region.right(), page_height - line_y, options.print_mode);
+            line_y += SIDEBAR_LINE_SPACING;
+        }
+    }
+}
+
+// Places an image stretched to `width` × `height` mm with its bottom-left corner at (x, y)
+fn place_image(layer: &PdfLayerReference, image: printpdf::Image, x: f32, y: f32, width: f32, height: f32) {
+    let natural_size = |pixels: usize| pixels as f32 / IMAGE_DPI * 25.4;
+    let scale_x = width / natural_size(image.image.width.0);
+    let scale_y = height / natural_size(image.image.height.0);
+    image.add_to_layer(layer.clone(), ImageTransform {
+        translate_x: Some(Mm(x)),
+        translate_y: Some(Mm(y)),
+        scale_x: Some(scale_x),
+        scale_y: Some(scale_y),
+        dpi: Some(IMAGE_DPI),
+        ..Default::default()
+    });
+}
+
+// Text along the page diagonal, centered and as large as fits in WATERMARK_FILL of it
+fn draw_watermark(layer: &PdfLayerReference, text: &str, font: &printpdf::IndirectFontRef, pdf: &PdfOptions) {
+    let (page_width, page_height) = (pdf.page_width(), pdf.page_height());
+    let angle = page_height.atan2(page_width);
+    let diagonal = page_width.hypot(page_height);
+    // Times capitals average about 0.7 em; the size is in points
+    let em_per_text = 0.7 * text.chars().count() as f32;
+    let font_size = (WATERMARK_FILL * diagonal / em_per_text * 72.0 / 25.4).min(WATERMARK_MAX_SIZE);
+    let width = em_per_text * font_size * 25.4 / 72.0;
+    // Half the cap height below the diagonal, so the letters straddle it
+    let drop = 0.35 * font_size * 25.4 / 72.0;
+    let x = (page_width - width * angle.cos()) / 2.0 + drop * angle.sin();
+    let y = (page_height - width * angle.sin()) / 2.0 - drop * angle.cos();
+    
+    layer.set_fill_color(printpdf::Color::Rgb(Rgb::new(WATERMARK_GRAY, WATERMARK_GRAY, WATERMARK_GRAY, None)));
+    layer.begin_text_section();
+    layer.set_font(font, font_size);
+    layer.set_text_matrix(TextMatrix::TranslateRotate(Mm(x).into(), Mm(y).into(), angle.to_degrees()));
+    layer.write_text(text, font);
+    layer.end_text_section();
+    layer.set_fill_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+}
+
+// What the study's PGN tells about it, label by label, then the note on its use
+pub fn draw_info_page(layer: &PdfLayerReference, study_data: &StudyData, font: &printpdf::IndirectFontRef, font_bold: &printpdf::IndirectFontRef, options: &Options) {
+    layer.use_text("About the study", 14.0, Mm(MARGIN_LEFT), Mm(options.pdf.page_height() - 35.0), font_bold);
+    
+    let info = &study_data.info;
+    let variants: Vec<String> = info.variants.iter().map(|(variant, count)| format!("{} {}", variant, count)).collect();
+    let mut rows = vec![("Study", study_data.name.clone())];
+    match info.authors.len() {
+        0 => {}
+        1 => rows.push(("Author", info.authors[0].clone())),
+        _ => rows.push(("Authors", info.authors.join(", "))),
+    }
+    rows.extend(info.url.clone().map(|url| ("Address", url)));
+    rows.push(("Exported", schedule::Date::from_unix_time(options.created).to_string()));
+    rows.push(("Chapters", info.chapters.to_string()));
+    rows.push(("Exercises", study_data.positions.len().to_string()));
+    if !variants.is_empty() {
+        rows.push(("Variants", variants.join(", ")));
+    }
+    
+    let mut y = options.pdf.page_height() - INFO_TOP;
+    for (label, value) in rows {
+        layer.use_text(label, 11.0, Mm(MARGIN_LEFT), Mm(y), font_bold);
+        layer.use_text(value, 11.0, Mm(MARGIN_LEFT + INFO_LABEL_WIDTH), Mm(y), font);
+        y -= SOLUTION_LINE_HEIGHT;
+    }
+    y -= SOLUTION_LINE_HEIGHT;
+    for line in wrap_text(INFO_LICENSE_NOTE, INFO_WRAP) {
+        layer.use_text(line, 10.0, Mm(MARGIN_LEFT), Mm(y), font);
+        y -= SOLUTION_LINE_HEIGHT;
+    }
+}
+
+// Table of exercises against review dates, each cell an empty checkbox
+pub fn draw_review_schedule(
+    layer: &PdfLayerReference,
+    positions: &[ChessPosition],
+    start: schedule::Date,
+    font: &printpdf::IndirectFontRef,
+    font_bold: &printpdf::IndirectFontRef,
+    options: &Options,
+) {
+    layer.use_text("Review schedule", 14.0, Mm(MARGIN_LEFT), Mm(options.pdf.page_height() - 35.0), font_bold);
+    
+    let column_width = (options.pdf.page_width() - MARGIN_LEFT - MARGIN_RIGHT - SCHEDULE_NUMBER_WIDTH) / schedule::REVIEW_INTERVALS.len() as f32;
+    let column_x = |column: usize| MARGIN_LEFT + SCHEDULE_NUMBER_WIDTH + column as f32 * column_width;
+    let top = options.pdf.page_height() - SCHEDULE_TOP;
+    let header_y = top + 2.0 * SCHEDULE_ROW_HEIGHT;
+    layer.use_text("No.", 9.0, Mm(MARGIN_LEFT), Mm(header_y), font_bold);
+    for (column, &days) in schedule::REVIEW_INTERVALS.iter().enumerate() {
+        layer.use_text(format!("Day {}", days), 9.0, Mm(column_x(column)), Mm(header_y), font_bold);
+        layer.use_text(start.add_days(days).to_string(), 8.0, Mm(column_x(column)), Mm(header_y - 4.0), font);
+    }
+    
+    layer.set_outline_thickness(0.3);
+    for (row, pos) in positions.iter().enumerate() {
+        let y = top - row as f32 * SCHEDULE_ROW_HEIGHT;
+        layer.use_text(pos.exercise_mark(options), 10.0, Mm(MARGIN_LEFT), Mm(y), font);
+        layer.set_outline_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+        for column in 0..schedule::REVIEW_INTERVALS.len() {
+            draw_checkbox(layer, column_x(column) + 1.0, y - 0.5);
+        }
+        // Ruling under each row to guide the eye across
+        draw_ruling(layer, MARGIN_LEFT, options.pdf.page_width() - MARGIN_RIGHT, y - 2.0, options.print_mode);
+    }
+}
+
+// Horizontal guide line from x1 to x2: light gray, or with --print-mode bw black and dashed,
+// as laser printers drop or dither light gray strokes
+pub fn draw_ruling(layer: &PdfLayerReference, x1: f32, x2: f32, y: f32, print_mode: PrintMode) {
+    if print_mode == PrintMode::Bw {
+        layer.set_outline_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+        layer.set_line_dash_pattern(LineDashPattern { dash_1: Some(2), gap_1: Some(2), ..Default::default() });
+    } else {
+        layer.set_outline_color(printpdf::Color::Rgb(Rgb::new(0.6, 0.6, 0.6, None)));
+    }
+    layer.add_line(Line {
+        points: vec![(Point::new(Mm(x1), Mm(y)), false), (Point::new(Mm(x2), Mm(y)), false)],
+        is_closed: false,
+    });
+    if print_mode == PrintMode::Bw {
+        layer.set_line_dash_pattern(LineDashPattern::default());
+    }
+}
+
+// Width of a number like "12." in Times: digits are half an em, the period a quarter
+pub fn number_width(text: &str, font_size: f32) -> f32 {
+    let em = font_size * 25.4 / 72.0;
+    // Digits are half an em in Times; capitals (in exercise IDs) about two thirds
+    text.chars()
+        .map(|c| match c {
+            '0'..='9' => 0.5 * em,
+            'A'..='Z' => 0.67 * em,
+            _ => 0.25 * em,
+        })
+        .sum()
+}
+
+// Room taken by the checkboxes and stars between the exercise number and its description
+pub fn decoration_width(checkboxes: usize, stars: bool) -> f32 {
+    let mut width = 0.0;
+    if checkboxes > 0 {
+        width += checkboxes as f32 * (CHECKBOX_SIZE + CHECKBOX_GAP) + 1.0 - CHECKBOX_GAP;
+    }
+    if stars {
+        width += STAR_SPACING * difficulty::MAX_LEVEL as f32 + 1.0;
+    }
+    width
+}
+
+// Five-pointed stars on the text baseline at (x, y): `filled` solid, the rest up to the maximum level outlined
+pub fn draw_stars(layer: &PdfLayerReference, x: f32, y: f32, filled: u32) {
+    layer.set_fill_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    layer.set_outline_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    layer.set_outline_thickness(0.3);
+    for star in 0..difficulty::MAX_LEVEL {
+        let center_x = x + STAR_SPACING * (star as f32 + 0.5);
+        let center_y = y + STAR_RADIUS * 0.8;
+        let points = (0..10)
+            .map(|i| {
+                let radius = if i % 2 == 0 { STAR_RADIUS } else { STAR_RADIUS * 0.45 };
+                let angle = std::f32::consts::FRAC_PI_2 + i as f32 * std::f32::consts::PI / 5.0;
+                (Point::new(Mm(center_x + radius * angle.cos()), Mm(center_y + radius * angle.sin())), false)
+            })
+            .collect();
+        layer.add_polygon(Polygon {
+            rings: vec![points],
+            mode: if star < filled { path::PaintMode::FillStroke } else { path::PaintMode::Stroke },
+            winding_order: path::WindingOrder::NonZero,
+        });
+    }
+}
+
+// Eval bar of `height` with its bottom-left corner at (x, y): White's share from White's side
+// of the board (the top when it is flipped), Black's filled, in a thin frame
+pub fn draw_eval_bar(layer: &PdfLayerReference, x: f32, y: f32, height: f32, score: engine::Score, flipped: bool) {
+    let white = height * (1.0 + critical::winning_chances(score) as f32) / 2.0;
+    let rectangle = |bottom: f32, top: f32| {
+        vec![
+            (Point::new(Mm(x), Mm(bottom)), false),
+            (Point::new(Mm(x + EVAL_BAR_WIDTH), Mm(bottom)), false),
+            (Point::new(Mm(x + EVAL_BAR_WIDTH), Mm(top)), false),
+            (Point::new(Mm(x), Mm(top)), false),
+        ]
+    };
+    let black = if flipped { rectangle(y, y + height - white) } else { rectangle(y + white, y + height) };
+    layer.set_fill_color(printpdf::Color::Rgb(Rgb::new(EVAL_BAR_BLACK, EVAL_BAR_BLACK, EVAL_BAR_BLACK, None)));
+    layer.add_polygon(Polygon { rings: vec![black], mode: path::PaintMode::Fill, winding_order: path::WindingOrder::NonZero });
+    layer.set_outline_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    layer.set_outline_thickness(0.3);
+    layer.add_polygon(Polygon { rings: vec![rectangle(y, y + height)], mode: path::PaintMode::Stroke, winding_order: path::WindingOrder::NonZero });
+    layer.set_fill_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+}
+
+// QR code of `text`, QR_SIZE wide; (x, top) is its upper left corner
+pub fn draw_qr_code(layer: &PdfLayerReference, x: f32, top: f32, text: &str) -> Result<()> {
+    let code = qr::encode(text)?;
+    let module = QR_SIZE / code.size as f32;
+    let rings = code
+        .runs
+        .iter()
+        .map(|&(row, column, length)| {
+            let (left, right) = (x + column as f32 * module, x + (column + length) as f32 * module);
+            let (upper, lower) = (top - row as f32 * module, top - (row + 1) as f32 * module);
+            vec![
+                (Point::new(Mm(left), Mm(lower)), false),
+                (Point::new(Mm(right), Mm(lower)), false),
+                (Point::new(Mm(right), Mm(upper)), false),
+                (Point::new(Mm(left), Mm(upper)), false),
+            ]
+        })
+        .collect();
+    layer.set_fill_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    layer.add_polygon(Polygon { rings, mode: path::PaintMode::Fill, winding_order: path::WindingOrder::NonZero });
+    Ok(())
+}
+
+// Empty square for ticking off; (x, y) is its lower left corner
+pub fn draw_checkbox(layer: &PdfLayerReference, x: f32, y: f32) {
+    layer.add_line(Line {
+        points: vec![
+            (Point::new(Mm(x), Mm(y)), false),
+            (Point::new(Mm(x + CHECKBOX_SIZE), Mm(y)), false),
+            (Point::new(Mm(x + CHECKBOX_SIZE), Mm(y + CHECKBOX_SIZE)), false),
+            (Point::new(Mm(x), Mm(y + CHECKBOX_SIZE)), false),
+        ],
+        is_closed: true,
+    });
+}
@@ -0,0 +1,207 @@
+// Rendering settings of the PDF: paper, diagram grid, board colors and where solutions go.
+// The command line fills them in through the same builder library users call, e.g.
+// PdfOptions::builder().page_size(PageSize::A4).grid(3, 3).theme(Theme::Blue).build()
+
+use crate::template::PageTemplate;
+use anyhow::{Result, anyhow};
+
+// Most diagrams across or down a page; more would leave the boards too small to read
+const MAX_GRID: usize = 6;
+
+// Square grids an automatic grid chooses from, smallest first; documents with more positions
+// than the largest holds get the default 3x3
+const AUTO_GRIDS: [usize; 4] = [1, 2, 3, 4];
+
+// How the chapters' solution moves are printed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SolutionsMode {
+    // A "Solutions" section after the diagrams
+    Appendix,
+    // Each page's solutions rotated 180° at the bottom of the page
+    UpsideDown,
+    // Solutions on the back of each diagram page, behind their diagrams
+    Verso,
+    // A two-column "Solutions" section with the chapters' comments, NAGs and variations
+    Annotated,
+    // A note on each diagram that PDF viewers open on click, left off the printed page
+    Popup,
+}
+
+// Trimmed paper size
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PageSize {
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    // Width and height in mm
+    pub fn dimensions(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+// Colors of the light and dark squares
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Theme {
+    // White and light gray, for printing on any printer
+    Gray,
+    Blue,
+    Brown,
+    Green,
+}
+
+impl Theme {
+    // Light and dark square as RGB
+    pub fn square_colors(self) -> ([u8; 3], [u8; 3]) {
+        match self {
+            Theme::Gray => ([255, 255, 255], [221, 221, 221]),
+            Theme::Blue => ([222, 227, 230], [140, 162, 173]),
+            Theme::Brown => ([240, 217, 181], [181, 136, 99]),
+            Theme::Green => ([238, 238, 210], [118, 150, 86]),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Gray => "gray",
+            Theme::Blue => "blue",
+            Theme::Brown => "brown",
+            Theme::Green => "green",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PdfOptions {
+    page_size: PageSize,
+    // Diagrams across and down each page
+    columns: usize,
+    rows: usize,
+    // The grid follows the number of positions (--grid auto); see fitted_to
+    auto_grid: bool,
+    theme: Theme,
+    solutions: Option<SolutionsMode>,
+    // Regions of the title, grid, footer and sidebar instead of the built-in page layout
+    template: Option<PageTemplate>,
+}
+
+impl Default for PdfOptions {
+    // A4 with 3x3 gray diagrams and no solutions
+    fn default() -> Self {
+        PdfOptions { page_size: PageSize::A4, columns: 3, rows: 3, auto_grid: false, theme: Theme::Gray, solutions: None, template: None }
+    }
+}
+
+impl PdfOptions {
+    pub fn builder() -> PdfOptionsBuilder {
+        PdfOptionsBuilder { options: PdfOptions::default() }
+    }
+
+    pub fn page_width(&self) -> f32 {
+        self.page_size.dimensions().0
+    }
+
+    pub fn page_height(&self) -> f32 {
+        self.page_size.dimensions().1
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn boards_per_page(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    pub fn solutions(&self) -> Option<SolutionsMode> {
+        self.solutions
+    }
+
+    pub fn template(&self) -> Option<&PageTemplate> {
+        self.template.as_ref()
+    }
+
+    pub fn auto_grid(&self) -> bool {
+        self.auto_grid
+    }
+
+    // The options for a document of `positions` diagrams: with an automatic grid, the smallest
+    // of AUTO_GRIDS that puts them all on one page
+    pub fn fitted_to(&self, positions: usize) -> PdfOptions {
+        if !self.auto_grid {
+            return self.clone();
+        }
+        let size = AUTO_GRIDS.into_iter().find(|size| size * size >= positions).unwrap_or(3);
+        PdfOptions { columns: size, rows: size, auto_grid: false, ..self.clone() }
+    }
+}
+
+pub struct PdfOptionsBuilder {
+    options: PdfOptions,
+}
+
+impl PdfOptionsBuilder {
+    pub fn page_size(mut self, page_size: PageSize) -> Self {
+        self.options.page_size = page_size;
+        self
+    }
+
+    // Diagrams across and down each page
+    pub fn grid(mut self, columns: usize, rows: usize) -> Self {
+        self.options.columns = columns;
+        self.options.rows = rows;
+        self
+    }
+
+    // A grid chosen for each document by its number of positions (fitted_to), 3x3 until then
+    pub fn auto_grid(mut self) -> Self {
+        self.options.columns = 3;
+        self.options.rows = 3;
+        self.options.auto_grid = true;
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.options.theme = theme;
+        self
+    }
+
+    pub fn solutions(mut self, solutions: SolutionsMode) -> Self {
+        self.options.solutions = Some(solutions);
+        self
+    }
+
+    // The page layout of the template, whose grid replaces the one set with grid()
+    pub fn template(mut self, template: PageTemplate) -> Self {
+        self.options.columns = template.columns;
+        self.options.rows = template.rows;
+        self.options.template = Some(template);
+        self
+    }
+
+    pub fn build(self) -> Result<PdfOptions> {
+        let PdfOptions { columns, rows, .. } = self.options;
+        if !(1..=MAX_GRID).contains(&columns) || !(1..=MAX_GRID).contains(&rows) {
+            return Err(anyhow!("A grid of {}x{} diagrams does not fit; use 1 to {} in each direction", columns, rows, MAX_GRID));
+        }
+        let (width, height) = self.options.page_size.dimensions();
+        for (name, region) in self.options.template.iter().flat_map(PageTemplate::regions) {
+            if region.x < 0.0 || region.y < 0.0 || region.right() > width || region.bottom() > height {
+                return Err(anyhow!("The template's {} region does not fit on the {}x{} mm page", name, width, height));
+            }
+        }
+        Ok(self.options)
+    }
+}
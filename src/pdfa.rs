@@ -0,0 +1,147 @@
+// PDF/A-2b output (--pdfa): embedded fonts instead of the standard Times, an sRGB output
+// intent for the RGB board images (or the CMYK one of --cmyk) and the document-level details
+// the standard requires
+
+use crate::RenderError;
+use anyhow::Result;
+use printpdf::lopdf::{Document, Object, Stream, dictionary};
+use usvg::fontdb;
+
+// Serif families tried in order, as for the SVG exports
+const SERIF_FAMILIES: [&str; 3] = ["Times New Roman", "Liberation Serif", "DejaVu Serif"];
+
+const PROFILE_NAME: &str = "sRGB IEC61966-2.1";
+
+// Regular and bold serif faces from the system fonts, to embed in place of the standard
+// fonts (which PDF/A does not allow unembedded)
+pub fn serif_fonts() -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut database = fontdb::Database::new();
+    database.load_system_fonts();
+    let families: Vec<fontdb::Family> = SERIF_FAMILIES.iter().map(|&name| fontdb::Family::Name(name)).collect();
+    let face = |weight| {
+        let query = fontdb::Query { families: &families, weight, ..Default::default() };
+        database
+            .query(&query)
+            // Faces inside font collections cannot be embedded by printpdf
+            .and_then(|id| database.with_face_data(id, |data, index| (index == 0).then(|| data.to_vec())))
+            .flatten()
+            .ok_or_else(|| RenderError::MissingFont { families: SERIF_FAMILIES.join(", ") })
+    };
+    Ok((face(fontdb::Weight::NORMAL)?, face(fontdb::Weight::BOLD)?))
+}
+
+// Adds what PDF/A-2b asks of the document printpdf wrote; the fonts and the XMP
+// identification are taken care of when creating it
+pub fn make_conformant(document: &mut Document) -> Result<()> {
+    // A CMYK document already has its output intent; PDF/A takes a copy of it, as all
+    // intents have to share one profile
+    let existing = document.catalog()?.get(b"OutputIntents").and_then(Object::as_array).ok().and_then(|intents| intents.first()).and_then(|intent| intent.as_dict().ok()).cloned();
+    let intent = match existing {
+        Some(mut intent) => {
+            intent.set("S", "GTS_PDFA1");
+            intent
+        }
+        None => {
+            let profile = Stream::new(dictionary! { "N" => 3 }, srgb_profile());
+            let profile = document.add_object(profile);
+            dictionary! {
+                "Type" => "OutputIntent",
+                "S" => "GTS_PDFA1",
+                "OutputConditionIdentifier" => Object::string_literal(PROFILE_NAME),
+                "Info" => Object::string_literal(PROFILE_NAME),
+                "DestinationOutputProfile" => profile,
+            }
+        }
+    };
+    let catalog = document.catalog_mut()?;
+    let intents = catalog.get_mut(b"OutputIntents").and_then(Object::as_array_mut);
+    match intents {
+        Ok(intents) => intents.push(Object::Dictionary(intent)),
+        Err(_) => catalog.set("OutputIntents", vec![Object::Dictionary(intent)]),
+    }
+    // printpdf's layer configuration lacks the name PDF/A asks for
+    if let Ok(configuration) = catalog.get_mut(b"OCProperties").and_then(Object::as_dict_mut).and_then(|properties| properties.get_mut(b"D")).and_then(Object::as_dict_mut) {
+        configuration.set("Name", Object::string_literal("Layers"));
+    }
+
+    // Annotations (the --links) have to be printable
+    for object in document.objects.values_mut() {
+        if let Object::Dictionary(dictionary) = object {
+            if dictionary.get(b"Type").and_then(Object::as_name_str).ok() == Some("Annot") {
+                dictionary.set("F", 4);
+            }
+        }
+    }
+
+    // The header has to be followed by a comment of at least four bytes above 127 (here the
+    // UTF-8 encoding of "âãÏÓ"); lopdf writes the version right after "%PDF-"
+    document.version = "1.7\n%âãÏÓ".to_string();
+    Ok(())
+}
+
+// A compact ICC version 2 display profile for sRGB: D50-adapted primaries and the sRGB
+// tone curve as a 1024-entry table, shared by the three channels
+fn srgb_profile() -> Vec<u8> {
+    let xyz = |values: [f64; 3]| {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for value in values {
+            tag.extend(((value * 65536.0).round() as i32).to_be_bytes());
+        }
+        tag
+    };
+    let mut description = b"desc\0\0\0\0".to_vec();
+    description.extend((PROFILE_NAME.len() as u32 + 1).to_be_bytes());
+    description.extend(PROFILE_NAME.as_bytes());
+    description.extend([0; 1 + 4 + 4 + 2 + 1 + 67]);
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend(1024u32.to_be_bytes());
+    for i in 0..1024 {
+        let value = i as f64 / 1023.0;
+        let linear = if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) };
+        curve.extend(((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+    let tags: [(&[u8; 4], Vec<u8>); 6] = [
+        (b"desc", description),
+        (b"cprt", b"text\0\0\0\0No copyright, use freely\0".to_vec()),
+        (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+        (b"rXYZ", xyz([0.4361, 0.2225, 0.0139])),
+        (b"gXYZ", xyz([0.3851, 0.7169, 0.0971])),
+        (b"bXYZ", xyz([0.1431, 0.0606, 0.7141])),
+    ];
+
+    // Tag table: the six tags above and the three tone curves pointing at one copy
+    let table_size = 4 + 12 * (tags.len() + 3);
+    let mut data = Vec::new();
+    let mut entries = Vec::new();
+    for (signature, tag) in &tags {
+        entries.push((*signature, 128 + table_size + data.len(), tag.len()));
+        data.extend(tag);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+    let curve_offset = 128 + table_size + data.len();
+    for signature in [b"rTRC", b"gTRC", b"bTRC"] {
+        entries.push((signature, curve_offset, curve.len()));
+    }
+    data.extend(&curve);
+
+    let size = 128 + table_size + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend((size as u32).to_be_bytes());
+    profile.extend([0; 4]); // Preferred CMM
+    profile.extend([2, 0x10, 0, 0]); // Version 2.1
+    profile.extend(b"mntrRGB XYZ ");
+    profile.extend([2000u16, 1, 1, 0, 0, 0].iter().flat_map(|field| field.to_be_bytes()));
+    profile.extend(b"acsp");
+    profile.extend([0; 28]); // Platform, flags, device manufacturer and model, attributes
+    profile.extend([0; 4]); // Perceptual rendering intent
+    profile.extend(&xyz([0.9642, 1.0, 0.8249])[8..]); // D50 illuminant
+    profile.resize(128, 0);
+    profile.extend((entries.len() as u32).to_be_bytes());
+    for (signature, offset, length) in entries {
+        profile.extend(signature);
+        profile.extend((offset as u32).to_be_bytes());
+        profile.extend((length as u32).to_be_bytes());
+    }
+    profile.extend(data);
+    profile
+}
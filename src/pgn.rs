@@ -0,0 +1,580 @@
+// PGN parsing helpers
+// Splits a study export into chapters (header tags + move text) and parses
+// the move text into moves with their comments, NAGs and variations
+
+use crate::engine::Score;
+use crate::error::ParseError;
+use encoding_rs::{Decoder, DecoderResult, Encoding};
+use std::io::{self, BufRead};
+
+#[derive(Debug, Clone, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub movetext: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// A sequence of moves; comments placed before the first move are kept separately
+#[derive(Debug, Clone, Default)]
+pub struct Line {
+    pub comments: Vec<String>,
+    pub moves: Vec<MoveNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MoveNode {
+    pub san: String,
+    pub nags: Vec<u16>,
+    pub comments: Vec<String>,
+    // Alternatives to this move
+    pub variations: Vec<Line>,
+}
+
+enum Token {
+    Move(String),
+    Nag(u16),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Result,
+}
+
+// Parse a header line like [ChapterName "Some text"]; the value may contain quotes and
+// backslashes escaped with a backslash, as in [ChapterName "He said \"check\""]. Other
+// backslashes and quotes not ending the value, which some tools leave unescaped, are kept
+pub fn parse_tag_line(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let name_end = inner.find(char::is_whitespace)?;
+    let name = &inner[..name_end];
+    // Rules out embedded commands like [%csl Ge4] that start a comment line
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let mut chars = inner[name_end..].trim_start().strip_prefix('"')?.chars();
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' if chars.as_str().trim().is_empty() => return Some((name.to_string(), value)),
+            '\\' if chars.as_str().starts_with(['"', '\\']) => value.extend(chars.next()),
+            c => value.push(c),
+        }
+    }
+}
+
+// A PGN file as UTF-8 text, decoded while it is read so that large files need not fit in
+// memory. The encoding is that of a byte order mark, else the one given, else UTF-8 unless the
+// first bytes that are not ASCII are not UTF-8 either, which makes it Windows-1252 as older
+// Windows tools write. Bytes a BOM's or the given encoding does not allow are an InvalidData
+// error holding ParseError::Encoding. Line ends become "\n"
+pub struct DecodeReader<R> {
+    inner: R,
+    // None while only ASCII has been read of a file without a BOM or a given encoding
+    decoder: Option<Decoder>,
+    // Malformed bytes are an error rather than replaced
+    strict: bool,
+    // Named in the notice of a Windows-1252 file; None keeps quiet
+    name: Option<String>,
+    // Bytes taken from `inner` but not decoded yet: the start of the file while looking for a
+    // BOM, or a first non-ASCII character cut off at the end of a chunk
+    bytes: Vec<u8>,
+    text: String,
+    position: usize,
+    after_cr: bool,
+    done: bool,
+}
+
+pub fn decode_reader<R: BufRead>(mut inner: R, encoding: Option<&'static Encoding>, name: Option<&str>) -> io::Result<DecodeReader<R>> {
+    // A BOM is up to 3 bytes, which a small buffer may hand out over several fills
+    let mut bytes = Vec::with_capacity(3);
+    while bytes.len() < 3 {
+        let chunk = inner.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let length = chunk.len().min(3 - bytes.len());
+        bytes.extend_from_slice(&chunk[..length]);
+        inner.consume(length);
+    }
+    let decoder = match (Encoding::for_bom(&bytes), encoding) {
+        (Some((encoding, bom_length)), _) => {
+            bytes.drain(..bom_length);
+            Some(encoding.new_decoder_without_bom_handling())
+        }
+        (None, encoding) => encoding.map(Encoding::new_decoder_without_bom_handling),
+    };
+    Ok(DecodeReader {
+        inner,
+        strict: decoder.is_some(),
+        decoder,
+        name: name.map(str::to_string),
+        bytes,
+        text: String::new(),
+        position: 0,
+        after_cr: false,
+        done: false,
+    })
+}
+
+impl<R: BufRead> DecodeReader<R> {
+    // Decodes the next piece of the file into `text`, which stays empty at its end
+    fn refill(&mut self) -> io::Result<()> {
+        self.text.clear();
+        self.position = 0;
+        let mut decoded = String::new();
+        while decoded.is_empty() && !self.done {
+            let chunk = self.inner.fill_buf()?;
+            let last = chunk.is_empty();
+            let length = chunk.len();
+            self.bytes.extend_from_slice(chunk);
+            self.inner.consume(length);
+            if self.decoder.is_none() {
+                // ASCII reads the same in either encoding
+                let ascii = self.bytes.iter().position(|byte| !byte.is_ascii()).unwrap_or(self.bytes.len());
+                decoded.extend(self.bytes.drain(..ascii).map(char::from));
+                match std::str::from_utf8(&self.bytes) {
+                    _ if self.bytes.is_empty() => {
+                        self.done = last;
+                        continue;
+                    }
+                    // The first character is cut off; the next chunk tells
+                    Err(e) if e.error_len().is_none() && e.valid_up_to() == 0 && !last => continue,
+                    Err(e) if e.error_len().is_some() || e.valid_up_to() == 0 => {
+                        if let Some(name) = &self.name {
+                            eprintln!("{} is not UTF-8, reading it as Windows-1252 (give another encoding with --encoding)", name);
+                        }
+                        self.decoder = Some(encoding_rs::WINDOWS_1252.new_decoder_without_bom_handling());
+                    }
+                    _ => {}
+                }
+            }
+            let decoder = self.decoder.get_or_insert_with(|| encoding_rs::UTF_8.new_decoder_without_bom_handling());
+            let read = if self.strict {
+                decoded.reserve(decoder.max_utf8_buffer_length_without_replacement(self.bytes.len()).unwrap_or(self.bytes.len() * 3 + 16));
+                let (result, read) = decoder.decode_to_string_without_replacement(&self.bytes, &mut decoded, last);
+                if let DecoderResult::Malformed(..) = result {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, ParseError::Encoding { encoding: decoder.encoding().name() }));
+                }
+                read
+            } else {
+                decoded.reserve(decoder.max_utf8_buffer_length(self.bytes.len()).unwrap_or(self.bytes.len() * 3 + 16));
+                decoder.decode_to_string(&self.bytes, &mut decoded, last).1
+            };
+            self.bytes.drain(..read);
+            self.done = last;
+        }
+        // Windows ends lines with "\r\n", old Macs with "\r" alone
+        for c in decoded.chars() {
+            match c {
+                '\r' => self.text.push('\n'),
+                '\n' if self.after_cr => {}
+                c => self.text.push(c),
+            }
+            self.after_cr = c == '\r';
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> io::Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+impl<R: BufRead> BufRead for DecodeReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A piece may decode to nothing but the "\n" of a "\r\n" cut in two
+        while self.position == self.text.len() && !self.done {
+            self.refill()?;
+        }
+        Ok(&self.text.as_bytes()[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.text.len());
+    }
+}
+
+// Games read one at a time from a PGN file, so that large databases need not fit in memory;
+// a header line following move text starts the next game
+pub struct Games<R> {
+    reader: R,
+    line: String,
+    current: PgnGame,
+    done: bool,
+}
+
+pub fn read_games<R: BufRead>(reader: R) -> Games<R> {
+    Games { reader, line: String::new(), current: PgnGame::default(), done: false }
+}
+
+impl<R: BufRead> Iterator for Games<R> {
+    type Item = io::Result<PgnGame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => self.done = true,
+                Ok(_) => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            let line = self.line.trim();
+
+            // Escape mechanism lines are ignored
+            if line.starts_with('%') {
+                continue;
+            }
+
+            if let Some(tag) = parse_tag_line(line) {
+                let game = (!self.current.movetext.trim().is_empty()).then(|| std::mem::take(&mut self.current));
+                self.current.tags.push(tag);
+                if let Some(game) = game {
+                    return Some(Ok(game));
+                }
+            } else if !line.is_empty() {
+                self.current.movetext.push_str(line);
+                self.current.movetext.push('\n');
+            }
+        }
+
+        let game = std::mem::take(&mut self.current);
+        (!game.tags.is_empty() || !game.movetext.trim().is_empty()).then_some(Ok(game))
+    }
+}
+
+// Parse move text into the mainline with nested variations
+pub fn parse_movetext(text: &str) -> Line {
+    let mut stack: Vec<Line> = vec![Line::default()];
+
+    for token in tokenize(text) {
+        match token {
+            Token::Move(san) => {
+                if let Some(line) = stack.last_mut() {
+                    line.moves.push(MoveNode {
+                        san,
+                        nags: Vec::new(),
+                        comments: Vec::new(),
+                        variations: Vec::new(),
+                    });
+                }
+            }
+            Token::Nag(nag) => {
+                if let Some(node) = stack.last_mut().and_then(|line| line.moves.last_mut()) {
+                    node.nags.push(nag);
+                }
+            }
+            Token::Comment(comment) => {
+                if let Some(line) = stack.last_mut() {
+                    match line.moves.last_mut() {
+                        Some(node) => node.comments.push(comment),
+                        None => line.comments.push(comment),
+                    }
+                }
+            }
+            Token::VariationStart => stack.push(Line::default()),
+            Token::VariationEnd => close_variation(&mut stack),
+            Token::Result => break,
+        }
+    }
+
+    // Close variations left open by unbalanced parentheses
+    while stack.len() > 1 {
+        close_variation(&mut stack);
+    }
+
+    stack.pop().unwrap_or_default()
+}
+
+fn close_variation(stack: &mut Vec<Line>) {
+    if stack.len() < 2 {
+        return;
+    }
+    if let Some(variation) = stack.pop() {
+        if let Some(node) = stack.last_mut().and_then(|line| line.moves.last_mut()) {
+            node.variations.push(variation);
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            '{' => {
+                chars.next();
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            }
+            ';' => {
+                chars.next();
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::VariationStart);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::VariationEnd);
+            }
+            '$' => {
+                chars.next();
+                let mut digits = String::new();
+                while let Some(&d) = chars.peek() {
+                    if !d.is_ascii_digit() {
+                        break;
+                    }
+                    digits.push(d);
+                    chars.next();
+                }
+                if let Ok(nag) = digits.parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{};()$".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                push_word_tokens(&word, &mut tokens);
+            }
+        }
+    }
+
+    tokens
+}
+
+// Turn a bare word into move/NAG/result tokens, dropping move numbers like "12." or "12..."
+fn push_word_tokens(word: &str, tokens: &mut Vec<Token>) {
+    if matches!(word, "1-0" | "0-1" | "1/2-1/2" | "*") {
+        tokens.push(Token::Result);
+        return;
+    }
+
+    // Castling is sometimes written with zeros, which would look like a move number
+    let word = if word.starts_with("0-0") {
+        word.replace('0', "O")
+    } else {
+        word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.').to_string()
+    };
+    if word.is_empty() {
+        return;
+    }
+
+    // Traditional suffix annotations map onto their NAG equivalents
+    let san = word.trim_end_matches(['!', '?']);
+    let nag = match &word[san.len()..] {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    };
+
+    if !san.is_empty() {
+        tokens.push(Token::Move(san.to_string()));
+    }
+    if let Some(nag) = nag {
+        tokens.push(Token::Nag(nag));
+    }
+}
+
+// Move text as printed in books: move numbers, annotation symbols, comments without
+// embedded commands and variations in parentheses. `fullmove` and `white_to_move`
+// describe the position before the first move
+pub fn annotated_text(comments: &[String], moves: &[MoveNode], fullmove: u32, white_to_move: bool) -> String {
+    let mut words = Vec::new();
+    push_annotated_line(comments, moves, fullmove, white_to_move, &mut words);
+    words.join(" ")
+}
+
+fn push_annotated_line(comments: &[String], moves: &[MoveNode], mut fullmove: u32, mut white_to_move: bool, words: &mut Vec<String>) {
+    words.extend(comments.iter().map(|comment| strip_commands(comment)).filter(|comment| !comment.is_empty()));
+
+    // Black's moves are numbered at the start and after anything interrupting the moves
+    let mut interrupted = true;
+    for node in moves {
+        let mut san = if white_to_move {
+            format!("{}. {}", fullmove, node.san)
+        } else if interrupted {
+            format!("{}... {}", fullmove, node.san)
+        } else {
+            node.san.clone()
+        };
+
+        // Move assessments attach to the move, position assessments follow it
+        let mut symbols = Vec::new();
+        for &nag in &node.nags {
+            match nag_symbol(nag) {
+                Some(symbol) if nag <= 6 => san.push_str(symbol),
+                Some(symbol) => symbols.push(symbol.to_string()),
+                None if matches!(nag, 220 | 221) => {} // Diagram markers
+                None => symbols.push(format!("${}", nag)),
+            }
+        }
+        words.push(san);
+        words.extend(symbols);
+
+        let comments: Vec<String> = node.comments.iter().map(|comment| strip_commands(comment)).filter(|comment| !comment.is_empty()).collect();
+        interrupted = !comments.is_empty() || !node.variations.is_empty();
+        words.extend(comments);
+        for variation in &node.variations {
+            let mut variation_words = Vec::new();
+            push_annotated_line(&variation.comments, &variation.moves, fullmove, white_to_move, &mut variation_words);
+            if !variation_words.is_empty() {
+                words.push(format!("({})", variation_words.join(" ")));
+            }
+        }
+
+        if !white_to_move {
+            fullmove += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+}
+
+// Common annotation symbols in plain ASCII; None for NAGs without one
+pub fn nag_symbol(nag: u16) -> Option<&'static str> {
+    Some(match nag {
+        1 => "!",
+        2 => "?",
+        3 => "!!",
+        4 => "??",
+        5 => "!?",
+        6 => "?!",
+        7 => "(forced)",
+        10 => "=",
+        13 => "(unclear)",
+        14 => "+=",
+        15 => "=+",
+        16 => "+/-",
+        17 => "-/+",
+        18 => "+-",
+        19 => "-+",
+        22 | 23 => "(zugzwang)",
+        32 | 33 => "(development)",
+        36 | 37 => "(initiative)",
+        40 | 41 => "(attack)",
+        132 | 133 => "(counterplay)",
+        138 | 139 => "(time trouble)",
+        146 => "N",
+        _ => return None,
+    })
+}
+
+// Remove embedded commands such as [%csl Ge4] or [%clk 0:01:00] from a comment
+pub fn strip_commands(comment: &str) -> String {
+    let mut text = String::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        text.push_str(&rest[..start]);
+        rest = rest[start..].find(']').map_or("", |end| &rest[start + end + 1..]);
+    }
+    text.push_str(rest);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// The evaluation of an [%eval 0.35] or [%eval #3] command in the comments, from White's point
+// of view as Lichess writes it
+pub fn comment_eval(comments: &[String]) -> Option<Score> {
+    comments.iter().find_map(|comment| {
+        let start = comment.find("[%eval ")? + "[%eval ".len();
+        let end = comment[start..].find(']')?;
+        Score::parse(&comment[start..start + end])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Read};
+
+    // Reads the bytes through a buffer of `capacity` bytes, so chunks end at awkward places
+    fn decode(bytes: &[u8], capacity: usize, encoding: Option<&'static Encoding>) -> io::Result<String> {
+        let mut text = String::new();
+        decode_reader(BufReader::with_capacity(capacity, bytes), encoding, None)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn tag_values_with_quotes_and_backslashes() {
+        let tag = |line: &str| parse_tag_line(line).map(|(name, value)| format!("{}={}", name, value));
+        assert_eq!(tag(r#"[Event "He said \"check\""]"#).as_deref(), Some(r#"Event=He said "check""#));
+        assert_eq!(tag(r#"[Site "C:\\"]"#).as_deref(), Some(r"Site=C:\"));
+        assert_eq!(tag(r#"[ChapterName "a "quoted" word"]"#).as_deref(), Some(r#"ChapterName=a "quoted" word"#));
+        assert_eq!(tag(r#"[Annotator "C:\games\x"]"#).as_deref(), Some(r"Annotator=C:\games\x"));
+        assert_eq!(tag(r#"[White ""]"#).as_deref(), Some("White="));
+        assert_eq!(tag(r#"[Event "no end]"#), None);
+        assert_eq!(tag("[%csl Ge4]"), None);
+        assert_eq!(tag("1. e4 e5"), None);
+    }
+
+    #[test]
+    fn line_ends_become_newlines() {
+        for capacity in 1..=4 {
+            assert_eq!(decode(b"a\r\nb\rc\nd\r\r\ne\r", capacity, None).unwrap(), "a\nb\nc\nd\n\ne\n", "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn bom_is_found_across_small_fills() {
+        for capacity in 1..=4 {
+            assert_eq!(decode(b"\xef\xbb\xbf[Event \"x\"]", capacity, None).unwrap(), "[Event \"x\"]");
+            assert_eq!(decode(b"\xff\xfeO\0k\0", capacity, None).unwrap(), "Ok");
+        }
+        // Shorter than a BOM
+        assert_eq!(decode(b"1.", 1, None).unwrap(), "1.");
+        assert_eq!(decode(b"", 1, None).unwrap(), "");
+    }
+
+    #[test]
+    fn windows_1252_is_detected() {
+        for capacity in 1..=8 {
+            assert_eq!(decode(b"{ Caf\xe9 } 1. e4", capacity, None).unwrap(), "{ Café } 1. e4");
+            // A lead byte at the end of a chunk that the next byte does not continue
+            assert_eq!(decode(b"Caf\xe9 ok", capacity, None).unwrap(), "Café ok");
+            assert_eq!(decode(b"Caf\xe9", capacity, None).unwrap(), "Café");
+        }
+    }
+
+    #[test]
+    fn utf8_characters_cut_at_chunk_ends() {
+        let text = "{ Café → Ünïcode 象棋 } 1. e4";
+        for capacity in 1..=8 {
+            assert_eq!(decode(text.as_bytes(), capacity, None).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn given_encoding_is_strict() {
+        let error = decode(b"Caf\xe9", 4, Some(encoding_rs::UTF_8)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(ParseError::from(error), ParseError::Encoding { encoding: "UTF-8" }));
+        assert_eq!(decode(b"Caf\xe9", 4, Some(encoding_rs::WINDOWS_1252)).unwrap(), "Café");
+    }
+}
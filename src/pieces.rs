@@ -36,6 +36,41 @@ pub fn get_piece_png_data(piece: char) -> Option<&'static [u8]> {
     }
 }
 
+// Vector pieces (45x45 SVG documents) for the SVG and HTML exports
+pub const WK_SVG: &str = include_str!("../assets/svg/wK.svg");
+pub const WQ_SVG: &str = include_str!("../assets/svg/wQ.svg");
+pub const WR_SVG: &str = include_str!("../assets/svg/wR.svg");
+pub const WB_SVG: &str = include_str!("../assets/svg/wB.svg");
+pub const WN_SVG: &str = include_str!("../assets/svg/wN.svg");
+pub const WP_SVG: &str = include_str!("../assets/svg/wP.svg");
+pub const BK_SVG: &str = include_str!("../assets/svg/bK.svg");
+pub const BQ_SVG: &str = include_str!("../assets/svg/bQ.svg");
+pub const BR_SVG: &str = include_str!("../assets/svg/bR.svg");
+pub const BB_SVG: &str = include_str!("../assets/svg/bB.svg");
+pub const BN_SVG: &str = include_str!("../assets/svg/bN.svg");
+pub const BP_SVG: &str = include_str!("../assets/svg/bP.svg");
+
+// The drawing inside a piece's <svg> element, for placing it into a larger SVG
+pub fn get_piece_svg_body(piece: char) -> Option<&'static str> {
+    let svg = match piece {
+        'K' => WK_SVG,
+        'Q' => WQ_SVG,
+        'R' => WR_SVG,
+        'B' => WB_SVG,
+        'N' => WN_SVG,
+        'P' => WP_SVG,
+        'k' => BK_SVG,
+        'q' => BQ_SVG,
+        'r' => BR_SVG,
+        'b' => BB_SVG,
+        'n' => BN_SVG,
+        'p' => BP_SVG,
+        _ => return None,
+    };
+    let (_, body) = svg.split_once('>')?;
+    Some(body.rsplit_once("</svg>")?.0)
+}
+
 // Example of how to convert PNG to literal array (for WK_PNG):
 // 1. Run: xxd -i assets/png/wK.png
 // 2. Replace: unsigned char assets_png_wK_png[] = { with pub const WK_PNG: &[u8] = &[
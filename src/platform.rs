@@ -0,0 +1,207 @@
+// What the library needs of the machine it runs on: reading the PGN and writing the PDF
+// (Storage) and HTTP requests (Network). Native builds use the disk and reqwest; the
+// WebAssembly build has neither, keeps its files in memory and cannot download. Everything
+// that differs between the two is in this file
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub trait Storage: Send + Sync {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    // The file as a reader, for files too large to read whole (PGN databases)
+    fn open(&self, path: &str) -> io::Result<Box<dyn BufRead>>;
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()>;
+
+    // A directory for exports of one file per diagram; nothing to do where names are just keys
+    fn create_dir_all(&self, path: &str) -> io::Result<()>;
+}
+
+// Files on disk (the default)
+pub struct DiskStorage;
+
+impl Storage for DiskStorage {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<dyn BufRead>> {
+        Ok(Box::new(io::BufReader::new(std::fs::File::open(path)?)))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::write(path, data)
+    }
+
+    fn create_dir_all(&self, path: &str) -> io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+}
+
+// Files in memory under their names, e.g. a pasted PGN in and the PDF out
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn insert(&self, path: &str, data: Vec<u8>) {
+        self.files.lock().unwrap().insert(path.to_string(), data);
+    }
+
+    // Removes the file and returns its contents
+    pub fn take(&self, path: &str) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().remove(path)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files.lock().unwrap().get(path).cloned().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No file '{}'", path)))
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<dyn BufRead>> {
+        Ok(Box::new(io::Cursor::new(self.read(path)?)))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.insert(path, data.to_vec());
+        Ok(())
+    }
+
+    fn create_dir_all(&self, _path: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Reads from disk (the download cache, inputs) and keeps what is written, the PDF (or the study
+// when there is no cache directory), in memory: for runs handing the PDF to their caller
+#[derive(Default)]
+pub struct OutputStorage {
+    pub written: MemoryStorage,
+}
+
+impl Storage for OutputStorage {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.written.read(path).or_else(|_| std::fs::read(path))
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<dyn BufRead>> {
+        self.written.open(path).or_else(|_| DiskStorage.open(path))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.written.write(path, data)
+    }
+
+    fn create_dir_all(&self, path: &str) -> io::Result<()> {
+        self.written.create_dir_all(path)
+    }
+}
+
+// A whole response: status code, headers and body
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    // The first header of this name, in any case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+pub trait Network: Send + Sync {
+    // A GET with these request headers. An error of kind InvalidInput (a malformed address)
+    // fails the same way every time; the others, such as timeouts, may be retried
+    fn get(&self, url: &str, headers: &[(String, String)]) -> io::Result<HttpResponse>;
+}
+
+// The Network of a run without Options::with_network: reqwest with the User-Agent, the
+// --timeout limits and --proxy. Without --proxy, reqwest takes the proxy from HTTP_PROXY,
+// HTTPS_PROXY or ALL_PROXY and skips the hosts in NO_PROXY
+#[cfg(not(target_arch = "wasm32"))]
+pub fn http_network(user_agent: &str, timeout: Duration, proxy: Option<&str>) -> Result<Arc<dyn Network>> {
+    let mut builder = reqwest::blocking::Client::builder().user_agent(user_agent).connect_timeout(timeout).timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(Arc::new(HttpNetwork { client: builder.build()? }))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn http_network(_user_agent: &str, _timeout: Duration, _proxy: Option<&str>) -> Result<Arc<dyn Network>> {
+    Err(anyhow::anyhow!("Downloads are not available in the WebAssembly build; pass the PGN instead"))
+}
+
+// Whether reqwest accepts `url` as a proxy
+#[cfg(not(target_arch = "wasm32"))]
+pub fn is_valid_proxy(url: &str) -> bool {
+    reqwest::Proxy::all(url).is_ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn is_valid_proxy(_url: &str) -> bool {
+    false
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct HttpNetwork {
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Network for HttpNetwork {
+    fn get(&self, url: &str, headers: &[(String, String)]) -> io::Result<HttpResponse> {
+        let mut request = self.client.get(url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let mut response = request.send().map_err(|e| match e.is_builder() {
+            true => io::Error::new(io::ErrorKind::InvalidInput, e),
+            false => io::Error::other(e),
+        })?;
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        // Read in pieces, so that the timeout limits each wait for data rather than the whole
+        // download of a large study
+        let mut body = Vec::new();
+        io::Read::read_to_end(&mut response, &mut body)?;
+        Ok(HttpResponse { status: response.status().as_u16(), headers, body })
+    }
+}
+
+// Time since 1970; WebAssembly has no system clock, so there it is the browser's
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_time() -> Duration {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn unix_time() -> Duration {
+    Duration::from_millis(js_sys::Date::now() as u64)
+}
+
+// Tells apart runs at the same time; WebAssembly has a single one
+#[cfg(not(target_arch = "wasm32"))]
+pub fn process_id() -> u32 {
+    std::process::id()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn process_id() -> u32 {
+    0
+}
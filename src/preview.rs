@@ -0,0 +1,187 @@
+// --preview-server: instead of writing the PDF, a page at http://localhost:8000/ shows its
+// diagram pages as SVG images, for trying out layout options. The --config files are checked
+// every second; when one changes, the arguments are parsed again and the pages rendered anew,
+// and the browser reloads. The study is downloaded once. The images follow the PDF's layout
+// (page size, grid or template, board size and colors, captions); solution and schedule
+// pages, template sidebars, logos and other extras are left out
+
+use crate::{BOARD_DESC_GAP, BOARD_IMAGE_SIZE, BOARD_SIZE, Options, SVG_COORDINATE_MARGIN, SVG_SQUARE, SolutionsMode, Source, StudyData};
+use anyhow::{Result, anyhow};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const CONFIG_CHECK: Duration = Duration::from_secs(1);
+
+// Asks for the version every second and reloads when the pages have changed
+const RELOAD_SCRIPT: &str = "
+setInterval(async () => {
+  try {
+    const response = await fetch('/version');
+    if ((await response.text()) !== String(version)) location.reload();
+  } catch (e) {}
+}, 1000);
+";
+
+const PAGE_CSS: &str = "
+body { background: #666; margin: 0; padding: 1em; font-family: sans-serif; }
+img { display: block; margin: 0 auto 1em; max-width: 100%; box-shadow: 0 0 0.5em #333; }
+pre { background: #fee; color: #900; padding: 1em; white-space: pre-wrap; }
+";
+
+// The pages as SVG documents, or the error that stopped them; `version` counts the renderings
+struct Rendering {
+    version: u64,
+    pages: Result<Vec<String>, String>,
+}
+
+pub fn serve_preview(args: &[String], port: u16, options: &Options) -> Result<()> {
+    let Source::Study(study_ids) = &options.source else { return Err(anyhow!("--preview-server shows one study")) };
+    let study_data = crate::fetch_lichess_study(&study_ids[0], options)?;
+    let rendering = Mutex::new(Rendering { version: 1, pages: render(&study_data, options) });
+
+    let server = tiny_http::Server::http(("127.0.0.1", port)).map_err(|e| anyhow!("Cannot listen on port {}: {}", port, e))?;
+    println!("Previewing {} at http://localhost:{}/", study_data.name, port);
+    if !options.config_files.is_empty() {
+        println!("Edit {} to render the pages again", options.config_files.join(", "));
+    }
+    std::thread::scope(|scope| {
+        let (study_data, rendering) = (&study_data, &rendering);
+        scope.spawn(move || watch_config_files(args, &options.config_files, study_data, rendering));
+        for request in server.incoming_requests() {
+            let response = respond(request.url(), &rendering.lock().unwrap());
+            if let Err(e) = request.respond(response) {
+                eprintln!("Warning: cannot send the response: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+// Renders the pages again with the arguments parsed anew whenever a config file is modified
+fn watch_config_files(args: &[String], config_files: &[String], study_data: &StudyData, rendering: &Mutex<Rendering>) {
+    let modified = || config_files.iter().map(|path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok()).collect::<Vec<Option<SystemTime>>>();
+    let mut last = modified();
+    loop {
+        std::thread::sleep(CONFIG_CHECK);
+        let current = modified();
+        if current == last {
+            continue;
+        }
+        last = current;
+        let pages = crate::parse_args(args).map_err(|e| e.to_string()).and_then(|options| render(study_data, &options));
+        match &pages {
+            Ok(pages) => println!("Rendered {} pages", pages.len()),
+            Err(e) => eprintln!("Warning: {}", e),
+        }
+        let mut rendering = rendering.lock().unwrap();
+        *rendering = Rendering { version: rendering.version + 1, pages };
+    }
+}
+
+// The positions selected as for the PDF, on as many pages as it has diagram pages
+fn render(study_data: &StudyData, options: &Options) -> Result<Vec<String>, String> {
+    let mut study_data = study_data.clone();
+    crate::prepare_positions(&mut study_data, options).map_err(|e| e.to_string())?;
+    let options = &options.fitted_to(study_data.positions.len());
+    let pages = crate::diagram_page_ranges(&study_data.positions, options);
+    // Page numbers as printed: behind every diagram page with --solutions verso
+    let step = if options.pdf.solutions() == Some(SolutionsMode::Verso) { 2 } else { 1 };
+    let (_, page_count) = crate::page_plan(&study_data, options);
+    Ok(pages
+        .into_iter()
+        .enumerate()
+        .map(|(page, range)| {
+            let positions = &study_data.positions[range];
+            page_svg(crate::running_header(&study_data, positions, options), positions, step * page + 1, page_count, options)
+        })
+        .collect())
+}
+
+// One page in mm, drawn where write_pdf draws: the title, the boards with their coordinates,
+// captions and labels, and the page number
+fn page_svg(title: &str, positions: &[crate::ChessPosition], page: usize, page_count: usize, options: &Options) -> String {
+    let (width, height) = (options.pdf.page_width(), options.pdf.page_height());
+    // Font sizes are in points
+    let text = |x: f32, y: f32, points: f32, anchor: &str, content: &str| {
+        format!(
+            "<text x=\"{x}\" y=\"{y}\" font-family=\"{}\" font-size=\"{}\" text-anchor=\"{anchor}\">{}</text>\n",
+            crate::SVG_FONT_FAMILY,
+            points * 25.4 / 72.0,
+            crate::escape_xml(content)
+        )
+    };
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}mm\" height=\"{height}mm\" viewBox=\"0 0 {width} {height}\">\n<rect width=\"{width}\" height=\"{height}\" fill=\"white\"/>\n"
+    );
+    // A template's title and page number are centered in their regions, if it has them
+    let middle = |region: crate::Region, points: f32| (region.x + region.width / 2.0, region.y + region.height / 2.0 + points * 25.4 / 72.0 * 0.35);
+    let (title_at, page_at) = match options.pdf.template() {
+        Some(template) => (template.title.map(|region| middle(region, 18.0)), template.footer.map(|region| middle(region, 14.0))),
+        None => (Some((width / 2.0, 25.0)), Some((width / 2.0, height - 10.0))),
+    };
+    if let Some((x, y)) = title_at {
+        svg.push_str(&text(x, y, 18.0, "middle", title));
+    }
+    if let Some((x, y)) = page_at {
+        svg.push_str(&text(x, y, 14.0, "middle", &format!("{}/{}", page, page_count)));
+    }
+
+    let scale = options.board_scale();
+    let board_size = BOARD_IMAGE_SIZE * scale;
+    let units = board_size / (8 * SVG_SQUARE) as f32;
+    for (i, pos) in positions.iter().enumerate() {
+        let (x, y) = crate::grid_cell(i, &options.pdf);
+        // Shrunk boards keep their top edge
+        let top = y + BOARD_SIZE - BOARD_IMAGE_SIZE;
+        svg.push_str(&format!(
+            "<g transform=\"translate({} {}) scale({})\">\n{}</g>\n",
+            x - SVG_COORDINATE_MARGIN as f32 * units,
+            top,
+            units,
+            crate::board_svg(pos, options, true)
+        ));
+
+        let description = if options.hide_captions { String::new() } else { pos.caption() };
+        let lines = match description.split_once(':') {
+            Some((first, second)) => vec![pos.numbered(options, &format!("{}:", first)), second.trim().to_string()],
+            None => vec![pos.numbered(options, &description)],
+        };
+        let mut line_y = y + BOARD_SIZE + BOARD_DESC_GAP - BOARD_IMAGE_SIZE * (1.0 - scale);
+        for line in lines.iter().filter(|line| !line.is_empty()) {
+            svg.push_str(&text(x, line_y, 11.0, "start", line));
+            line_y += 5.0;
+        }
+        for label in pos.caption_labels(options) {
+            svg.push_str(&text(x, line_y, 9.0, "start", &label));
+            line_y += 4.0;
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn respond(url: &str, rendering: &Rendering) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    let path = url.split_once('?').map_or(url, |(path, _)| path);
+    let page = path.strip_prefix("/page/").and_then(|file| file.strip_suffix(".svg")).and_then(|number| number.parse::<usize>().ok());
+    let (content_type, body) = match (path, page, &rendering.pages) {
+        ("/", _, _) => ("text/html; charset=utf-8", index_html(rendering)),
+        ("/version", _, _) => ("text/plain", rendering.version.to_string()),
+        (_, Some(page), Ok(pages)) if (1..=pages.len()).contains(&page) => ("image/svg+xml", pages[page - 1].clone()),
+        _ => return tiny_http::Response::from_string("Not found\n").with_status_code(404),
+    };
+    let header = tiny_http::Header::from_bytes("Content-Type", content_type).expect("valid header");
+    tiny_http::Response::from_string(body).with_header(header)
+}
+
+// The pages one below the other, or the error; the version in the image addresses keeps the
+// browser from showing pages it cached before
+fn index_html(rendering: &Rendering) -> String {
+    let content = match &rendering.pages {
+        Ok(pages) => (1..=pages.len()).map(|page| format!("<img src=\"/page/{0}.svg?v={1}\" alt=\"Page {0}\">\n", page, rendering.version)).collect(),
+        Err(e) => format!("<pre>{}</pre>\n", crate::html::escape_html(e)),
+    };
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>fen2pdf preview</title>\n<style>{}</style>\n</head>\n<body>\n{}<script>\nconst version = {};\n{}</script>\n</body>\n</html>\n",
+        PAGE_CSS, content, rendering.version, RELOAD_SCRIPT
+    )
+}
@@ -0,0 +1,20 @@
+// Progress reports for programs embedding fen2pdf: a GUI or web frontend registers a callback
+// with Options::with_progress (which can forward to a channel) and hears of each phase as it
+// finishes. The command line prints its own messages and registers none
+
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    // The study's PGN is on disk: downloaded, unchanged in the cache, or read offline
+    Downloaded { study_id: String },
+    // The PGN was read: its chapters (or games) and the positions found in them
+    Parsed { chapters: usize, positions: usize },
+    // A page of a PDF was drawn, counting from 1; solution and schedule pages included
+    RenderedPage { page: usize, pages: usize },
+    // A PDF was written
+    Written { filename: String },
+}
+
+// The registered callback; called from download threads too, hence Send + Sync
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
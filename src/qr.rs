@@ -0,0 +1,35 @@
+// QR codes for --qr: the dark modules of a code as horizontal runs, so that a code is
+// drawn as a few dozen rectangles rather than one per module
+
+use anyhow::{Result, anyhow};
+use qrcodegen::{QrCode, QrCodeEcc};
+
+pub struct QrRuns {
+    // Modules per side, without the quiet zone
+    pub size: usize,
+    // (row, first column, length) of each run of dark modules, row 0 at the top
+    pub runs: Vec<(usize, usize, usize)>,
+}
+
+// Low error correction keeps the modules large for a URL; qrcodegen raises the level when
+// that fits in the same number of modules
+pub fn encode(text: &str) -> Result<QrRuns> {
+    let code = QrCode::encode_text(text, QrCodeEcc::Low).map_err(|e| anyhow!("Cannot encode '{}' as a QR code: {}", text, e))?;
+    let size = code.size() as usize;
+    let mut runs = Vec::new();
+    for row in 0..size {
+        let mut column = 0;
+        while column < size {
+            if !code.get_module(column as i32, row as i32) {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < size && code.get_module(column as i32, row as i32) {
+                column += 1;
+            }
+            runs.push((row, start, column - start));
+        }
+    }
+    Ok(QrRuns { size, runs })
+}
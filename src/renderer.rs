@@ -0,0 +1,70 @@
+// Board renderers: what draws a position's board for the PDF, the PNG export and the Anki
+// deck. The built-in raster renderer paints the piece PNGs; the vector one draws the SVG
+// piece set, and library users can plug in their own through Options::with_renderer
+
+use crate::{BOARD_IMAGE_PX, ChessPosition, Options};
+use anyhow::{Result, anyhow};
+
+// A rendered board without coordinates, which the PDF draws around it
+pub enum BoardArtifact {
+    // RGB pixels, row by row
+    Raster { width: u32, height: u32, rgb_data: Vec<u8> },
+    // A square SVG document, rasterized at BOARD_IMAGE_PX for the PDF
+    Svg(String),
+}
+
+pub trait BoardRenderer: Send + Sync {
+    // Tells the renderer's images apart in --cache-dir; change it when the drawing changes
+    fn name(&self) -> &str;
+
+    // The board of the position, from White's side or flipped as `pos.flipped` says
+    fn render(&self, pos: &ChessPosition, options: &Options) -> Result<BoardArtifact>;
+}
+
+// The piece PNGs on a BOARD_IMAGE_PX bitmap (the default)
+pub struct RasterRenderer;
+
+impl BoardRenderer for RasterRenderer {
+    fn name(&self) -> &str {
+        "raster"
+    }
+
+    fn render(&self, pos: &ChessPosition, options: &Options) -> Result<BoardArtifact> {
+        let (width, height, rgb_data) = crate::generate_board_rgb_data(pos, options)?;
+        Ok(BoardArtifact::Raster { width, height, rgb_data })
+    }
+}
+
+// The SVG piece set, as in the --export svg images
+pub struct VectorRenderer;
+
+impl BoardRenderer for VectorRenderer {
+    fn name(&self) -> &str {
+        "vector"
+    }
+
+    fn render(&self, pos: &ChessPosition, options: &Options) -> Result<BoardArtifact> {
+        Ok(BoardArtifact::Svg(crate::board_svg(pos, options, false)))
+    }
+}
+
+impl BoardArtifact {
+    // Width, height and RGB data; SVG is scaled to BOARD_IMAGE_PX wide on white
+    pub fn into_rgb(self) -> Result<(u32, u32, Vec<u8>)> {
+        use usvg::{TreeParsing, TreePostProc};
+
+        let svg = match self {
+            BoardArtifact::Raster { width, height, rgb_data } => return Ok((width, height, rgb_data)),
+            BoardArtifact::Svg(svg) => svg,
+        };
+        let mut tree = usvg::Tree::from_str(&svg, &usvg::Options::default())?;
+        // Boards have no text, so no fonts are needed
+        tree.postprocess(usvg::PostProcessingSteps::default(), &usvg::fontdb::Database::new());
+        let scale = BOARD_IMAGE_PX as f32 / tree.size.width();
+        let height = (tree.size.height() * scale).round() as u32;
+        let mut pixmap = tiny_skia::Pixmap::new(BOARD_IMAGE_PX, height).ok_or_else(|| anyhow!("Invalid board SVG size"))?;
+        pixmap.fill(tiny_skia::Color::WHITE);
+        resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+        Ok((BOARD_IMAGE_PX, height, crate::pixmap_to_rgb(&pixmap)))
+    }
+}
@@ -0,0 +1,79 @@
+// Byte-identical output for the same input (--reproducible): printpdf keeps the images of a
+// page in a hash map, so their objects and the page resources come out in a different order
+// on every run, and its file identifier is not derived from the content
+
+use printpdf::lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+use std::collections::{HashMap, HashSet};
+use crate::linearize::{collect_references, renumber};
+
+// Sorts all dictionary keys, numbers the objects in the order they are reached from the
+// trailer and sets the file identifier to a digest of the result
+pub fn make_reproducible(document: &mut Document) {
+    for object in document.objects.values_mut() {
+        sort_keys(object);
+    }
+
+    let mut order: Vec<ObjectId> = [b"Root".as_slice(), b"Info"]
+        .iter()
+        .filter_map(|key| document.trailer.get(key).and_then(Object::as_reference).ok())
+        .collect();
+    let mut seen: HashSet<ObjectId> = order.iter().copied().collect();
+    let mut i = 0;
+    while i < order.len() {
+        let mut references = Vec::new();
+        if let Ok(object) = document.get_object(order[i]) {
+            collect_references(object, &mut references);
+        }
+        for id in references {
+            if document.objects.contains_key(&id) && seen.insert(id) {
+                order.push(id);
+            }
+        }
+        i += 1;
+    }
+    // Anything unreachable keeps its relative order at the end
+    order.extend(document.objects.keys().filter(|id| !seen.contains(id)));
+
+    let numbers: HashMap<ObjectId, ObjectId> = order.iter().enumerate().map(|(i, &id)| (id, (i as u32 + 1, 0))).collect();
+    let objects = std::mem::take(&mut document.objects);
+    document.objects = objects
+        .into_iter()
+        .map(|(id, mut object)| {
+            renumber(&mut object, &numbers);
+            (numbers[&id], object)
+        })
+        .collect();
+    for (_, value) in document.trailer.iter_mut() {
+        renumber(value, &numbers);
+    }
+    document.max_id = numbers.len() as u32;
+
+    let mut digest = md5::Context::new();
+    for object in document.objects.values() {
+        match object {
+            Object::Stream(stream) => {
+                digest.consume(format!("{:?}", stream.dict));
+                digest.consume(&stream.content);
+            }
+            object => digest.consume(format!("{:?}", object)),
+        }
+    }
+    let id = digest.compute().to_vec();
+    document.trailer.set("ID", vec![Object::String(id.clone(), StringFormat::Hexadecimal), Object::String(id, StringFormat::Hexadecimal)]);
+}
+
+fn sort_keys(object: &mut Object) {
+    match object {
+        Object::Array(items) => items.iter_mut().for_each(sort_keys),
+        Object::Dictionary(dictionary) => sort_dictionary(dictionary),
+        Object::Stream(stream) => sort_dictionary(&mut stream.dict),
+        _ => {}
+    }
+}
+
+fn sort_dictionary(dictionary: &mut Dictionary) {
+    dictionary.iter_mut().for_each(|(_, value)| sort_keys(value));
+    let mut entries: Vec<(Vec<u8>, Object)> = dictionary.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    *dictionary = entries.into_iter().collect();
+}
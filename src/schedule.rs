@@ -0,0 +1,85 @@
+// Review schedule for using a printed puzzle set as a spaced repetition system:
+// Leitner-style intervals that roughly double after each successful review
+
+use anyhow::{Result, anyhow};
+use std::fmt;
+
+// Days after the start date on which every exercise is reviewed
+pub const REVIEW_INTERVALS: [u32; 6] = [1, 3, 7, 14, 30, 60];
+
+// A calendar date, stored as days since 1970-01-01
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Date {
+    days: i64,
+}
+
+impl Date {
+    // The (UTC) date of a time in seconds since 1970
+    pub fn from_unix_time(seconds: u64) -> Self {
+        Date { days: (seconds / 86_400) as i64 }
+    }
+
+    // YYYY-MM-DD
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid date '{}', expected YYYY-MM-DD", text);
+        let fields: Vec<&str> = text.split('-').collect();
+        let [year, month, day] = fields[..] else {
+            return Err(invalid());
+        };
+        let year: i64 = year.parse().map_err(|_| invalid())?;
+        let month: i64 = month.parse().map_err(|_| invalid())?;
+        let day: i64 = day.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+            return Err(invalid());
+        }
+        Ok(Date { days: days_from_civil(year, month, day) })
+    }
+
+    pub fn add_days(self, days: u32) -> Self {
+        Date { days: self.days + days as i64 }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.days);
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Proleptic Gregorian calendar conversions, counting years from March so that
+// the leap day comes last (Howard Hinnant's algorithms)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
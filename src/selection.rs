@@ -0,0 +1,275 @@
+// Which positions end up in the document and in what order: analysis, filters, sorting,
+// slicing and shuffling
+
+use crate::{ChessPosition, ENDGAME_MAX_PIECES, GamePhase, Options, Storage, difficulty, engine, mate, platform, tablebase};
+use crate::args::read_text;
+use crate::board::parse_fen;
+use crate::document::diagram_page_ranges;
+use crate::download::network;
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+
+// Run the engine over every position it understands (standard chess and Chess960)
+// that has not been analysed yet
+pub fn analyse_positions(positions: &mut [ChessPosition], config: &engine::EngineConfig) -> Result<()> {
+    let analysable: Vec<usize> = (0..positions.len())
+        .filter(|&i| positions[i].has_standard_material() && positions[i].analysis.is_none())
+        .collect();
+    if analysable.is_empty() {
+        return Ok(());
+    }
+    println!("Analysing positions with {} (depth {})...", config.path, config.depth);
+    let jobs: Vec<engine::EngineJob> = analysable
+        .iter()
+        .map(|&i| engine::EngineJob {
+            fen: positions[i].fen.clone(),
+            chess960: positions[i].is_chess960(),
+        })
+        .collect();
+    
+    let analyses = engine::analyse_all(config, &jobs)?;
+    let analysed = analyses.iter().filter(|analysis| analysis.is_some()).count();
+    for (i, analysis) in analysable.into_iter().zip(analyses) {
+        positions[i].analysis = analysis;
+    }
+    println!("Analysed {} of {} positions", analysed, positions.len());
+    Ok(())
+}
+
+// Tablebase results for endgames of up to seven pieces; failed lookups are reported and skipped
+pub fn probe_tablebase(positions: &mut [ChessPosition], options: &Options) -> Result<()> {
+    let network = network(options)?;
+    for pos in positions.iter_mut().filter(|pos| pos.has_standard_material() && tablebase::is_covered(&pos.fen)) {
+        match tablebase::probe(network.as_ref(), &pos.fen) {
+            Ok(result) => pos.tablebase = result,
+            Err(e) => eprintln!("Warning: tablebase lookup failed for '{}': {}", pos.fen, e),
+        }
+    }
+    let found = positions.iter().filter(|pos| pos.tablebase.is_some()).count();
+    println!("Found {} tablebase results", found);
+    Ok(())
+}
+
+// Mate-in-N from the engine's score when the side to move mates, otherwise from a short search
+pub fn label_mates(positions: &mut [ChessPosition], max_moves: u32) {
+    for pos in positions.iter_mut().filter(|pos| pos.has_standard_material()) {
+        pos.mate = match &pos.analysis {
+            Some(analysis) => match analysis.score {
+                engine::Score::Mate(moves) if (moves > 0) != pos.black_to_move => Some(mate::Mate {
+                    moves: moves.unsigned_abs(),
+                    first_move: analysis.pv[0].clone(),
+                }),
+                _ => None,
+            },
+            None => mate::find_mate(&pos.fen, pos.is_chess960(), max_moves),
+        };
+    }
+    let mates = positions.iter().filter(|pos| pos.mate.is_some()).count();
+    println!("Found {} forced mates", mates);
+}
+
+// First move and number of moves of the solution, from the mate labels or the engine,
+// or else from a short mate search
+fn solution_outline(pos: &ChessPosition, max_moves: u32) -> Option<(String, u32)> {
+    if let Some(mate) = &pos.mate {
+        return Some((mate.first_move.clone(), mate.moves));
+    }
+    if let Some(analysis) = &pos.analysis {
+        let moves = match analysis.score {
+            engine::Score::Mate(moves) => moves.unsigned_abs(),
+            engine::Score::Centipawns(_) => 1,
+        };
+        return Some((analysis.pv[0].clone(), moves));
+    }
+    mate::find_mate(&pos.fen, pos.is_chess960(), max_moves).map(|mate| (mate.first_move, mate.moves))
+}
+
+// Difficulty from the puzzle database rating when available, otherwise estimated
+pub fn rate_difficulty(positions: &mut [ChessPosition], max_moves: u32) {
+    for pos in positions.iter_mut().filter(|pos| pos.has_standard_material()) {
+        pos.difficulty = match pos.rating {
+            Some(rating) => Some(difficulty::level_from_rating(rating, &difficulty::LEVEL_THRESHOLDS)),
+            None => solution_outline(pos, max_moves)
+                .and_then(|(first_move, moves)| difficulty::estimate(&pos.fen, pos.is_chess960(), &first_move, moves)),
+        };
+    }
+    let rated = positions.iter().filter(|pos| pos.difficulty.is_some()).count();
+    println!("Rated the difficulty of {} of {} positions", rated, positions.len());
+}
+
+// Easiest first; positions without a difficulty keep their order at the end.
+// Positions are renumbered to match the new order, from the first number of a --skip slice
+pub fn sort_by_difficulty(positions: &mut [ChessPosition]) {
+    let first = positions.iter().map(|pos| pos.number).min().unwrap_or(1);
+    positions.sort_by_key(|pos| pos.difficulty.unwrap_or(u32::MAX));
+    for (i, pos) in positions.iter_mut().enumerate() {
+        pos.number = first + i as i32;
+    }
+}
+
+// Each position's place among the positions of its chapter, for --numbering chapter
+pub fn number_within_chapters(positions: &mut [ChessPosition]) {
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for pos in positions.iter_mut() {
+        let count = counts.entry(pos.chapter).or_default();
+        *count += 1;
+        pos.in_chapter = *count;
+    }
+}
+
+// Drop the positions the selection options exclude, before any analysis is spent on them.
+// The remaining positions are renumbered
+pub fn filter_positions(positions: &mut Vec<ChessPosition>, options: &Options) -> Result<()> {
+    let found = positions.len();
+    if let Some(side) = options.side {
+        positions.retain(|pos| pos.black_to_move == (side == shakmaty::Color::Black));
+    }
+    positions.retain(|pos| {
+        let pieces = board_pieces(&pos.fen);
+        let count = |piece: char| pieces.iter().filter(|&&on_board| on_board == piece).count();
+        options.max_pieces.is_none_or(|max| pieces.len() <= max)
+            && options.phase.is_none_or(|phase| (phase == GamePhase::Endgame) == is_endgame(&pieces))
+            && options.must_contain.iter().all(|&piece| count(piece) >= options.must_contain.iter().filter(|&&required| required == piece).count())
+    });
+    if positions.len() == found {
+        return Ok(());
+    }
+    if positions.is_empty() {
+        return Err(anyhow!("None of the {} positions match the selection", found));
+    }
+    for (i, pos) in positions.iter_mut().enumerate() {
+        pos.number = i as i32 + 1;
+    }
+    println!("Selected {} of {} positions", positions.len(), found);
+    Ok(())
+}
+
+// Rearrange the chapters as listed in an order file: one chapter per line, by name or by
+// number, blank lines and # comments ignored. A chapter's diagrams stay together in their
+// order; chapters the file leaves out follow in study order or are dropped.
+// Positions are renumbered to match the new order
+pub fn order_positions(positions: &mut Vec<ChessPosition>, path: &str, keep_unlisted: bool, storage: &dyn Storage) -> Result<()> {
+    let content = read_text(storage, path).map_err(|e| anyhow!("Cannot read order file '{}': {}", path, e))?;
+    let mut listed: Vec<usize> = Vec::new();
+    for entry in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let chapter = match entry.parse::<usize>() {
+            Ok(number) => positions.iter().find(|pos| pos.chapter == number),
+            Err(_) => positions.iter().find(|pos| pos.chapter_name.eq_ignore_ascii_case(entry)),
+        };
+        match chapter {
+            Some(pos) if !listed.contains(&pos.chapter) => listed.push(pos.chapter),
+            Some(_) => eprintln!("Warning: chapter '{}' is listed twice in {}", entry, path),
+            None => eprintln!("Warning: no chapter '{}' in the study (from {})", entry, path),
+        }
+    }
+    
+    let found = positions.len();
+    let rank = |pos: &ChessPosition| listed.iter().position(|&chapter| chapter == pos.chapter);
+    if !keep_unlisted {
+        positions.retain(|pos| rank(pos).is_some());
+    }
+    if positions.is_empty() {
+        return Err(anyhow!("None of the chapters in {} are in the study", path));
+    }
+    // Stable, so unlisted chapters (ranked last) keep their study order
+    positions.sort_by_key(|pos| rank(pos).unwrap_or(usize::MAX));
+    for (i, pos) in positions.iter_mut().enumerate() {
+        pos.number = i as i32 + 1;
+    }
+    println!("Ordered {} chapters from {} ({} of {} positions)", listed.len(), path, positions.len(), found);
+    Ok(())
+}
+
+// A slice of the positions for handouts of a long study. Unlike the filters, the slice keeps
+// the positions' numbers, so consecutive handouts continue the numbering
+pub fn slice_positions(positions: &mut Vec<ChessPosition>, options: &Options) -> Result<()> {
+    let found = positions.len();
+    if options.skip >= found && options.skip > 0 {
+        return Err(anyhow!("--skip {} leaves none of the {} positions", options.skip, found));
+    }
+    // The positions on the first --max-pages pages, which hold fewer with --section-breaks
+    let rest = &positions[options.skip.min(found)..];
+    let pages = diagram_page_ranges(rest, options);
+    let page_end = options.max_pages.map(|max| if max == 0 { 0 } else { pages.get(max - 1).map_or(rest.len(), |range| range.end) });
+    let take = [options.take, page_end].into_iter().flatten().min();
+    if options.skip == 0 && take.is_none_or(|take| take >= found) {
+        return Ok(());
+    }
+    positions.drain(..options.skip);
+    positions.truncate(take.unwrap_or(usize::MAX));
+    if positions.is_empty() {
+        return Err(anyhow!("No positions to render"));
+    }
+    println!("Rendering positions {} to {} of {}", positions[0].number, positions[positions.len() - 1].number, found);
+    Ok(())
+}
+
+// Pieces on the board, as FEN letters
+fn board_pieces(fen: &str) -> Vec<char> {
+    parse_fen(fen.split(' ').next().unwrap_or("")).into_iter().flatten().filter(|&square| square != ' ').collect()
+}
+
+// Lichess' definition: an endgame has at most ENDGAME_MAX_PIECES queens, rooks, bishops and knights left
+fn is_endgame(pieces: &[char]) -> bool {
+    pieces.iter().filter(|piece| "QRBNqrbn".contains(**piece)).count() <= ENDGAME_MAX_PIECES
+}
+
+// Fisher-Yates shuffle driven by SplitMix64, so a seed gives the same order on every
+// platform and version. Positions are renumbered to match the new order
+pub fn shuffle_positions(positions: &mut [ChessPosition], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    for i in (1..positions.len()).rev() {
+        positions.swap(i, (next() % (i as u64 + 1)) as usize);
+    }
+    for (i, pos) in positions.iter_mut().enumerate() {
+        pos.number = i as i32 + 1;
+    }
+}
+
+// Seed for --shuffle without one; printed so that the order can be reproduced
+pub fn random_seed() -> u64 {
+    (platform::unix_time().as_nanos() as u64 ^ platform::process_id() as u64) % 1_000_000
+}
+
+// SOURCE_DATE_EPOCH when set, as for reproducible builds; otherwise the current time, or
+// the epoch itself with --reproducible
+pub fn creation_time(reproducible: bool) -> Result<u64> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch.trim().parse().map_err(|_| anyhow!("Invalid SOURCE_DATE_EPOCH '{}', expected seconds since 1970", epoch)),
+        Err(_) if reproducible => Ok(0),
+        Err(_) => Ok(platform::unix_time().as_secs()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(positions: &[ChessPosition]) -> Vec<String> {
+        positions.iter().map(|pos| format!("{}:{}", pos.number, pos.description)).collect()
+    }
+
+    #[test]
+    fn shuffle_seed_gives_the_same_order() {
+        let positions: Vec<ChessPosition> = (1..=20).map(|i| ChessPosition::new(i, i.to_string(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string())).collect();
+        let shuffled = |seed| {
+            let mut positions = positions.clone();
+            shuffle_positions(&mut positions, seed);
+            numbers(&positions)
+        };
+        assert_eq!(shuffled(42), shuffled(42));
+        assert_ne!(shuffled(42), shuffled(43));
+        assert_ne!(shuffled(42), numbers(&positions));
+        // Renumbered in the new order
+        let mut sorted = positions.clone();
+        shuffle_positions(&mut sorted, 42);
+        assert!(sorted.iter().enumerate().all(|(i, pos)| pos.number == i as i32 + 1));
+    }
+}
@@ -0,0 +1,123 @@
+// `fen2pdf serve`: an HTTP server making the PDF of a study on request, e.g. for "print this
+// study" links on a club website. GET /study/{id}.pdf?grid=3x3&theme=blue downloads the study
+// (through the download cache, so unchanged studies are not fetched again) and answers with
+// its PDF. The options given after `serve` apply to every request
+
+use crate::platform::OutputStorage;
+use crate::{BoardCache, FetchError, ParseError};
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+
+const DEFAULT_PORT: u16 = 8080;
+
+// Query parameters and the options they stand for
+const QUERY_OPTIONS: [(&str, &str); 5] = [
+    ("grid", "--grid"),
+    ("theme", "--theme"),
+    ("page-size", "--page-size"),
+    ("solutions", "--solutions"),
+    ("renderer", "--renderer"),
+];
+
+// Checks the options with a stand-in study ID, then answers requests, each in a thread of its
+// own, until the process is stopped
+pub fn serve(args: &[String]) -> Result<()> {
+    let mut port = DEFAULT_PORT;
+    let mut options_args = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--port" => port = crate::next_number(&mut iter, arg)?,
+            _ => options_args.push(arg.clone()),
+        }
+    }
+    let options = crate::parse_args(&request_args(&options_args, &[], "study"))?;
+    if !options.exports.is_empty() {
+        return Err(anyhow!("fen2pdf serve answers with the PDF only; leave out --export"));
+    }
+    let boards = BoardCache::new(options.cache_dir.as_deref())?;
+
+    let server = tiny_http::Server::http(("0.0.0.0", port)).map_err(|e| anyhow!("Cannot listen on port {}: {}", port, e))?;
+    println!("Serving study PDFs at http://localhost:{}/study/<id>.pdf", port);
+    std::thread::scope(|scope| {
+        for request in server.incoming_requests() {
+            let (options_args, boards) = (&options_args, &boards);
+            scope.spawn(move || answer(request, options_args, boards));
+        }
+    });
+    Ok(())
+}
+
+// The command line of a request: the server's options, then the query's
+fn request_args(options_args: &[String], query: &[(String, String)], study_id: &str) -> Vec<String> {
+    let mut args = vec!["fen2pdf".to_string()];
+    args.extend(options_args.iter().cloned());
+    for (flag, value) in query {
+        args.extend([flag.clone(), value.clone()]);
+    }
+    args.push(study_id.to_string());
+    args
+}
+
+fn answer(request: tiny_http::Request, options_args: &[String], boards: &BoardCache) {
+    println!("{} {}", request.method(), request.url());
+    let result = match request.method() {
+        tiny_http::Method::Get => make_pdf(request.url(), options_args, boards),
+        _ => Err((405, "Only GET is supported".to_string())),
+    };
+    let response = match result {
+        Ok((filename, pdf)) => {
+            let mut response = tiny_http::Response::from_data(pdf);
+            let headers = [("Content-Type", "application/pdf".to_string()), ("Content-Disposition", format!("inline; filename=\"{}\"", filename))];
+            // Names that are not ASCII are left out of the header
+            for header in headers.iter().filter_map(|(name, value)| tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()).ok()) {
+                response.add_header(header);
+            }
+            response
+        }
+        Err((status, message)) => {
+            eprintln!("Warning: {} answered {}: {}", request.url(), status, message);
+            tiny_http::Response::from_string(message + "\n").with_status_code(status)
+        }
+    };
+    if let Err(e) = request.respond(response) {
+        eprintln!("Warning: cannot send the response: {}", e);
+    }
+}
+
+// The PDF's file name and contents, or the status and message of the error
+fn make_pdf(url: &str, options_args: &[String], boards: &BoardCache) -> Result<(String, Vec<u8>), (u16, String)> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let study_id = path
+        .strip_prefix("/study/")
+        .and_then(|file| file.strip_suffix(".pdf"))
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()))
+        .ok_or_else(|| (404, "Not found; ask for /study/<id>.pdf".to_string()))?;
+    let mut flags = Vec::new();
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        let Some((_, flag)) = QUERY_OPTIONS.iter().find(|(name, _)| *name == key) else {
+            return Err((400, format!("Unknown parameter '{}'", key)));
+        };
+        // Values are never flags, --config least of all, which would read a file of the server's
+        if value.starts_with('-') {
+            return Err((400, format!("Invalid value for '{}'", key)));
+        }
+        flags.push((flag.to_string(), value.into_owned()));
+    }
+
+    let storage = Arc::new(OutputStorage::default());
+    let options = crate::parse_args_with_storage(&request_args(options_args, &flags, study_id), storage.clone()).map_err(|e| (400, e.to_string()))?;
+    let study_data = crate::fetch_lichess_study(study_id, &options).map_err(|e| (fetch_status(&e), e.to_string()))?;
+    let filename = format!("{}.pdf", crate::pdf_base_name(&study_data.name));
+    let pdf = crate::pdf_bytes(study_data, &options, &storage, boards).map_err(|e| (500, e.to_string()))?;
+    Ok((filename, pdf))
+}
+
+// Missing studies are the client's to fix; failed downloads are the upstream server's
+fn fetch_status(error: &FetchError) -> u16 {
+    match error {
+        FetchError::Status(404) | FetchError::InvalidContent | FetchError::Parse(ParseError::NoPositions) => 404,
+        FetchError::Status(_) | FetchError::Network { .. } => 502,
+        _ => 500,
+    }
+}
@@ -0,0 +1,182 @@
+// Arrows and highlighted squares (--shapes) from Lichess' [%cal Ge2e4] and [%csl Rd5]
+// comment commands, drawn over the boards. Lichess has four brushes (green, red, yellow and
+// blue); --shape-colors maps them to other colors, e.g. grays for printing, and --arrow-style
+// dashes the arrows so that they stay apart where the colors do not, as on grayscale printers
+
+use anyhow::{Result, anyhow};
+use printpdf::{Color, Line, LineDashPattern, Mm, PdfLayerReference, Point, Polygon, Rgb, path};
+
+// Arrow shaft width on a full-size board, in mm
+pub const ARROW_WIDTH: f32 = 1.2;
+
+// Lichess' brushes and, for printing, grays from black to light
+const LICHESS_COLORS: [(f32, f32, f32); 4] = [(0.08, 0.47, 0.11), (0.53, 0.13, 0.13), (0.9, 0.56, 0.0), (0.0, 0.19, 0.53)];
+const PRINT_COLORS: [(f32, f32, f32); 4] = [(0.0, 0.0, 0.0), (0.3, 0.3, 0.3), (0.6, 0.6, 0.6), (0.15, 0.15, 0.15)];
+const BRUSH_LETTERS: [char; 4] = ['G', 'R', 'Y', 'B'];
+
+// Arrowheads relative to the shaft width; tips stop short of the target square's center by a
+// share of the square
+const HEAD_LENGTH: f32 = 2.6;
+const HEAD_WIDTH: f32 = 3.0;
+const TIP_INSET: f32 = 0.15;
+// Circles around highlighted squares
+const CIRCLE_RADIUS: f32 = 0.44;
+const CIRCLE_WIDTH: f32 = 0.7;
+
+// An arrow from one square to another, or a highlighted square when `to` is None. Squares
+// are (file, rank) from 0, a1 being (0, 0); `brush` indexes BRUSH_LETTERS
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shape {
+    pub brush: usize,
+    pub from: (u8, u8),
+    pub to: Option<(u8, u8)>,
+}
+
+// Dashes of the arrow shafts and circles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArrowStyle {
+    Solid,
+    Dashed,
+    // Solid green, dashed red, dotted yellow and dash-dotted blue
+    ByColor,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShapeStyle {
+    // By brush, in BRUSH_LETTERS order
+    pub colors: [(f32, f32, f32); 4],
+    pub width: f32,
+    pub arrows: ArrowStyle,
+}
+
+impl ShapeStyle {
+    // Lichess' colors in solid arrows; grays told apart by their dashes with --print-mode bw
+    pub fn new(grayscale: bool) -> Self {
+        if grayscale {
+            ShapeStyle { colors: PRINT_COLORS, width: ARROW_WIDTH, arrows: ArrowStyle::ByColor }
+        } else {
+            ShapeStyle { colors: LICHESS_COLORS, width: ARROW_WIDTH, arrows: ArrowStyle::Solid }
+        }
+    }
+
+    fn dashes(&self, brush: usize) -> LineDashPattern {
+        let pattern = |dash_1, gap_1, dash_2| LineDashPattern { dash_1: Some(dash_1), gap_1: Some(gap_1), dash_2, gap_2: dash_2.map(|_| gap_1), ..Default::default() };
+        match (self.arrows, brush) {
+            (ArrowStyle::Solid, _) | (ArrowStyle::ByColor, 0) => LineDashPattern::default(),
+            (ArrowStyle::Dashed, _) | (ArrowStyle::ByColor, 1) => pattern(4, 2, None),
+            (ArrowStyle::ByColor, 2) => pattern(1, 2, None),
+            (ArrowStyle::ByColor, _) => pattern(4, 2, Some(1)),
+        }
+    }
+}
+
+// Colors as "lichess" or "print", then changes of single brushes, e.g. "print,R=#808080"
+pub fn parse_colors(value: &str) -> Result<[(f32, f32, f32); 4]> {
+    let mut colors = LICHESS_COLORS;
+    for item in value.split(',').map(str::trim) {
+        match item {
+            "lichess" => colors = LICHESS_COLORS,
+            "print" => colors = PRINT_COLORS,
+            _ => {
+                let brush = item.split_once('=').and_then(|(letter, color)| Some((brush_index(letter)?, parse_hex_color(color)?)));
+                let Some((brush, color)) = brush else {
+                    return Err(anyhow!("Invalid shape color '{}', expected lichess, print or a brush like R=#808080 (G, R, Y or B)", item));
+                };
+                colors[brush] = color;
+            }
+        }
+    }
+    Ok(colors)
+}
+
+fn brush_index(letter: &str) -> Option<usize> {
+    let mut chars = letter.chars();
+    let letter = chars.next().filter(|_| chars.next().is_none())?.to_ascii_uppercase();
+    BRUSH_LETTERS.iter().position(|&brush| brush == letter)
+}
+
+// "#rrggbb" or "#rgb"
+fn parse_hex_color(text: &str) -> Option<(f32, f32, f32)> {
+    let hex = text.strip_prefix('#')?;
+    let digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16)).collect::<Option<_>>()?;
+    let channel = |i: usize| match digits.len() {
+        3 => Some((digits[i] * 17) as f32 / 255.0),
+        6 => Some((digits[2 * i] * 16 + digits[2 * i + 1]) as f32 / 255.0),
+        _ => None,
+    };
+    Some((channel(0)?, channel(1)?, channel(2)?))
+}
+
+// The arrows and circles of the [%cal] and [%csl] commands in the comments
+pub fn comment_shapes(comments: &[String]) -> Vec<Shape> {
+    let mut shapes = Vec::new();
+    for comment in comments {
+        let mut rest = comment.as_str();
+        while let Some(start) = rest.find("[%c") {
+            let command = &rest[start + 2..];
+            let end = command.find(']').unwrap_or(command.len());
+            let (name, arguments) = command[..end].split_once(' ').unwrap_or((&command[..end], ""));
+            // Squares and brushes are ASCII; anything else is not a shape (nor sliceable by bytes)
+            for item in arguments.split(',').map(str::trim).filter(|item| item.is_ascii()) {
+                let shape = match (name, item.len()) {
+                    ("csl", 3) => brush_index(&item[..1]).zip(square(&item[1..3])).map(|(brush, from)| Shape { brush, from, to: None }),
+                    ("cal", 5) => brush_index(&item[..1]).zip(square(&item[1..3])).zip(square(&item[3..5])).map(|((brush, from), to)| Shape { brush, from, to: Some(to) }),
+                    _ => None,
+                };
+                shapes.extend(shape);
+            }
+            rest = &command[end..];
+        }
+    }
+    shapes
+}
+
+fn square(name: &str) -> Option<(u8, u8)> {
+    match name.as_bytes() {
+        &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some((file - b'a', rank - b'1')),
+        _ => None,
+    }
+}
+
+// The shapes over a board of `size` with its bottom-left corner at (x, y)
+pub fn draw_shapes(layer: &PdfLayerReference, x: f32, y: f32, size: f32, shapes: &[Shape], flipped: bool, style: &ShapeStyle) {
+    let square = size / 8.0;
+    let width = style.width * size / crate::BOARD_IMAGE_SIZE;
+    let center = |(file, rank): (u8, u8)| {
+        let (file, rank) = if flipped { (7 - file, 7 - rank) } else { (file, rank) };
+        (x + (file as f32 + 0.5) * square, y + (rank as f32 + 0.5) * square)
+    };
+    let point = |(x, y): (f32, f32)| (Point::new(Mm(x), Mm(y)), false);
+    for shape in shapes {
+        let (red, green, blue) = style.colors[shape.brush];
+        layer.set_outline_color(Color::Rgb(Rgb::new(red, green, blue, None)));
+        layer.set_fill_color(Color::Rgb(Rgb::new(red, green, blue, None)));
+        layer.set_line_dash_pattern(style.dashes(shape.brush));
+        let from = center(shape.from);
+        match shape.to.filter(|&to| to != shape.from) {
+            None => {
+                layer.set_outline_thickness(mm_to_pt(width * CIRCLE_WIDTH));
+                let circle = printpdf::utils::calculate_points_for_circle(Mm(square * CIRCLE_RADIUS), Mm(from.0), Mm(from.1));
+                layer.add_line(Line { points: circle, is_closed: true });
+            }
+            Some(to) => {
+                let to = center(to);
+                let length = ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2)).sqrt();
+                let (dx, dy) = ((to.0 - from.0) / length, (to.1 - from.1) / length);
+                let tip = (to.0 - dx * square * TIP_INSET, to.1 - dy * square * TIP_INSET);
+                let base = (tip.0 - dx * width * HEAD_LENGTH, tip.1 - dy * width * HEAD_LENGTH);
+                layer.set_outline_thickness(mm_to_pt(width));
+                layer.add_line(Line { points: vec![point(from), point(base)], is_closed: false });
+                let (nx, ny) = (-dy * width * HEAD_WIDTH / 2.0, dx * width * HEAD_WIDTH / 2.0);
+                let head = vec![point(tip), point((base.0 + nx, base.1 + ny)), point((base.0 - nx, base.1 - ny))];
+                layer.add_polygon(Polygon { rings: vec![head], mode: path::PaintMode::Fill, winding_order: path::WindingOrder::NonZero });
+            }
+        }
+    }
+    layer.set_line_dash_pattern(LineDashPattern::default());
+    layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+}
+
+fn mm_to_pt(mm: f32) -> f32 {
+    mm * 72.0 / 25.4
+}
@@ -0,0 +1,343 @@
+// Solution text and its layouts: the solutions section, upside-down and verso solutions,
+// notes and answer fields
+
+use crate::{BOARD_DESC_GAP, BOARD_IMAGE_SIZE, BOARD_SIZE, ChessPosition, NOTE_ICON_SIZE, Options, PrintMode, SOLUTION_BOTTOM, SOLUTION_COLUMN_WRAP, SOLUTION_DIAGRAM_CHARS, SOLUTION_DIAGRAM_MIN_PLIES, SOLUTION_DIAGRAM_SIZE, SOLUTION_LINE_HEIGHT, SOLUTION_TOP, SOLUTION_WRAP, SolutionsMode, UPSIDE_DOWN_BOTTOM, UPSIDE_DOWN_FONT_SIZE, UPSIDE_DOWN_LINES, UPSIDE_DOWN_LINE_HEIGHT, UPSIDE_DOWN_MARGIN, UPSIDE_DOWN_WRAP, VERSO_LINES, VERSO_LINE_HEIGHT, VERSO_WRAP, WORKSHEET_BOTTOM, form, images, is_black_to_move, notes, pgn};
+use crate::board::{parse_fen, rgb_image};
+use crate::document::grid_cell;
+use anyhow::Result;
+use printpdf::*;
+
+// One position's entry in the solutions section
+#[derive(Clone)]
+pub struct SolutionBlock {
+    pub heading: String,
+    pub lines: Vec<String>,
+    // Miniature beside the lines; its description labels it
+    pub diagram: Option<ChessPosition>,
+}
+
+// Solution text for a position; empty when there is nothing to show
+pub fn solution_lines(pos: &ChessPosition, options: &Options) -> Vec<String> {
+    let mut lines = Vec::new();
+    if options.hide_side_to_move {
+        lines.push(if pos.black_to_move { "Black to move" } else { "White to move" }.to_string());
+    }
+    if let Some(annotated) = &pos.annotated_solution {
+        lines.push(annotated.clone());
+    } else if options.pdf.solutions().is_some() && !pos.solution.is_empty() {
+        lines.push(format!("Solution: {}", numbered_moves(&pos.fen, &pos.solution)));
+    }
+    if let Some(game_move) = &pos.game_move {
+        lines.push(format!("Played: {}", game_move));
+    }
+    if let Some(analysis) = &pos.analysis {
+        lines.push(format!("Evaluation: {} (depth {})", analysis.score.display(), analysis.depth));
+        lines.push(format!("Best move: {}", numbered_moves(&pos.fen, &analysis.pv[..1])));
+        if analysis.pv.len() > 1 {
+            lines.push(format!("Line: {}", numbered_moves(&pos.fen, &analysis.pv)));
+        }
+    }
+    if let Some(result) = &pos.tablebase {
+        match &result.best_move {
+            Some(best_move) => lines.push(format!(
+                "Tablebase: {}, best move {}",
+                result.display(),
+                numbered_moves(&pos.fen, std::slice::from_ref(best_move))
+            )),
+            None => lines.push(format!("Tablebase: {}", result.display())),
+        }
+    }
+    lines
+}
+
+// Solution text printed next to the number only, led by the caption when it is hidden under the diagram
+fn answer_lines(pos: &ChessPosition, options: &Options) -> Vec<String> {
+    let mut lines = solution_lines(pos, options);
+    let caption = pos.caption();
+    if options.hide_captions && !caption.is_empty() {
+        lines.insert(0, caption);
+    }
+    lines
+}
+
+// The moves played from `fen` as annotated move text; None without moves
+pub fn annotated_solution(fen: &str, comments: &[String], moves: &[pgn::MoveNode]) -> Option<String> {
+    if moves.is_empty() {
+        return None;
+    }
+    let fullmove = fen.split_whitespace().nth(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+    Some(pgn::annotated_text(comments, moves, fullmove, !is_black_to_move(fen)))
+}
+
+// Format SAN moves played from `fen` with move numbers, e.g. "23... Nf5 24. Qg3+"
+pub fn numbered_moves(fen: &str, moves: &[String]) -> String {
+    let mut move_number: u32 = fen.split_whitespace().nth(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+    let mut white_to_move = !is_black_to_move(fen);
+    let mut text = Vec::new();
+    
+    for (i, san) in moves.iter().enumerate() {
+        if white_to_move {
+            text.push(format!("{}. {}", move_number, san));
+        } else if i == 0 {
+            text.push(format!("{}... {}", move_number, san));
+        } else {
+            text.push(san.clone());
+        }
+        if !white_to_move {
+            move_number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+    
+    text.join(" ")
+}
+
+// Greedy word wrap at `width` characters
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// The position after the key move of a longer solution, seen from the solver's side
+fn solution_diagram(pos: &ChessPosition, options: &Options) -> Option<ChessPosition> {
+    if !options.solution_diagrams || pos.solution.len() < SOLUTION_DIAGRAM_MIN_PLIES {
+        return None;
+    }
+    let label = format!("After {}", numbered_moves(&pos.fen, &pos.solution[..1]));
+    Some(ChessPosition {
+        black_to_move: pos.black_to_move,
+        variant: pos.variant.clone(),
+        ..ChessPosition::new(pos.number, label, pos.key_fen.clone()?)
+    })
+}
+
+// Lines of solution text a miniature diagram and its label take up
+pub fn solution_diagram_lines() -> usize {
+    ((SOLUTION_DIAGRAM_SIZE + 5.0) / SOLUTION_LINE_HEIGHT).ceil() as usize
+}
+
+// Columns per page and characters per line of the solutions section
+pub fn solution_columns(options: &Options) -> (usize, usize) {
+    match options.pdf.solutions() {
+        Some(SolutionsMode::Annotated) => (2, SOLUTION_COLUMN_WRAP),
+        _ => (1, SOLUTION_WRAP),
+    }
+}
+
+// Distribute the solution blocks over the columns of each page. A block is only split
+// when it is longer than a whole column, and continues under the same heading.
+// Upside-down, verso and popup solutions go with the diagram pages instead
+pub fn layout_solution_pages(positions: &[ChessPosition], options: &Options) -> Vec<Vec<Vec<SolutionBlock>>> {
+    // Drafts have no answer key for hidden captions either
+    if options.draft || matches!(options.pdf.solutions(), Some(SolutionsMode::UpsideDown | SolutionsMode::Verso | SolutionsMode::Popup)) {
+        return Vec::new();
+    }
+    let (columns_per_page, wrap) = solution_columns(options);
+    let lines_per_column = ((options.pdf.page_height() - SOLUTION_TOP - SOLUTION_BOTTOM) / SOLUTION_LINE_HEIGHT) as usize;
+    let mut columns = Vec::new();
+    let mut column = Vec::new();
+    let mut used_lines = 0;
+    
+    for pos in positions {
+        // A miniature diagram narrows the text beside it
+        let mut diagram = solution_diagram(pos, options);
+        let wrap = if diagram.is_some() { wrap - SOLUTION_DIAGRAM_CHARS } else { wrap };
+        let lines: Vec<String> = solution_lines(pos, options).iter().flat_map(|line| wrap_text(line, wrap)).collect();
+        // With hidden captions the heading alone is worth listing
+        if lines.is_empty() && !options.hide_captions {
+            continue;
+        }
+        
+        let heading = pos.numbered(options, &pos.caption());
+        let chunks: Vec<&[String]> = if lines.is_empty() { vec![&[]] } else { lines.chunks(lines_per_column - 2).collect() };
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            // Heading, solution lines (or the diagram beside them) and a blank line
+            let block_lines = if i == 0 && diagram.is_some() { chunk.len().max(solution_diagram_lines()) } else { chunk.len() } + 2;
+            if used_lines + block_lines > lines_per_column && !column.is_empty() {
+                columns.push(std::mem::take(&mut column));
+                used_lines = 0;
+            }
+            used_lines += block_lines;
+            column.push(SolutionBlock {
+                heading: if i == 0 { heading.clone() } else { format!("{} (continued)", heading) },
+                lines: chunk.to_vec(),
+                diagram: diagram.take(),
+            });
+        }
+    }
+    
+    if !column.is_empty() {
+        columns.push(column);
+    }
+    columns.chunks(columns_per_page).map(<[_]>::to_vec).collect()
+}
+
+// Miniature board with its label below; `top` is the board's upper edge
+#[allow(clippy::too_many_arguments)]
+pub fn draw_solution_diagram(
+    layer: &PdfLayerReference,
+    x: f32,
+    top: f32,
+    diagram: &ChessPosition,
+    (width, height, rgb_data): (u32, u32, Vec<u8>),
+    font: &printpdf::IndirectFontRef,
+    images: &images::ImageStore,
+    options: &Options,
+) -> Result<()> {
+    let scale = SOLUTION_DIAGRAM_SIZE / BOARD_IMAGE_SIZE;
+    rgb_image(width, height, rgb_data, images, options)?.add_to_layer(layer.clone(), ImageTransform {
+        translate_x: Some(Mm(x)),
+        translate_y: Some(Mm(top - SOLUTION_DIAGRAM_SIZE)),
+        scale_x: Some(scale),
+        scale_y: Some(scale),
+        ..Default::default()
+    });
+    if options.print_mode == PrintMode::Bw {
+        draw_board_border(layer, x, top - SOLUTION_DIAGRAM_SIZE, SOLUTION_DIAGRAM_SIZE);
+    }
+    layer.use_text(&diagram.description, 8.0, Mm(x), Mm(top - SOLUTION_DIAGRAM_SIZE - 3.5), font);
+    Ok(())
+}
+
+// Heavier black frame around a board image of `size` with its bottom-left corner at (x, y),
+// so the white edge squares do not fade into the paper
+pub fn draw_board_border(layer: &PdfLayerReference, x: f32, y: f32, size: f32) {
+    layer.set_outline_color(printpdf::Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    layer.set_outline_thickness(0.6);
+    layer.add_line(Line {
+        points: vec![
+            (Point::new(Mm(x), Mm(y)), false),
+            (Point::new(Mm(x + size), Mm(y)), false),
+            (Point::new(Mm(x + size), Mm(y + size)), false),
+            (Point::new(Mm(x), Mm(y + size)), false),
+        ],
+        is_closed: true,
+    });
+}
+
+// The pieces of a draft board as FEN letters (uppercase White, lowercase Black) centered in
+// their squares; (x, y) is the board's bottom-left corner
+pub fn draw_draft_pieces(layer: &PdfLayerReference, x: f32, y: f32, size: f32, pos: &ChessPosition, font: &printpdf::IndirectFontRef, options: &Options) {
+    let board = parse_fen(pos.fen.split(' ').next().unwrap_or(""));
+    let square = size / 8.0;
+    let font_size = square * 0.6 / 25.4 * 72.0;
+    for (rank, row) in board.iter().enumerate() {
+        for (file, &piece) in row.iter().enumerate() {
+            if piece == ' ' {
+                continue;
+            }
+            // Rank 8 at the top, or at the bottom when the board is flipped
+            let (column, row) = if pos.flipped(options) { (7 - file, rank) } else { (file, 7 - rank) };
+            let width_estimate = square * 0.4; // Rough estimate
+            let text_x = x + (column as f32 + 0.5) * square - width_estimate / 2.0;
+            let text_y = y + (row as f32 + 0.3) * square;
+            layer.use_text(piece.to_string(), font_size, Mm(text_x), Mm(text_y), font);
+        }
+    }
+}
+
+// Text turned 180°, reading from the opposite edge of the page; (x, y) is where the line starts
+fn use_text_upside_down(layer: &PdfLayerReference, text: &str, font_size: f32, x: f32, y: f32, font: &printpdf::IndirectFontRef) {
+    layer.begin_text_section();
+    layer.set_font(font, font_size);
+    layer.set_text_matrix(TextMatrix::TranslateRotate(Mm(x).into(), Mm(y).into(), 180.0));
+    layer.write_text(text, font);
+    layer.end_text_section();
+}
+
+// Note holding a diagram's solution (--solutions popup), its icon left of the exercise number;
+// None when there is nothing to show
+pub fn solution_note(page: usize, x: f32, y: f32, pos: &ChessPosition, options: &Options) -> Option<notes::Note> {
+    let lines = answer_lines(pos, options);
+    if lines.is_empty() {
+        return None;
+    }
+    // The caption's baseline, as in draw_coordinates_and_description
+    let text_y = options.pdf.page_height() - (y + BOARD_SIZE + BOARD_DESC_GAP) + BOARD_IMAGE_SIZE * (1.0 - options.board_scale());
+    let points = |mm: f32| Pt::from(Mm(mm)).0;
+    Some(notes::Note {
+        page,
+        rect: [points(x - NOTE_ICON_SIZE - 1.0), points(text_y - 1.0), points(x - 1.0), points(text_y - 1.0 + NOTE_ICON_SIZE)],
+        title: format!("Solution {}", pos.exercise_label(options)),
+        contents: lines.join("\n"),
+    })
+}
+
+// Answer field (--fillable) under the caption, down to where worksheet lines end
+pub fn answer_field(page: usize, x: f32, y: f32, text_bottom: f32, pos: &ChessPosition, options: &Options) -> form::Field {
+    let points = |mm: f32| Pt::from(Mm(mm)).0;
+    let bottom = options.pdf.page_height() - y - BOARD_SIZE - WORKSHEET_BOTTOM - 1.0;
+    form::Field {
+        page,
+        rect: [points(x), points(bottom), points(x + BOARD_IMAGE_SIZE * options.board_scale()), points(text_bottom - 1.5)],
+        name: format!("Answer {}", pos.number),
+        tooltip: format!("Your answer to exercise {}", pos.exercise_label(options)),
+    }
+}
+
+// Solutions of the page's diagrams, puzzle-book style: turned upside down at the page bottom
+// so they are not read by accident. Lines that do not fit are cut off with "..."
+pub fn draw_upside_down_solutions(layer: &PdfLayerReference, positions: &[ChessPosition], font: &printpdf::IndirectFontRef, options: &Options) {
+    let mut lines: Vec<String> = positions
+        .iter()
+        .filter_map(|pos| {
+            let solution = answer_lines(pos, options);
+            (!solution.is_empty()).then(|| pos.numbered(options, &solution.join("; ")))
+        })
+        .flat_map(|entry| wrap_text(&entry, UPSIDE_DOWN_WRAP))
+        .collect();
+    if lines.len() > 2 * UPSIDE_DOWN_LINES {
+        lines.truncate(2 * UPSIDE_DOWN_LINES);
+        lines.last_mut().unwrap().push_str(" ...");
+    }
+    
+    // Turned around, the page bottom is the top and the right column comes first
+    let right = options.pdf.page_width() - UPSIDE_DOWN_MARGIN;
+    let column_width = (right - UPSIDE_DOWN_MARGIN) / 2.0;
+    for (i, line) in lines.iter().enumerate() {
+        let x = right - (i / UPSIDE_DOWN_LINES) as f32 * column_width;
+        let y = UPSIDE_DOWN_BOTTOM + ((i % UPSIDE_DOWN_LINES) + 1) as f32 * UPSIDE_DOWN_LINE_HEIGHT;
+        use_text_upside_down(layer, line, UPSIDE_DOWN_FONT_SIZE, x, y, font);
+    }
+}
+
+// Solutions in the grid cells behind their diagrams. Duplex printing flips the sheet
+// around its long edge, so the columns are mirrored; long solutions are cut off with "..."
+pub fn draw_verso_solutions(
+    layer: &PdfLayerReference,
+    positions: &[ChessPosition],
+    font: &printpdf::IndirectFontRef,
+    font_bold: &printpdf::IndirectFontRef,
+    options: &Options,
+) {
+    for (i, pos) in positions.iter().enumerate() {
+        let columns = options.pdf.columns();
+        let mirrored = i - i % columns + (columns - 1 - i % columns);
+        let (x, y) = grid_cell(mirrored, &options.pdf);
+        let mut line_y = options.pdf.page_height() - y - BOARD_SIZE + BOARD_IMAGE_SIZE - VERSO_LINE_HEIGHT;
+        layer.use_text(pos.exercise_mark(options), 11.0, Mm(x), Mm(line_y), font_bold);
+        
+        let mut lines: Vec<String> = answer_lines(pos, options).iter().flat_map(|line| wrap_text(line, VERSO_WRAP)).collect();
+        if lines.len() > VERSO_LINES {
+            lines.truncate(VERSO_LINES);
+            lines.last_mut().unwrap().push_str(" ...");
+        }
+        for line in lines {
+            line_y -= VERSO_LINE_HEIGHT;
+            layer.use_text(line, 9.0, Mm(x), Mm(line_y), font);
+        }
+    }
+}
@@ -0,0 +1,510 @@
+// Reading a study's PGN into positions: diagram markers, mainlines and sidelines, and
+// critical positions of games
+
+use crate::{ChessPosition, HintMode, Options, ParseError, Progress, SkippedChapter, SolutionsMode, StudyData, StudyInfo, chess960, critical, eco, engine, normalize_fen, pgn, shapes, variant};
+use crate::solutions::{annotated_solution, numbered_moves};
+use anyhow::{Result, anyhow};
+
+// A diagram marked inside a chapter's move text
+struct MarkedDiagram {
+    label: String,
+    fen: String,
+    opening: Option<String>,
+    // Index of the marked move in the mainline
+    ply: usize,
+    // From an [%eval] command in the marked move's comment
+    eval: Option<engine::Score>,
+    // From [%cal] and [%csl] commands in the marked move's comment
+    shapes: Vec<shapes::Shape>,
+    // The rest of the variation for diagrams taken from one (--variation-diagrams); the
+    // mainline's moves from `ply` on solve the others
+    sideline: Option<Sideline>,
+}
+
+// A variation's moves after a diagram, in SAN with the FEN after each, and as parsed
+struct Sideline {
+    moves: Vec<String>,
+    fens: Vec<String>,
+    nodes: Vec<pgn::MoveNode>,
+}
+
+// What following a chapter's mainline yields: the marked diagrams and the moves in SAN
+// with the FEN after each, along with the parsed mainline up to the first illegal move
+struct Mainline {
+    diagrams: Vec<MarkedDiagram>,
+    moves: Vec<String>,
+    fens: Vec<String>,
+    line: pgn::Line,
+    // From [%eval], [%cal] and [%csl] commands in the comment before the first move
+    start_eval: Option<engine::Score>,
+    start_shapes: Vec<shapes::Shape>,
+}
+
+// A PGN with a chapter for each FEN of a pasted list, one a line, named by their numbers
+pub fn fen_list_pgn(fens: &str) -> String {
+    fens.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(i, fen)| format!("[Event \"Positions\"]\n[ChapterName \"Position {}\"]\n[FEN \"{}\"]\n\n*\n\n", i + 1, fen))
+        .collect()
+}
+
+pub fn read_lichess_study(filename: &str, options: &Options) -> Result<StudyData, ParseError> {
+    let games = pgn::read_games(pgn_reader(filename, options, true)?);
+    let opening_book = options.eco.then(eco::OpeningBook::load);
+    let mut positions = Vec::new();
+    let mut position_number = 1;
+    let mut study_name = String::new();
+    let mut author = String::new();
+    let mut info = StudyInfo::default();
+    
+    // Extract study name from the first [Event] line which usually contains the study name
+    let mut found_study_name = false;
+    let mut chapters = 0;
+    
+    for (chapter_index, game) in games.enumerate() {
+        let game = game?;
+        chapters += 1;
+        // StudyName takes priority over Event
+        if let Some(name) = game.tag("StudyName") {
+            if !found_study_name {
+                study_name = name.to_string();
+                found_study_name = true;
+            }
+        }
+        
+        // Lichess names the study's owner as https://lichess.org/@/user
+        if let Some(annotator) = game.tag("Annotator") {
+            let name = annotator.rsplit("/@/").next().unwrap_or(annotator).to_string();
+            if author.is_empty() {
+                author = name.clone();
+            }
+            if !info.authors.contains(&name) {
+                info.authors.push(name);
+            }
+        }
+        if let Some(event) = game.tag("Event") {
+            // Use the first Event as the study name if we haven't found StudyName yet
+            if !found_study_name && study_name.is_empty() {
+                // Remove "WM25: " prefix if present for the study name
+                study_name = event.strip_prefix("WM25: ").unwrap_or(event).to_string();
+            }
+        }
+        
+        let chapter = game.tag("ChapterName").unwrap_or_default();
+        let fen = normalize_fen(game.tag("FEN").unwrap_or_default());
+        let fen = fen.as_str();
+        
+        // Skip chapters of variants we cannot render
+        let chapter_variant = match variant::parse_variant_tag(game.tag("Variant")) {
+            Ok(chapter_variant) => chapter_variant,
+            Err(e) => {
+                skip_chapter(&mut info, chapter_index + 1, chapter, e);
+                continue;
+            }
+        };
+        
+        // Chess960 chapters are tagged as such or use Shredder-FEN castling rights (e.g. HAha)
+        let chess960 = game.tag("Variant").is_some_and(|variant| variant.eq_ignore_ascii_case("Chess960"))
+            || chess960::is_shredder_castling(fen);
+        let start_position = if chess960 { chess960::start_position_number(fen) } else { None };
+        let variant_name = variant::label(chapter_variant, chess960).map(str::to_string);
+        // A bad FEN only costs its own chapter; variant positions must be legal in their variant
+        if !fen.is_empty() {
+            let checked = if variant_name.is_some() { variant::validate(fen, chapter_variant, chess960) } else { variant::check_syntax(fen) };
+            if let Err(e) = checked {
+                skip_chapter(&mut info, chapter_index + 1, chapter, e);
+                continue;
+            }
+        }
+        
+        // Counted for the info page only once the chapter is in
+        let variant = game.tag("Variant").unwrap_or("Standard");
+        match info.variants.iter_mut().find(|(name, _)| name == variant) {
+            Some((_, count)) => *count += 1,
+            None => info.variants.push((variant.to_string(), 1)),
+        }
+        
+        let mainline = follow_mainline(
+            &game,
+            fen,
+            chapter_variant,
+            chess960,
+            options.diagram_keyword.as_deref(),
+            opening_book.as_ref(),
+            options.pdf.solutions().is_some() || options.hints == Some(HintMode::MoveCount) || !options.exports.is_empty(),
+            options.variation_diagrams,
+        );
+        
+        let annotated = options.pdf.solutions() == Some(SolutionsMode::Annotated);
+        // Lichess exports the chapter's address as ChapterURL, older exports only as Site
+        let chapter_url = game
+            .tag("ChapterURL")
+            .or(game.tag("Site").filter(|site| site.starts_with(&format!("{}/study/", options.api_base))))
+            .map(str::to_string);
+        if info.url.is_none() {
+            info.url = chapter_url.as_deref().and_then(|url| url.rsplit_once('/')).map(|(study, _)| study.to_string()).filter(|study| study.contains("/study/"));
+        }
+        
+        // When we have ChapterName and FEN, create position; its solution is the whole mainline
+        if !chapter.is_empty() && !fen.is_empty() {
+            positions.push(ChessPosition {
+                variant: variant_name.clone(),
+                start_position,
+                solution: mainline.moves.clone(),
+                theme: game.tag("Theme").map(str::to_string),
+                rating: game.tag("Rating").and_then(|rating| rating.parse().ok()),
+                annotated_solution: annotated
+                    .then(|| annotated_solution(fen, &mainline.line.comments, &mainline.line.moves))
+                    .flatten(),
+                key_fen: mainline.fens.first().cloned(),
+                eval: mainline.start_eval,
+                shapes: mainline.start_shapes.clone(),
+                chapter: chapter_index + 1,
+                chapter_name: chapter.to_string(),
+                url: chapter_url.clone(),
+                ..ChessPosition::new(position_number, chapter.to_string(), fen.to_string())
+            });
+            position_number += 1;
+        }
+        
+        // Additional diagrams marked inside the chapter's move text, solved by the moves that follow
+        for diagram in mainline.diagrams {
+            let description = match (chapter.is_empty(), diagram.sideline.is_some()) {
+                (true, false) => format!("After {}", diagram.label),
+                (false, false) => format!("{} (after {})", chapter, diagram.label),
+                (true, true) => format!("Variation {}", diagram.label),
+                (false, true) => format!("{} (variation {})", chapter, diagram.label),
+            };
+            let (solution, fens, nodes) = match &diagram.sideline {
+                Some(sideline) => (&sideline.moves[..], &sideline.fens[..], &sideline.nodes[..]),
+                None => (
+                    mainline.moves.get(diagram.ply + 1..).unwrap_or_default(),
+                    mainline.fens.get(diagram.ply + 1..).unwrap_or_default(),
+                    mainline.line.moves.get(diagram.ply + 1..).unwrap_or_default(),
+                ),
+            };
+            positions.push(ChessPosition {
+                variant: variant_name.clone(),
+                start_position,
+                opening: diagram.opening,
+                solution: solution.to_vec(),
+                annotated_solution: annotated.then(|| annotated_solution(&diagram.fen, &[], nodes)).flatten(),
+                key_fen: fens.first().cloned(),
+                eval: diagram.eval,
+                shapes: diagram.shapes,
+                chapter: chapter_index + 1,
+                chapter_name: chapter.to_string(),
+                url: chapter_url.clone(),
+                ..ChessPosition::new(position_number, description, diagram.fen)
+            });
+            position_number += 1;
+        }
+    }
+    
+    // If no study name found, use a default
+    if study_name.is_empty() {
+        study_name = "Chess Positions".to_string();
+    }
+    
+    // Check if we found any positions
+    if positions.is_empty() {
+        return Err(ParseError::NoPositions);
+    }
+    options.report(Progress::Parsed { chapters, positions: positions.len() });
+    info.chapters = chapters;
+    
+    Ok(StudyData {
+        name: study_name,
+        author,
+        positions,
+        pgn: source_pgn(filename, options)?,
+        info,
+    })
+}
+
+// Warn about a chapter left out and keep it for the summary at the end of the run
+fn skip_chapter(info: &mut StudyInfo, chapter: usize, name: &str, reason: anyhow::Error) {
+    eprintln!("Warning: skipping chapter '{}': {}", name, reason);
+    info.skipped.push(SkippedChapter { chapter, name: name.to_string(), reason: reason.to_string() });
+}
+
+// The chapters left out of the study, as a table after everything else the run printed
+pub fn print_skipped(study_data: &StudyData) {
+    let skipped = &study_data.info.skipped;
+    if skipped.is_empty() {
+        return;
+    }
+    println!("Skipped {} chapter{} of {}:", skipped.len(), if skipped.len() == 1 { "" } else { "s" }, study_data.name);
+    let width = skipped.iter().map(|chapter| chapter.name.chars().count()).max().unwrap_or(0).max("Chapter".len());
+    println!("  {:>3}  {:<width$}  Reason", "#", "Chapter");
+    for chapter in skipped {
+        println!("  {:>3}  {:<width$}  {}", chapter.chapter, chapter.name, chapter.reason);
+    }
+}
+
+// The PGN to embed with --attach-pgn, read again as a whole; not kept otherwise
+fn source_pgn(filename: &str, options: &Options) -> Result<String, ParseError> {
+    let mut text = String::new();
+    if options.attach_pgn {
+        std::io::Read::read_to_string(&mut pgn_reader(filename, options, false)?, &mut text)?;
+    }
+    Ok(text)
+}
+
+// The PGN's games are read through pgn::decode_reader, in the encoding of its byte order mark or
+// the --encoding, else UTF-8 or Windows-1252; `notice` names a Windows-1252 file on stderr
+fn pgn_reader(filename: &str, options: &Options, notice: bool) -> Result<pgn::DecodeReader<Box<dyn std::io::BufRead>>, ParseError> {
+    let reader = options.storage.open(filename)?;
+    Ok(pgn::decode_reader(reader, options.encoding, notice.then_some(filename))?)
+}
+
+// Analyse every position of every game and turn the worst moves into puzzles.
+// The solution shows the engine's better move and the move that was played
+pub fn read_critical_positions(filename: &str, options: &Options) -> Result<StudyData> {
+    let config = options.engine.as_ref().ok_or_else(|| anyhow!("--games requires --engine"))?;
+    println!("Reading games from {}...", filename);
+    let games = pgn::read_games(pgn_reader(filename, options, true)?);
+    
+    let mut lines = Vec::new();
+    let mut event = None;
+    let mut info = StudyInfo::default();
+    for (i, game) in games.enumerate() {
+        let game = game?;
+        event = event.or_else(|| game.tag("Event").filter(|event| *event != "?").map(str::to_string));
+        let start_fen = game.tag("FEN").map(normalize_fen);
+        match critical::game_line(&game, start_fen.as_deref()) {
+            Ok(line) => lines.push((i + 1, line)),
+            Err(e) => {
+                eprintln!("Warning: skipping game {}: {}", i + 1, e);
+                info.skipped.push(SkippedChapter { chapter: i + 1, name: format!("Game {}", i + 1), reason: e.to_string() });
+            }
+        }
+    }
+    
+    let jobs: Vec<engine::EngineJob> = lines
+        .iter()
+        .flat_map(|(_, line)| line.fens.iter())
+        .map(|fen| engine::EngineJob { fen: fen.clone(), chess960: false })
+        .collect();
+    println!("Analysing {} positions from {} games with {} (depth {})...", jobs.len(), lines.len(), config.path, config.depth);
+    let mut analyses = engine::analyse_all(config, &jobs)?.into_iter();
+    
+    let mut positions = Vec::new();
+    for (game_number, line) in &lines {
+        let line_analyses: Vec<Option<engine::Analysis>> = analyses.by_ref().take(line.fens.len()).collect();
+        for critical in critical::critical_moves(line, &line_analyses, options.critical_max) {
+            let description = format!("Find the better move: {}", line.title);
+            let mut pos = ChessPosition::new(positions.len() as i32 + 1, description, line.fens[critical.index].clone());
+            pos.analysis = line_analyses[critical.index].clone();
+            pos.chapter = *game_number;
+            pos.chapter_name = line.title.clone();
+            pos.game_move = Some(format!(
+                "{}{} ({} to {})",
+                numbered_moves(&line.fens[critical.index], &line.moves[critical.index..=critical.index]),
+                critical.annotation(),
+                critical.before.display(),
+                critical.after.display()
+            ));
+            positions.push(pos);
+        }
+    }
+    
+    if positions.is_empty() {
+        return Err(anyhow!("No critical positions found in the games"));
+    }
+    println!("Found {} critical positions", positions.len());
+    options.report(Progress::Parsed { chapters: lines.len(), positions: positions.len() });
+    
+    Ok(StudyData {
+        name: event.unwrap_or_else(|| "Critical Positions".to_string()),
+        author: String::new(),
+        positions,
+        pgn: source_pgn(filename, options)?,
+        info: StudyInfo { chapters: lines.len(), ..info },
+    })
+}
+
+// Diagram markers: a [%diagram] command or the configured keyword in a move comment,
+// or the "diagram" NAG ($220) used by ChessBase and other PGN editors
+fn is_diagram_marker(comments: &[String], nags: &[u16], keyword: Option<&str>) -> bool {
+    if nags.contains(&220) {
+        return true;
+    }
+    comments.iter().any(|comment| {
+        comment.contains("[%diagram]")
+            || keyword.is_some_and(|keyword| {
+                comment
+                    .split(|c: char| !c.is_alphanumeric())
+                    .any(|word| word.eq_ignore_ascii_case(keyword))
+            })
+    })
+}
+
+// Play through the chapter's mainline and collect every position marked as a diagram,
+// along with the deepest known opening reached on the way when a book is given. With
+// `variations`, the variations are followed too (see follow_sideline).
+// The moves are only kept (in SAN) when `keep_moves` is set
+#[allow(clippy::too_many_arguments)]
+fn follow_mainline(
+    game: &pgn::PgnGame,
+    start_fen: &str,
+    chapter_variant: shakmaty::variant::Variant,
+    chess960: bool,
+    keyword: Option<&str>,
+    opening_book: Option<&eco::OpeningBook>,
+    keep_moves: bool,
+    variations: bool,
+) -> Mainline {
+    use shakmaty::{fen::Fen, san::SanPlus, Color, EnPassantMode, Position};
+    
+    let mainline = pgn::parse_movetext(&game.movetext);
+    let mut result = Mainline {
+        diagrams: Vec::new(),
+        moves: Vec::new(),
+        fens: Vec::new(),
+        line: pgn::Line::default(),
+        start_eval: pgn::comment_eval(&mainline.comments),
+        start_shapes: shapes::comment_shapes(&mainline.comments),
+    };
+    let has_markers = mainline.moves.iter().any(|node| is_diagram_marker(&node.comments, &node.nags, keyword));
+    if mainline.moves.is_empty() || (!has_markers && !keep_moves && !variations) {
+        return result;
+    }
+    
+    let chapter = game.tag("ChapterName").unwrap_or("?");
+    
+    // Without a FEN a Chess960 chapter's start position is unknown
+    let start_fen = Some(start_fen).filter(|fen| !fen.is_empty());
+    if chess960 && start_fen.is_none() {
+        eprintln!("Warning: cannot follow the moves of Chess960 chapter '{}' without a FEN", chapter);
+        return result;
+    }
+    let mut position = match variant::start_position(start_fen, chapter_variant, chess960) {
+        Ok(position) => position,
+        Err(e) => {
+            eprintln!("Warning: cannot follow the moves of chapter '{}': {}", chapter, e);
+            return result;
+        }
+    };
+    
+    // Opening names only make sense for standard chess
+    let opening_book = opening_book.filter(|_| chapter_variant == shakmaty::variant::Variant::Chess && !chess960);
+    
+    // Lichess tags the chapter's opening; used when the book has no match
+    let tagged_opening = match (opening_book, game.tag("ECO"), game.tag("Opening")) {
+        (Some(_), Some(code), Some(name)) if code != "?" && name != "?" => Some(format!("{} {}", code, name)),
+        _ => None,
+    };
+    let mut opening = opening_book.and_then(|book| book.lookup(&position)).map(str::to_string);
+    
+    for (ply, node) in mainline.moves.iter().enumerate() {
+        let move_number = position.fullmoves();
+        let white_moved = position.turn() == Color::White;
+        
+        // Alternatives to this move start from the position before it
+        if variations {
+            for variation in &node.variations {
+                follow_sideline(&position, variation, keyword, chapter, opening.clone().or_else(|| tagged_opening.clone()), ply, &mut result.diagrams);
+            }
+        }
+        
+        let m = match SanPlus::from_ascii(node.san.as_bytes()).ok().and_then(|san| san.san.to_move(&position).ok()) {
+            Some(m) => m,
+            None => {
+                eprintln!("Warning: illegal move '{}' in chapter '{}', ignoring the rest of the mainline", node.san, chapter);
+                break;
+            }
+        };
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, m).to_string();
+        
+        if let Some(name) = opening_book.and_then(|book| book.lookup(&position)) {
+            opening = Some(name.to_string());
+        }
+        
+        if is_diagram_marker(&node.comments, &node.nags, keyword) {
+            let label = if white_moved {
+                format!("{}. {}", move_number, san)
+            } else {
+                format!("{}... {}", move_number, san)
+            };
+            let fen = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+            result.diagrams.push(MarkedDiagram {
+                label,
+                fen,
+                opening: opening.clone().or_else(|| tagged_opening.clone()),
+                ply,
+                eval: pgn::comment_eval(&node.comments),
+                shapes: shapes::comment_shapes(&node.comments),
+                sideline: None,
+            });
+        }
+        if keep_moves {
+            result.moves.push(san);
+            result.fens.push(Fen::from_position(&position, EnPassantMode::Legal).to_string());
+        }
+    }
+    
+    if keep_moves {
+        result.line = mainline;
+        result.line.moves.truncate(result.moves.len());
+    }
+    result
+}
+
+// Diagrams from a variation starting at `start` and the variations nested in it: after its
+// first move when the variation is annotated (a comment or NAG there) or marked, and after
+// every marked move further on. The rest of the variation solves each diagram
+fn follow_sideline(
+    start: &shakmaty::variant::VariantPosition,
+    line: &pgn::Line,
+    keyword: Option<&str>,
+    chapter: &str,
+    opening: Option<String>,
+    ply: usize,
+    diagrams: &mut Vec<MarkedDiagram>,
+) {
+    use shakmaty::{fen::Fen, san::SanPlus, Color, EnPassantMode, Position};
+    
+    // Each playable move with its label, the FEN after it and the position before it
+    let mut position = start.clone();
+    let mut played = Vec::new();
+    for node in &line.moves {
+        let before = position.clone();
+        let Some(m) = SanPlus::from_ascii(node.san.as_bytes()).ok().and_then(|san| san.san.to_move(&position).ok()) else {
+            eprintln!("Warning: illegal move '{}' in a variation of chapter '{}', ignoring the rest of the variation", node.san, chapter);
+            break;
+        };
+        let (move_number, white_moved) = (position.fullmoves(), position.turn() == Color::White);
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, m).to_string();
+        let label = if white_moved { format!("{}. {}", move_number, san) } else { format!("{}... {}", move_number, san) };
+        played.push((label, san, Fen::from_position(&position, EnPassantMode::Legal).to_string(), before));
+    }
+    
+    for (i, (node, (label, _, fen, before))) in line.moves.iter().zip(&played).enumerate() {
+        let annotated = i == 0
+            && (!node.nags.is_empty()
+                || is_diagram_marker(&line.comments, &[], keyword)
+                || line.comments.iter().chain(&node.comments).any(|comment| !pgn::strip_commands(comment).is_empty()));
+        if annotated || is_diagram_marker(&node.comments, &node.nags, keyword) {
+            let rest = &played[i + 1..];
+            diagrams.push(MarkedDiagram {
+                label: label.clone(),
+                fen: fen.clone(),
+                opening: opening.clone(),
+                ply,
+                eval: pgn::comment_eval(&node.comments),
+                shapes: shapes::comment_shapes(&node.comments),
+                sideline: Some(Sideline {
+                    moves: rest.iter().map(|(_, san, _, _)| san.clone()).collect(),
+                    fens: rest.iter().map(|(_, _, fen, _)| fen.clone()).collect(),
+                    nodes: line.moves[i + 1..played.len()].to_vec(),
+                }),
+            });
+        }
+        for variation in &node.variations {
+            follow_sideline(before, variation, keyword, chapter, opening.clone(), ply, diagrams);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// Downloaded studies kept between runs in the user's cache directory ($XDG_CACHE_HOME/fen2pdf/
+// studies, else ~/.cache/fen2pdf/studies), one PGN per study ID. The ETag and Last-Modified
+// headers of the download are stored next to it, so later runs ask Lichess for the study only
+// if it changed and reuse the file on 304 Not Modified
+
+use crate::platform::HttpResponse;
+use anyhow::{Result, anyhow};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Response headers kept with the PGN, each sent back in the request header beside it
+const VALIDATORS: [(&str, &str); 2] = [("etag", "if-none-match"), ("last-modified", "if-modified-since")];
+
+pub struct CachedStudy {
+    pub pgn: PathBuf,
+    // The stored headers, one "Name: value" line each
+    headers: PathBuf,
+}
+
+// The cache directory; None when no home directory is known
+pub fn studies_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME").filter(|dir| Path::new(dir).is_absolute()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?).join(".cache"),
+    };
+    Some(base.join("fen2pdf").join("studies"))
+}
+
+impl CachedStudy {
+    pub fn new(dir: &Path, study_id: &str) -> Self {
+        let file_id: String = study_id.chars().filter(char::is_ascii_alphanumeric).collect();
+        CachedStudy { pgn: dir.join(format!("{}.pgn", file_id)), headers: dir.join(format!("{}.headers", file_id)) }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.pgn.is_file()
+    }
+
+    // Conditional request headers for the cached copy; empty when there is none
+    pub fn validators(&self) -> Vec<(String, String)> {
+        if !self.exists() {
+            return Vec::new();
+        }
+        let Ok(stored) = fs::read_to_string(&self.headers) else { return Vec::new() };
+        stored
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(": ")?;
+                let (_, request_name) = VALIDATORS.iter().find(|(response_name, _)| response_name.eq_ignore_ascii_case(name))?;
+                Some((request_name.to_string(), value.to_string()))
+            })
+            .collect()
+    }
+
+    // Replaces the cached copy. The old headers go first, so an interrupted write cannot pair
+    // them with a newer PGN
+    pub fn store(&self, content: &[u8], response: &HttpResponse) -> Result<()> {
+        let dir = self.pgn.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir).map_err(|e| anyhow!("Cannot create cache directory '{}': {}", dir.display(), e))?;
+        if self.headers.exists() {
+            fs::remove_file(&self.headers)?;
+        }
+        // Written under another name first, so a concurrent run never reads half a file
+        let partial = self.pgn.with_extension(format!("pgn.{}", std::process::id()));
+        fs::write(&partial, content)?;
+        fs::rename(&partial, &self.pgn)?;
+
+        let stored: String = VALIDATORS
+            .iter()
+            .filter_map(|(name, _)| Some(format!("{}: {}\n", name, response.header(name)?)))
+            .collect();
+        if !stored.is_empty() {
+            fs::write(&self.headers, stored)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+// Font subsetting: printpdf embeds TrueType fonts (the serif faces of --pdfa) whole, several
+// hundred kilobytes each, although a study uses a few dozen glyphs. The outlines of unused
+// glyphs are dropped; glyph numbers stay the same, so the text and widths need no changes
+
+use anyhow::Result;
+use printpdf::lopdf::content::Content;
+use printpdf::lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Tables a PDF viewer needs from an embedded CIDFontType2 program; the others (character
+// maps, names, layout features) only serve text shaping and font menus
+const KEPT_TABLES: [&[u8; 4]; 10] = [b"OS/2", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep"];
+
+// Composite glyph flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+// Replaces every embedded TrueType font program with a subset of the glyphs the pages show
+pub fn subset_fonts(document: &mut Document) -> Result<()> {
+    let mut used = used_glyphs(document)?;
+    // Fonts no page shows (the bold face of a document without headings) keep just .notdef
+    let fonts: Vec<ObjectId> = document
+        .objects
+        .iter()
+        .filter(|(_, object)| object.as_dict().and_then(|font| font.get(b"Subtype")).and_then(Object::as_name_str).ok() == Some("Type0"))
+        .map(|(&id, _)| id)
+        .collect();
+    for font_id in fonts {
+        let glyphs = used.remove(&font_id).unwrap_or_default();
+        let Some((descriptor_id, file_id)) = font_file(document, font_id) else { continue };
+        let Ok(Object::Stream(stream)) = document.get_object(file_id) else { continue };
+        let program = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+        // CFF-based OpenType and damaged fonts are embedded as they are
+        let Some(subset) = subset_program(&program, &glyphs) else { continue };
+
+        let Ok(Object::Stream(stream)) = document.get_object_mut(file_id) else { continue };
+        stream.dict.set("Length1", subset.len() as i64);
+        stream.set_plain_content(subset);
+
+        // Subset fonts are named with a tag of six capitals derived from their glyphs
+        let digest = md5::compute(glyphs.iter().flat_map(|glyph| glyph.to_be_bytes()).collect::<Vec<u8>>());
+        let tag: String = digest.0[..6].iter().map(|byte| (b'A' + byte % 26) as char).collect();
+        let rename = |dictionary: &mut Dictionary, key: &[u8]| {
+            if let Ok(name) = dictionary.get(key).and_then(Object::as_name_str) {
+                let name = format!("{}+{}", tag, name);
+                dictionary.set(key.to_vec(), Object::Name(name.into_bytes()));
+            }
+        };
+        if let Ok(descriptor) = document.get_object_mut(descriptor_id).and_then(Object::as_dict_mut) {
+            rename(descriptor, b"FontName");
+        }
+        if let Ok(font) = document.get_object_mut(font_id).and_then(Object::as_dict_mut) {
+            rename(font, b"BaseFont");
+            if let Ok(Some(Object::Dictionary(descendant))) = font.get_mut(b"DescendantFonts").and_then(Object::as_array_mut).map(|fonts| fonts.first_mut()) {
+                rename(descendant, b"BaseFont");
+            }
+        }
+    }
+    Ok(())
+}
+
+// Two-byte glyph numbers shown with each Type0 font on any page
+fn used_glyphs(document: &Document) -> Result<BTreeMap<ObjectId, BTreeSet<u16>>> {
+    let mut used: BTreeMap<ObjectId, BTreeSet<u16>> = BTreeMap::new();
+    for page_id in document.get_pages().into_values() {
+        let fonts = page_fonts(document, page_id);
+        let content = Content::decode(&document.get_page_content(page_id)?)?;
+        let mut font = None;
+        for operation in &content.operations {
+            match operation.operator.as_str() {
+                "Tf" => font = operation.operands.first().and_then(|name| name.as_name().ok()).and_then(|name| fonts.get(name)).copied(),
+                "Tj" | "TJ" | "'" | "\"" => {
+                    let Some(font) = font else { continue };
+                    let glyphs = used.entry(font).or_default();
+                    let mut strings: Vec<&Object> = operation.operands.iter().collect();
+                    if let Some(Object::Array(items)) = operation.operands.first() {
+                        strings.extend(items);
+                    }
+                    for string in strings {
+                        if let Object::String(bytes, _) = string {
+                            glyphs.extend(bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(used)
+}
+
+// Resource names of the page's fonts and their objects
+fn page_fonts(document: &Document, page_id: ObjectId) -> BTreeMap<Vec<u8>, ObjectId> {
+    let fonts = document
+        .get_dictionary(page_id)
+        .and_then(|page| page.get(b"Resources"))
+        .ok()
+        .and_then(|resources| resolve(document, resources))
+        .and_then(|resources| resources.get(b"Font").ok())
+        .and_then(|fonts| resolve(document, fonts));
+    fonts
+        .map(|fonts| fonts.iter().filter_map(|(name, font)| Some((name.clone(), font.as_reference().ok()?))).collect())
+        .unwrap_or_default()
+}
+
+// A dictionary given directly or by reference
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    match object {
+        Object::Reference(id) => document.get_dictionary(*id).ok(),
+        Object::Dictionary(dictionary) => Some(dictionary),
+        _ => None,
+    }
+}
+
+// The font descriptor and embedded TrueType program of a Type0 font
+fn font_file(document: &Document, font_id: ObjectId) -> Option<(ObjectId, ObjectId)> {
+    let font = document.get_dictionary(font_id).ok()?;
+    let descendant = resolve(document, font.get(b"DescendantFonts").and_then(Object::as_array).ok()?.first()?)?;
+    let descriptor_id = descendant.get(b"FontDescriptor").and_then(Object::as_reference).ok()?;
+    let file_id = document.get_dictionary(descriptor_id).ok()?.get(b"FontFile2").and_then(Object::as_reference).ok()?;
+    Some((descriptor_id, file_id))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+// The font with empty outlines for all glyphs but `used`, .notdef and their components, and
+// only the KEPT_TABLES; None when it is not a TrueType font
+fn subset_program(program: &[u8], used: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    if read_u32(program, 0)? != 0x0001_0000 && &program[..4] != b"true" {
+        return None;
+    }
+    let mut tables: BTreeMap<[u8; 4], &[u8]> = BTreeMap::new();
+    for i in 0..read_u16(program, 4)? as usize {
+        let entry = 12 + 16 * i;
+        let tag: [u8; 4] = program.get(entry..entry + 4)?.try_into().ok()?;
+        let (offset, length) = (read_u32(program, entry + 8)? as usize, read_u32(program, entry + 12)? as usize);
+        tables.insert(tag, program.get(offset..offset + length)?);
+    }
+    let head = *tables.get(b"head")?;
+    let long_offsets = read_u16(head, 50)? == 1;
+    let glyph_count = read_u16(tables.get(b"maxp")?, 4)? as usize;
+    let (loca, glyf) = (*tables.get(b"loca")?, *tables.get(b"glyf")?);
+    let glyph = |id: usize| -> Option<&[u8]> {
+        let (start, end) = if long_offsets {
+            (read_u32(loca, 4 * id)? as usize, read_u32(loca, 4 * id + 4)? as usize)
+        } else {
+            (2 * read_u16(loca, 2 * id)? as usize, 2 * read_u16(loca, 2 * id + 2)? as usize)
+        };
+        glyf.get(start..end)
+    };
+
+    // Composite glyphs are drawn from other glyphs, which have to stay as well
+    let mut kept: BTreeSet<usize> = used.iter().map(|&id| id as usize).filter(|&id| id < glyph_count).collect();
+    kept.insert(0);
+    let mut pending: Vec<usize> = kept.iter().copied().collect();
+    while let Some(id) = pending.pop() {
+        let data = glyph(id)?;
+        if data.len() < 10 || (read_u16(data, 0)? as i16) >= 0 {
+            continue;
+        }
+        let mut offset = 10;
+        loop {
+            let flags = read_u16(data, offset)?;
+            let component = read_u16(data, offset + 2)? as usize;
+            if component < glyph_count && kept.insert(component) {
+                pending.push(component);
+            }
+            offset += 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+            offset += if flags & WE_HAVE_A_SCALE != 0 {
+                2
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                4
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                8
+            } else {
+                0
+            };
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity(4 * (glyph_count + 1));
+    for id in 0..glyph_count {
+        new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+        if kept.contains(&id) {
+            new_glyf.extend(glyph(id)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+    // Long offsets in the new loca table; the checksum adjustment is set once the font is complete
+    let mut new_head = head.to_vec();
+    new_head.get_mut(50..52)?.copy_from_slice(&1u16.to_be_bytes());
+    new_head.get_mut(8..12)?.copy_from_slice(&[0; 4]);
+
+    let mut subset_tables: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    for tag in KEPT_TABLES {
+        let data = match tag {
+            b"glyf" => std::mem::take(&mut new_glyf),
+            b"loca" => std::mem::take(&mut new_loca),
+            b"head" => std::mem::take(&mut new_head),
+            _ => match tables.get(tag) {
+                Some(data) => data.to_vec(),
+                None => continue,
+            },
+        };
+        subset_tables.push((*tag, data));
+    }
+    Some(write_font(&subset_tables))
+}
+
+// A TrueType file from its tables (in tag order), with checksums
+fn write_font(tables: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let checksum = |data: &[u8]| {
+        data.chunks(4).fold(0u32, |sum, chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            sum.wrapping_add(u32::from_be_bytes(word))
+        })
+    };
+    let count = tables.len() as u16;
+    let entry_selector = 15 - count.leading_zeros() as u16;
+    let search_range = 16 << entry_selector;
+    let mut font = Vec::new();
+    font.extend(0x0001_0000u32.to_be_bytes());
+    for value in [count, search_range, entry_selector, count * 16 - search_range] {
+        font.extend(value.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = 0;
+    for (tag, data) in tables {
+        if tag == b"head" {
+            head_offset = offset;
+        }
+        font.extend(tag);
+        font.extend(checksum(data).to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in tables {
+        font.extend(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+    font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    font
+}
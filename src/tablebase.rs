@@ -0,0 +1,72 @@
+// Endgame tablebase results from the Lichess tablebase API (Syzygy, up to 7 pieces)
+
+use crate::platform::Network;
+use anyhow::{Result, anyhow};
+
+const TABLEBASE_URL: &str = "https://tablebase.lichess.ovh/standard";
+pub const MAX_PIECES: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+// Result for the side to move
+#[derive(Debug, Clone)]
+pub struct TablebaseResult {
+    pub outcome: Outcome,
+    // Moves to mate (DTM) when known, otherwise plies to a zeroing move (DTZ)
+    pub dtm: Option<u32>,
+    pub dtz: Option<u32>,
+    pub best_move: Option<String>,
+}
+
+impl TablebaseResult {
+    // "Win in 23", "Loss (DTZ 14)" or "Draw"
+    pub fn display(&self) -> String {
+        let outcome = match self.outcome {
+            Outcome::Win => "Win",
+            Outcome::Draw => return "Draw".to_string(),
+            Outcome::Loss => "Loss",
+        };
+        match (self.dtm, self.dtz) {
+            (Some(moves), _) => format!("{} in {}", outcome, moves),
+            (None, Some(plies)) => format!("{} (DTZ {})", outcome, plies),
+            (None, None) => outcome.to_string(),
+        }
+    }
+}
+
+// Positions the tablebase covers: at most seven pieces and no castling rights
+pub fn is_covered(fen: &str) -> bool {
+    let mut fields = fen.split_whitespace();
+    let pieces = fields.next().unwrap_or("").chars().filter(|c| c.is_ascii_alphabetic()).count();
+    let castling = fields.nth(1).unwrap_or("-");
+    pieces <= MAX_PIECES && castling == "-"
+}
+
+pub fn probe(network: &dyn Network, fen: &str) -> Result<Option<TablebaseResult>> {
+    let url = url::Url::parse_with_params(TABLEBASE_URL, [("fen", fen)])?;
+    let response = network.get(url.as_str(), &[])?;
+    if !response.is_success() {
+        return Err(anyhow!("Tablebase request failed: HTTP {}", response.status));
+    }
+    let json: serde_json::Value = serde_json::from_slice(&response.body)?;
+
+    // Cursed wins and blessed losses are drawn under the 50-move rule
+    let outcome = match json["category"].as_str() {
+        Some("win" | "syzygy-win" | "maybe-win") => Outcome::Win,
+        Some("loss" | "syzygy-loss" | "maybe-loss") => Outcome::Loss,
+        Some("draw" | "cursed-win" | "blessed-loss") => Outcome::Draw,
+        _ => return Ok(None),
+    };
+
+    // DTM is given in plies; convert to moves of the side to move
+    let dtm = json["dtm"].as_i64().map(|plies| (plies.unsigned_abs() as u32).div_ceil(2));
+    let dtz = json["dtz"].as_i64().map(|plies| plies.unsigned_abs() as u32);
+    let best_move = json["moves"][0]["san"].as_str().map(str::to_string);
+
+    Ok(Some(TablebaseResult { outcome, dtm, dtz, best_move }))
+}
@@ -0,0 +1,100 @@
+// Page templates (--template): where the title, the diagram grid, a footer with the page
+// number and a sidebar of ruled note lines go on every page, for sheet layouts of one's own
+// such as six diagrams beside a notes column. One region per line, in mm from the page's top
+// left corner:
+//
+//   # name   x   y  width height  extra
+//   title   20  10   170    15
+//   grid    15  30   125   245    2x3
+//   sidebar 145 30    50   245    Notes
+//   footer  20 280   170    10
+//
+// The grid region's extra is its columns x rows; the sidebar's is its heading. Only the grid
+// is required; pages leave out the regions the template has not
+
+use anyhow::{Result, anyhow};
+
+// A rectangle in mm from the page's top left corner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Region {
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PageTemplate {
+    pub title: Option<Region>,
+    pub grid: Region,
+    // Diagrams across and down the grid region
+    pub columns: usize,
+    pub rows: usize,
+    pub footer: Option<Region>,
+    // Ruled lines for notes under a heading
+    pub sidebar: Option<(Region, String)>,
+}
+
+impl PageTemplate {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Cannot read template '{}': {}", path, e))?;
+        PageTemplate::parse(&text).map_err(|e| anyhow!("Template '{}': {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let (mut title, mut grid, mut footer, mut sidebar) = (None, None, None, None);
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 5 {
+                return Err(anyhow!("line {}: expected a region name, x, y, width and height", number + 1));
+            }
+            let numbers: Vec<f32> = fields[1..5]
+                .iter()
+                .map(|field| field.parse().map_err(|_| anyhow!("line {}: '{}' is not a length in mm", number + 1, field)))
+                .collect::<Result<_>>()?;
+            let region = Region { x: numbers[0], y: numbers[1], width: numbers[2], height: numbers[3] };
+            let (name, extra) = (fields[0], fields[5..].join(" "));
+            if region.width <= 0.0 || region.height <= 0.0 {
+                return Err(anyhow!("line {}: the {} region has no area", number + 1, name));
+            }
+            match name {
+                "title" => title = Some(region),
+                "footer" => footer = Some(region),
+                "sidebar" => sidebar = Some((region, extra)),
+                "grid" => {
+                    let size = extra.split_once('x').map(|(columns, rows)| (columns.parse(), rows.parse()));
+                    let Some((Ok(columns), Ok(rows))) = size else {
+                        return Err(anyhow!("line {}: the grid needs its columns x rows, e.g. 2x3", number + 1));
+                    };
+                    grid = Some((region, columns, rows));
+                }
+                _ => return Err(anyhow!("line {}: unknown region '{}'; use title, grid, footer or sidebar", number + 1, name)),
+            }
+        }
+        let (grid, columns, rows) = grid.ok_or_else(|| anyhow!("no grid region"))?;
+        Ok(PageTemplate { title, grid, columns, rows, footer, sidebar })
+    }
+
+    // The regions with their names, to check that they fit on the page
+    pub fn regions(&self) -> Vec<(&'static str, Region)> {
+        let mut regions = vec![("grid", self.grid)];
+        regions.extend(self.title.map(|region| ("title", region)));
+        regions.extend(self.footer.map(|region| ("footer", region)));
+        regions.extend(self.sidebar.as_ref().map(|(region, _)| ("sidebar", *region)));
+        regions
+    }
+}
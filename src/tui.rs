@@ -0,0 +1,225 @@
+// `fen2pdf tui [options] <study-id>`: a curation step before printing. The study's positions
+// are listed beside a preview of the selected board; they can be reordered, left out and
+// their captions edited, and `w` writes the PDF of the positions kept, in the order shown,
+// with the other options as on the command line
+
+use crate::{BoardCache, ChessPosition, Options, Source, StudyData};
+use anyhow::{Result, anyhow};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+const HELP: &str = "↑/↓ select  K/J move up/down  Space keep/leave out  e edit caption  w write PDF  q quit";
+const EDIT_HELP: &str = "Type the caption  Enter keep it  Esc cancel";
+
+struct Entry {
+    position: ChessPosition,
+    kept: bool,
+}
+
+struct Curation {
+    entries: Vec<Entry>,
+    list: ListState,
+    // The caption being typed, while editing the selected position's
+    editing: Option<String>,
+}
+
+pub fn run_tui(args: &[String]) -> Result<()> {
+    let mut argv = vec!["fen2pdf".to_string()];
+    argv.extend(args.iter().cloned());
+    let options = crate::parse_args(&argv)?;
+    let study_id = match &options.source {
+        Source::Study(study_ids) if study_ids.len() == 1 => study_ids[0].clone(),
+        _ => return Err(anyhow!("fen2pdf tui takes one study ID")),
+    };
+    // Listed as the options select and order them; the list's order is the one printed.
+    // Sorting by difficulty needs the analysis first, otherwise it waits for the positions kept
+    let mut study_data = crate::fetch_lichess_study(&study_id, &options)?;
+    crate::select_positions(&mut study_data, &options)?;
+    let sorted = options.sort.is_some();
+    if sorted {
+        crate::label_positions(&mut study_data, &options)?;
+    }
+
+    let mut curation = Curation {
+        entries: study_data.positions.iter().map(|position| Entry { position: position.clone(), kept: true }).collect(),
+        list: ListState::default().with_selected(Some(0)),
+        editing: None,
+    };
+    let write = curation.run(ratatui::init(), &options);
+    ratatui::restore();
+    if !write? {
+        println!("Left without writing a PDF");
+        return Ok(());
+    }
+
+    let positions = curation.kept_positions();
+    if positions.is_empty() {
+        return Err(anyhow!("All positions were left out; no PDF written"));
+    }
+    let mut study_data = StudyData { positions, ..study_data };
+    if !sorted {
+        crate::label_positions(&mut study_data, &options)?;
+    }
+    let boards = BoardCache::new(options.cache_dir.as_deref())?;
+    crate::write_study(study_data, &options, &boards)
+}
+
+impl Curation {
+    // Until `w` (true) or `q` (false)
+    fn run(&mut self, mut terminal: DefaultTerminal, options: &Options) -> Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame, options))?;
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(caption) = &mut self.editing {
+                match key.code {
+                    KeyCode::Enter => {
+                        let caption = caption.trim().to_string();
+                        self.editing = None;
+                        if let Some(entry) = self.selected_mut() {
+                            entry.position.description = caption;
+                        }
+                    }
+                    KeyCode::Esc => self.editing = None,
+                    KeyCode::Backspace => {
+                        caption.pop();
+                    }
+                    KeyCode::Char(c) => caption.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                KeyCode::Char('w') => return Ok(true),
+                KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => self.move_selected(-1),
+                KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => self.move_selected(1),
+                KeyCode::Char('K') => self.move_selected(-1),
+                KeyCode::Char('J') => self.move_selected(1),
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::Char(' ') | KeyCode::Char('x') => {
+                    if let Some(entry) = self.selected_mut() {
+                        entry.kept = !entry.kept;
+                    }
+                }
+                KeyCode::Char('e') | KeyCode::Enter => self.editing = self.selected().map(|entry| entry.position.description.clone()),
+                _ => {}
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.list.selected()?)
+    }
+
+    fn selected_mut(&mut self) -> Option<&mut Entry> {
+        self.entries.get_mut(self.list.selected()?)
+    }
+
+    // Swaps the selected position with its neighbor above (-1) or below (1)
+    fn move_selected(&mut self, step: isize) {
+        let Some(index) = self.list.selected() else { return };
+        let Some(target) = index.checked_add_signed(step).filter(|&target| target < self.entries.len()) else { return };
+        self.entries.swap(index, target);
+        self.list.select(Some(target));
+    }
+
+    // The positions kept, numbered anew in their order
+    fn kept_positions(&self) -> Vec<ChessPosition> {
+        let mut positions: Vec<ChessPosition> = self.entries.iter().filter(|entry| entry.kept).map(|entry| entry.position.clone()).collect();
+        for (i, pos) in positions.iter_mut().enumerate() {
+            pos.number = i as i32 + 1;
+        }
+        positions
+    }
+
+    fn draw(&mut self, frame: &mut Frame, options: &Options) {
+        let [main, help] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, preview_area] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(main);
+
+        // Kept positions show the number they will be printed with
+        let mut number = 0;
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .map(|entry| match entry.kept {
+                true => {
+                    number += 1;
+                    ListItem::new(format!("{:>3}. {}", number, entry.position.description))
+                }
+                false => ListItem::new(format!("  -  {}", entry.position.description)).style(Style::default().fg(Color::DarkGray)),
+            })
+            .collect();
+        let title = format!(" {} of {} positions kept ", number, self.entries.len());
+        let list = List::new(items).block(Block::bordered().title(title)).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        if let Some(entry) = self.selected() {
+            frame.render_widget(preview(&entry.position, self.editing.as_deref(), options), preview_area);
+        }
+        let help_text = if self.editing.is_some() { EDIT_HELP } else { HELP };
+        frame.render_widget(Paragraph::new(help_text).style(Style::default().fg(Color::DarkGray)), help);
+    }
+}
+
+// The board as it is printed (flipped or not, in the theme's colors) with its chapter,
+// caption, FEN and solution; the caption being typed while editing
+fn preview(pos: &ChessPosition, editing: Option<&str>, options: &Options) -> Paragraph<'static> {
+    let board = crate::parse_fen(&pos.fen);
+    let flipped = pos.flipped(options);
+    let (light, dark) = options.pdf.theme().square_colors();
+    let mut lines = Vec::new();
+    for row in 0..8 {
+        let rank = if flipped { 7 - row } else { row };
+        let mut spans = vec![Span::raw(format!("{} ", 8 - rank))];
+        for column in 0..8 {
+            let file = if flipped { 7 - column } else { column };
+            let [r, g, b] = if (rank + file) % 2 == 0 { light } else { dark };
+            let style = Style::default().bg(Color::Rgb(r, g, b)).fg(Color::Black);
+            spans.push(Span::styled(format!(" {} ", piece_symbol(board[rank][file])), style));
+        }
+        lines.push(Line::from(spans));
+    }
+    let files: String = (0..8).map(|column| format!(" {} ", (b'a' + if flipped { 7 - column } else { column }) as char)).collect();
+    lines.push(Line::from(format!("  {}", files)));
+    lines.push(Line::default());
+
+    if !pos.chapter_name.is_empty() {
+        lines.push(Line::from(format!("Chapter: {}", pos.chapter_name)));
+    }
+    lines.push(match editing {
+        Some(caption) => Line::from(vec![Span::raw("Caption: "), Span::styled(format!("{}_", caption), Style::default().add_modifier(Modifier::BOLD))]),
+        None => Line::from(format!("Caption: {}", pos.description)),
+    });
+    lines.push(Line::from(format!("FEN: {}", pos.fen)));
+    if !pos.solution.is_empty() {
+        lines.push(Line::from(format!("Solution: {}", crate::numbered_moves(&pos.fen, &pos.solution))));
+    }
+    Paragraph::new(lines).wrap(Wrap { trim: false }).block(Block::bordered().title(" Preview "))
+}
+
+// White pieces as outlines, black ones filled
+fn piece_symbol(piece: char) -> char {
+    match piece {
+        'K' => '♔',
+        'Q' => '♕',
+        'R' => '♖',
+        'B' => '♗',
+        'N' => '♘',
+        'P' => '♙',
+        'k' => '♚',
+        'q' => '♛',
+        'r' => '♜',
+        'b' => '♝',
+        'n' => '♞',
+        'p' => '♟',
+        _ => ' ',
+    }
+}
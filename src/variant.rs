@@ -0,0 +1,55 @@
+// Variant handling: maps the [Variant] header to shakmaty's variants,
+// validates chapter FENs and provides the caption label
+
+use anyhow::{Result, anyhow};
+use shakmaty::fen::Fen;
+use shakmaty::variant::{Variant, VariantPosition};
+
+use crate::chess960;
+
+// Parse the [Variant] header; a missing header means standard chess
+pub fn parse_variant_tag(tag: Option<&str>) -> Result<Variant> {
+    match tag {
+        None | Some("") => Ok(Variant::Chess),
+        Some(name) => Variant::from_ascii(name.as_bytes()).map_err(|_| anyhow!("unsupported variant '{}'", name)),
+    }
+}
+
+// Name printed on the diagram caption; standard chess is not labeled
+pub fn label(variant: Variant, chess960: bool) -> Option<&'static str> {
+    match variant {
+        Variant::Chess if chess960 => Some("Chess960"),
+        Variant::Chess => None,
+        Variant::Atomic => Some("Atomic"),
+        Variant::Antichess => Some("Antichess"),
+        Variant::KingOfTheHill => Some("King of the Hill"),
+        Variant::ThreeCheck => Some("Three-check"),
+        Variant::Crazyhouse => Some("Crazyhouse"),
+        Variant::RacingKings => Some("Racing Kings"),
+        Variant::Horde => Some("Horde"),
+    }
+}
+
+// Set up the chapter's starting position, or the variant's initial position without a FEN
+pub fn start_position(fen: Option<&str>, variant: Variant, chess960: bool) -> Result<VariantPosition> {
+    let fen = match fen {
+        Some(fen) => fen,
+        None => return Ok(VariantPosition::new(variant)),
+    };
+    VariantPosition::from_setup(variant, parse_fen(fen)?.into_setup(), chess960::castling_mode(chess960))
+        .map_err(|e| anyhow!("invalid {} position '{}': {}", label(variant, chess960).unwrap_or("chess"), fen, e))
+}
+
+// Check that a FEN describes a legal position in the given variant
+pub fn validate(fen: &str, variant: Variant, chess960: bool) -> Result<()> {
+    start_position(Some(fen), variant, chess960).map(|_| ())
+}
+
+// Check that a FEN can be read at all; standard positions are drawn even when they are not legal
+pub fn check_syntax(fen: &str) -> Result<()> {
+    parse_fen(fen).map(|_| ())
+}
+
+fn parse_fen(fen: &str) -> Result<Fen> {
+    Fen::from_ascii(fen.as_bytes()).map_err(|e| anyhow!("invalid FEN '{}': {}", fen, e))
+}
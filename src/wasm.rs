@@ -0,0 +1,30 @@
+// Entry points of the WebAssembly build (wasm32-unknown-unknown, e.g. through wasm-pack): a
+// web page passes a pasted PGN or FEN list with command line options and gets the PDF's bytes
+// back, generated in the browser. Downloads, engines and --export anki are not available there
+
+use crate::platform::OutputStorage;
+use crate::{BoardCache, PASTED_PGN};
+use anyhow::Result;
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+// The PDF of a PGN, e.g. pgnToPdf(text, "--grid 2x3 --theme blue --solutions appendix")
+#[wasm_bindgen(js_name = pgnToPdf)]
+pub fn pgn_to_pdf(pgn: &str, args: &str) -> Result<Vec<u8>, JsError> {
+    render(pgn, args).map_err(|e| JsError::new(&e.to_string()))
+}
+
+// The PDF of a list of FENs, one a line
+#[wasm_bindgen(js_name = fensToPdf)]
+pub fn fens_to_pdf(fens: &str, args: &str) -> Result<Vec<u8>, JsError> {
+    render(&crate::fen_list_pgn(fens), args).map_err(|e| JsError::new(&e.to_string()))
+}
+
+// The positions are selected and drawn as on the command line
+fn render(pgn: &str, args: &str) -> Result<Vec<u8>> {
+    let storage = Arc::new(OutputStorage::default());
+    storage.written.insert(PASTED_PGN, pgn.as_bytes().to_vec());
+    let options = crate::embedded_options(args, PASTED_PGN, &storage)?;
+    let study_data = crate::read_lichess_study(PASTED_PGN, &options)?;
+    crate::pdf_bytes(study_data, &options, &storage, &BoardCache::new(None)?)
+}